@@ -0,0 +1,202 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The userland api, to create the business spans, add tags and logs, and get
+//! the trace id and the propagation header of the current request.
+//!
+//! All the functions are no-op (return `null` or `false`) when the agent is
+//! disabled or there is no tracing request, so that the business code can call
+//! them unconditionally.
+
+use crate::{
+    SpanLimitExceeded,
+    component::COMPONENT_PHP_ID,
+    context::RequestContext,
+    execute::infer_request_id,
+    module::is_enable,
+    util::{catch_unwind_result, z_val_to_scalar_string},
+};
+use anyhow::anyhow;
+use phper::{
+    arrays::IterKey,
+    eg,
+    values::{ExecuteData, ZVal},
+};
+use skywalking::trace::span::HandleSpanObject;
+use std::panic::AssertUnwindSafe;
+use tracing::debug;
+
+pub const TRACE_ID_FUNCTION_NAME: &str = "skywalking_trace_id";
+
+pub const GET_SW8_HEADER_FUNCTION_NAME: &str = "skywalking_get_sw8_header";
+
+pub const START_SPAN_FUNCTION_NAME: &str = "skywalking_start_span";
+
+pub const STOP_SPAN_FUNCTION_NAME: &str = "skywalking_stop_span";
+
+pub const ADD_TAG_FUNCTION_NAME: &str = "skywalking_add_tag";
+
+pub const ADD_LOG_FUNCTION_NAME: &str = "skywalking_add_log";
+
+pub const MARK_ERROR_FUNCTION_NAME: &str = "skywalking_mark_error";
+
+/// `skywalking_trace_id(): ?string`
+pub fn skywalking_trace_id(_: &mut [ZVal]) -> phper::Result<ZVal> {
+    Ok(
+        with_request_context(|ctx| Ok(ctx.tracing_context.trace_id().to_owned()))
+            .map(ZVal::from)
+            .unwrap_or_default(),
+    )
+}
+
+/// `skywalking_get_sw8_header(string $peer): ?string`, the header is used to
+/// propagate the context by the custom transport.
+pub fn skywalking_get_sw8_header(args: &mut [ZVal]) -> phper::Result<ZVal> {
+    let peer = args[0].expect_z_str()?.to_str()?.to_owned();
+
+    if !is_enable() {
+        return Ok(ZVal::default());
+    }
+    let request_id = infer_current_request_id();
+    Ok(
+        match catch_unwind_result(AssertUnwindSafe(|| {
+            RequestContext::try_get_sw_header(request_id, &peer)
+        })) {
+            Ok(header) => ZVal::from(header),
+            Err(err) => {
+                debug!(?err, "get sw8 header failed");
+                ZVal::default()
+            }
+        },
+    )
+}
+
+/// `skywalking_start_span(string $operation_name): bool`, the span becomes the
+/// active span, and must be stopped by `skywalking_stop_span`.
+pub fn skywalking_start_span(args: &mut [ZVal]) -> phper::Result<ZVal> {
+    let operation_name = args[0].expect_z_str()?.to_str()?.to_owned();
+
+    Ok(with_request_context(|ctx| {
+        let span = match ctx.create_span(|ctx| Ok(ctx.create_local_span(&operation_name))) {
+            Ok(mut span) => {
+                span.span_object_mut().component_id = COMPONENT_PHP_ID;
+                Some(span)
+            }
+            // The suppressed span is still stopped by `skywalking_stop_span`.
+            Err(err) if err.is::<SpanLimitExceeded>() => None,
+            Err(err) => return Err(err),
+        };
+        ctx.local_spans.push(span);
+        Ok(())
+    })
+    .is_some()
+    .into())
+}
+
+/// `skywalking_stop_span(): bool`, stop the active span started by
+/// `skywalking_start_span`.
+pub fn skywalking_stop_span(_: &mut [ZVal]) -> phper::Result<ZVal> {
+    Ok(with_request_context(|ctx| {
+        let span = ctx
+            .local_spans
+            .pop()
+            .ok_or_else(|| anyhow!("no span to stop"))?;
+        drop(span);
+        Ok(())
+    })
+    .is_some()
+    .into())
+}
+
+/// `skywalking_add_tag(string $key, string $value): bool`, add the tag to the
+/// active span, or the entry span if there is no active span.
+pub fn skywalking_add_tag(args: &mut [ZVal]) -> phper::Result<ZVal> {
+    let key = args[0].expect_z_str()?.to_str()?.to_owned();
+    let value = args[1].expect_z_str()?.to_str()?.to_owned();
+
+    Ok(with_request_context(|ctx| {
+        if let Some(span) = ctx.active_span_mut() {
+            span.add_tag(key, value);
+        }
+        Ok(())
+    })
+    .is_some()
+    .into())
+}
+
+/// `skywalking_add_log(array $fields): bool`, add the log with the key value
+/// fields to the active span, or the entry span if there is no active span.
+pub fn skywalking_add_log(args: &mut [ZVal]) -> phper::Result<ZVal> {
+    let fields = args[0]
+        .expect_z_arr()?
+        .iter()
+        .filter_map(|(key, value)| {
+            let key = match key {
+                IterKey::Index(i) => i.to_string(),
+                IterKey::ZStr(s) => s.to_str().ok()?.to_owned(),
+            };
+            Some((key, z_val_to_scalar_string(value)?))
+        })
+        .collect::<Vec<_>>();
+
+    Ok(with_request_context(|ctx| {
+        if !fields.is_empty() {
+            if let Some(span) = ctx.active_span_mut() {
+                span.add_log(fields);
+            }
+        }
+        Ok(())
+    })
+    .is_some()
+    .into())
+}
+
+/// `skywalking_mark_error(): bool`, mark the active span, or the entry span if
+/// there is no active span, as error.
+pub fn skywalking_mark_error(_: &mut [ZVal]) -> phper::Result<ZVal> {
+    Ok(with_request_context(|ctx| {
+        if let Some(span) = ctx.active_span_mut() {
+            span.span_object_mut().is_error = true;
+        }
+        Ok(())
+    })
+    .is_some()
+    .into())
+}
+
+fn with_request_context<T>(f: impl FnOnce(&mut RequestContext) -> anyhow::Result<T>) -> Option<T> {
+    if !is_enable() {
+        return None;
+    }
+
+    let request_id = infer_current_request_id();
+
+    // The span finished in the wrong order will panic, which shouldn't crash the
+    // php process.
+    match catch_unwind_result(AssertUnwindSafe(|| {
+        Ok(RequestContext::try_with_global(request_id, f)?)
+    })) {
+        Ok(result) => Some(result),
+        Err(err) => {
+            debug!(?err, "call userland api failed");
+            None
+        }
+    }
+}
+
+/// The request id of swoole is inferred from the call stack, like the plugins.
+fn infer_current_request_id() -> Option<i64> {
+    unsafe { ExecuteData::try_from_mut_ptr(eg!(current_execute_data)) }.and_then(infer_request_id)
+}
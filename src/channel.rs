@@ -13,9 +13,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::util::truncate_str;
 use anyhow::anyhow;
 use once_cell::sync::OnceCell;
-use skywalking::reporter::{CollectItem, Report};
+use skywalking::{
+    proto::v3::KeyStringValuePair,
+    reporter::{CollectItem, Report},
+};
 use std::{
     io::Write,
     ops::DerefMut,
@@ -38,16 +42,50 @@ where
     Ok(())
 }
 
+fn truncate_values<'a>(
+    pairs: impl IntoIterator<Item = &'a mut KeyStringValuePair>, max_length: usize,
+) {
+    for pair in pairs {
+        truncate_str(&mut pair.value, max_length);
+    }
+}
+
 pub struct Reporter {
     worker_addr: PathBuf,
     stream: OnceCell<Mutex<UnixStream>>,
+    /// The max length of the tag and log values, 0 means unlimited.
+    max_value_length: usize,
 }
 
 impl Reporter {
-    pub fn new(worker_addr: impl AsRef<Path>) -> Self {
+    pub fn new(worker_addr: impl AsRef<Path>, max_value_length: usize) -> Self {
         Self {
             worker_addr: worker_addr.as_ref().to_path_buf(),
             stream: OnceCell::new(),
+            max_value_length,
+        }
+    }
+
+    fn truncate(&self, item: &mut CollectItem) {
+        let max_length = self.max_value_length;
+        if max_length == 0 {
+            return;
+        }
+        match item {
+            CollectItem::Trace(segment) => {
+                for span in &mut segment.spans {
+                    truncate_values(&mut span.tags, max_length);
+                    for log in &mut span.logs {
+                        truncate_values(&mut log.data, max_length);
+                    }
+                }
+            }
+            CollectItem::Log(log) => {
+                if let Some(tags) = &mut log.tags {
+                    truncate_values(&mut tags.data, max_length);
+                }
+            }
+            _ => {}
         }
     }
 
@@ -63,7 +101,8 @@ impl Reporter {
 }
 
 impl Report for Reporter {
-    fn report(&self, item: CollectItem) {
+    fn report(&self, mut item: CollectItem) {
+        self.truncate(&mut item);
         if let Err(err) = self.try_report(item) {
             error!(?err, "channel send failed");
         }
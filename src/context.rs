@@ -13,8 +13,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::{
+    SpanLimitExceeded, endpoint::Endpoint, sampler::SegmentBuffer, tag::TAG_SUPPRESSED_SPANS,
+};
 use anyhow::anyhow;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use once_cell::sync::Lazy;
 use skywalking::trace::{
     propagation::encoder::encode_propagation,
@@ -26,12 +29,69 @@ pub const SW_HEADER: &str = "sw8";
 
 static REQUEST_CONTEXT: Lazy<DashMap<Option<i64>, RequestContext>> = Lazy::new(DashMap::new);
 
+/// The requests matched by the ignore endpoint rules, which have no request
+/// context, and the plugins skip them.
+static IGNORED_REQUESTS: Lazy<DashSet<Option<i64>>> = Lazy::new(DashSet::new);
+
 pub struct RequestContext {
     pub tracing_context: TracingContext,
     pub entry_span: Span,
+    /// The unsampled request still has the tracing context to propagate, but
+    /// the plugins won't create spans for it, and it won't be reported.
+    pub is_sampled: bool,
+    /// Only exists when the tail sampling is enabled and the request is
+    /// sampled.
+    pub segment_buffer: Option<SegmentBuffer>,
+    /// The local spans created by the userland api, the last one is the active
+    /// span. `None` is the span suppressed by the max count, kept to be stopped
+    /// in pairs.
+    pub local_spans: Vec<Option<Span>>,
+    /// The count of spans created, including the entry span.
+    pub span_count: usize,
+    /// The max count of spans, zero means unlimited.
+    pub max_span_count: usize,
+    /// The count of plugin spans suppressed by the max count.
+    pub suppressed_span_count: usize,
+    /// The endpoint of the http request or the renamed one, limited when the
+    /// request finishes.
+    pub endpoint: Option<Endpoint>,
 }
 
 impl RequestContext {
+    /// Finish the entry span and the tracing context, then the segment will be
+    /// reported.
+    pub fn finish(self) {
+        let Self {
+            tracing_context,
+            mut entry_span,
+            segment_buffer,
+            mut local_spans,
+            suppressed_span_count,
+            endpoint,
+            ..
+        } = self;
+
+        if let Some(endpoint) = endpoint {
+            entry_span.span_object_mut().operation_name = endpoint.into_limited_name();
+        }
+
+        if suppressed_span_count > 0 {
+            entry_span.add_tag(TAG_SUPPRESSED_SPANS, suppressed_span_count.to_string());
+        }
+
+        // The local spans not stopped by userland must be finished in reverse
+        // order, before the entry span.
+        while let Some(span) = local_spans.pop() {
+            drop(span);
+        }
+        drop(entry_span);
+        drop(tracing_context);
+
+        if let Some(segment_buffer) = segment_buffer {
+            segment_buffer.flush();
+        }
+    }
+
     pub fn set_global(request_id: Option<i64>, ctx: Self) {
         REQUEST_CONTEXT.insert(request_id, ctx);
     }
@@ -56,6 +116,51 @@ impl RequestContext {
         Self::try_with_global(request_id, |ctx| f(&mut ctx.tracing_context))
     }
 
+    /// Create the span of plugin by `f`, fail with [SpanLimitExceeded] if the
+    /// span count reaches the max count, then the span is suppressed.
+    pub fn try_create_span(
+        request_id: Option<i64>, f: impl FnOnce(&mut TracingContext) -> anyhow::Result<Span>,
+    ) -> anyhow::Result<Span> {
+        Self::try_with_global(request_id, |ctx| ctx.create_span(f))
+    }
+
+    /// Create the span by `f` within the max count, like
+    /// [RequestContext::try_create_span].
+    pub fn create_span(
+        &mut self, f: impl FnOnce(&mut TracingContext) -> anyhow::Result<Span>,
+    ) -> anyhow::Result<Span> {
+        if self.max_span_count > 0 && self.span_count >= self.max_span_count {
+            self.suppressed_span_count += 1;
+            return Err(SpanLimitExceeded.into());
+        }
+        let span = f(&mut self.tracing_context)?;
+        self.span_count += 1;
+        Ok(span)
+    }
+
+    pub fn set_ignored(request_id: Option<i64>) {
+        IGNORED_REQUESTS.insert(request_id);
+    }
+
+    /// Return true if the request was ignored.
+    pub fn remove_ignored(request_id: Option<i64>) -> bool {
+        IGNORED_REQUESTS.remove(&request_id).is_some()
+    }
+
+    pub fn is_ignored(request_id: Option<i64>) -> bool {
+        IGNORED_REQUESTS.contains(&request_id)
+    }
+
+    /// Whether the request is sampled, the request without context is treated
+    /// as sampled, to let the hooks run as before, which fail to create the
+    /// spans without the context by themselves.
+    pub fn is_sampled(request_id: Option<i64>) -> bool {
+        REQUEST_CONTEXT
+            .get(&request_id)
+            .map(|ctx| ctx.is_sampled)
+            .unwrap_or(true)
+    }
+
     pub fn try_get_sw_header(request_id: Option<i64>, peer: &str) -> crate::Result<String> {
         Ok(Self::try_with_global(request_id, |req_ctx| {
             Ok(encode_propagation(
@@ -66,6 +171,37 @@ impl RequestContext {
         })?)
     }
 
+    /// Rename the entry span by the route matched by the framework, such as
+    /// `GET:/users/{id}`, to avoid the explosion of endpoints. The route rather
+    /// than the path is limited by the endpoint name max count.
+    pub fn rename_by_route(request_id: Option<i64>, route: &str) -> crate::Result<()> {
+        Ok(Self::try_with_global(request_id, |ctx| {
+            let endpoint = ctx.endpoint.get_or_insert_with(|| {
+                let method = ctx
+                    .entry_span
+                    .span_object()
+                    .tags
+                    .iter()
+                    .find(|tag| tag.key == "http.method")
+                    .map(|tag| tag.value.clone())
+                    .unwrap_or_default();
+                Endpoint::new(&method, String::new())
+            });
+            endpoint.rename(route);
+            ctx.entry_span.span_object_mut().operation_name = endpoint.name();
+            Ok(())
+        })?)
+    }
+
+    /// The span which the userland api operates on, the last local span, or
+    /// the entry span. `None` if the last local span is suppressed.
+    pub fn active_span_mut(&mut self) -> Option<&mut Span> {
+        match self.local_spans.last_mut() {
+            Some(span) => span.as_mut(),
+            None => Some(&mut self.entry_span),
+        }
+    }
+
     /// Primary endpoint name is used for endpoint dependency.
     #[inline]
     fn get_primary_span(&self) -> &Span {
@@ -0,0 +1,207 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The dynamic configurations fetched by the worker from the
+//! ConfigurationDiscoveryService, which override the ini settings without
+//! reloading PHP-FPM.
+
+use crate::{
+    endpoint::{IgnoreEndpointRule, parse_ignore_endpoint_rules},
+    module::{
+        CONFIGURATION_DISCOVERY_PERIOD, CONFIGURATION_FILE_PATH, is_standalone_reporter_type,
+    },
+};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    fs,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::{info, warn};
+
+/// Check the file at most once per interval, rather than every request.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+const KEY_ENABLE_TRACING: &str = "agent.enable_tracing";
+const KEY_SAMPLE_RATE: &str = "agent.sample_rate";
+const KEY_SAMPLE_N_PER_3_SECS: &str = "agent.sample_n_per_3_secs";
+const KEY_IGNORE_PATH: &str = "agent.trace.ignore_path";
+const KEY_SPAN_LIMIT_PER_SEGMENT: &str = "agent.span_limit_per_segment";
+
+/// The fetched values, `None` means the ini setting is used.
+#[derive(Debug, Default)]
+pub struct DynamicConfig {
+    pub enable_tracing: Option<bool>,
+    pub sample_rate: Option<f64>,
+    pub sample_n_per_3_secs: Option<i64>,
+    pub ignore_path: Option<Vec<IgnoreEndpointRule>>,
+    pub span_limit_per_segment: Option<i64>,
+}
+
+impl DynamicConfig {
+    fn parse(configurations: &HashMap<String, String>) -> Self {
+        Self {
+            enable_tracing: parse_value(configurations, KEY_ENABLE_TRACING),
+            sample_rate: parse_value(configurations, KEY_SAMPLE_RATE),
+            sample_n_per_3_secs: parse_value(configurations, KEY_SAMPLE_N_PER_3_SECS),
+            ignore_path: configurations
+                .get(KEY_IGNORE_PATH)
+                .map(|rules| parse_ignore_endpoint_rules(rules)),
+            span_limit_per_segment: parse_value(configurations, KEY_SPAN_LIMIT_PER_SEGMENT),
+        }
+    }
+
+    /// Convert `agent.sample_n_per_3_secs` to the count of `period` seconds,
+    /// the non-positive count means unlimited as the other agents.
+    pub fn sample_n_per_period(&self, period: i64) -> Option<i64> {
+        self.sample_n_per_3_secs.map(|n| {
+            if n <= 0 {
+                -1
+            } else {
+                n.saturating_mul(period.max(1)).saturating_add(2) / 3
+            }
+        })
+    }
+}
+
+#[derive(Default)]
+struct State {
+    config: Arc<DynamicConfig>,
+    modified: Option<SystemTime>,
+    checked_at: Option<Instant>,
+}
+
+static IS_ENABLED: Lazy<bool> =
+    Lazy::new(|| *CONFIGURATION_DISCOVERY_PERIOD > 0 && !is_standalone_reporter_type());
+
+static STATE: Lazy<Mutex<State>> = Lazy::new(Default::default);
+
+/// Reload the file if it's modified, should be called once at the beginning of
+/// the request, so the configurations are unchanged during the request.
+pub fn refresh() {
+    if !*IS_ENABLED {
+        return;
+    }
+
+    let mut state = STATE.lock().unwrap_or_else(|err| err.into_inner());
+    if state
+        .checked_at
+        .is_some_and(|checked_at| checked_at.elapsed() < REFRESH_INTERVAL)
+    {
+        return;
+    }
+    state.checked_at = Some(Instant::now());
+
+    let path = &*CONFIGURATION_FILE_PATH;
+    // The file doesn't exist until the configurations are fetched.
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if modified == state.modified {
+        return;
+    }
+    state.modified = modified;
+
+    let config = match modified {
+        Some(_) => match read_configurations() {
+            Ok(configurations) => DynamicConfig::parse(&configurations),
+            Err(err) => {
+                warn!(?err, ?path, "read dynamic configurations failed");
+                return;
+            }
+        },
+        None => DynamicConfig::default(),
+    };
+    info!(?config, "dynamic configurations changed");
+    state.config = Arc::new(config);
+}
+
+/// Get the dynamic configurations of current request.
+pub fn current() -> Arc<DynamicConfig> {
+    STATE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .config
+        .clone()
+}
+
+fn read_configurations() -> anyhow::Result<HashMap<String, String>> {
+    let content = fs::read(&*CONFIGURATION_FILE_PATH)?;
+    Ok(serde_json::from_slice(&content)?)
+}
+
+fn parse_value<T: FromStr>(configurations: &HashMap<String, String>, key: &str) -> Option<T> {
+    let value = configurations.get(key)?.trim();
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            warn!(key, value, "invalid dynamic configuration, ignored");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(configurations: &[(&str, &str)]) -> DynamicConfig {
+        DynamicConfig::parse(
+            &configurations
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = parse(&[
+            ("agent.enable_tracing", "false"),
+            ("agent.sample_rate", " 0.5 "),
+            ("agent.sample_n_per_3_secs", "10"),
+            ("agent.trace.ignore_path", "/healthz,/static/**"),
+            ("agent.span_limit_per_segment", "invalid"),
+            ("agent.unknown", "1"),
+        ]);
+        assert_eq!(config.enable_tracing, Some(false));
+        assert_eq!(config.sample_rate, Some(0.5));
+        assert_eq!(config.sample_n_per_3_secs, Some(10));
+        assert_eq!(config.ignore_path.map(|rules| rules.len()), Some(2));
+        assert_eq!(config.span_limit_per_segment, None);
+
+        let config = parse(&[]);
+        assert_eq!(config.enable_tracing, None);
+        assert_eq!(config.sample_n_per_3_secs, None);
+        assert!(config.ignore_path.is_none());
+    }
+
+    #[test]
+    fn test_sample_n_per_period() {
+        let config = |n| DynamicConfig {
+            sample_n_per_3_secs: n,
+            ..Default::default()
+        };
+        assert_eq!(config(None).sample_n_per_period(3), None);
+        assert_eq!(config(Some(10)).sample_n_per_period(3), Some(10));
+        assert_eq!(config(Some(10)).sample_n_per_period(6), Some(20));
+        assert_eq!(config(Some(10)).sample_n_per_period(1), Some(4));
+        assert_eq!(config(Some(1)).sample_n_per_period(0), Some(1));
+        assert_eq!(config(Some(0)).sample_n_per_period(3), Some(-1));
+        assert_eq!(config(Some(-1)).sample_n_per_period(3), Some(-1));
+    }
+}
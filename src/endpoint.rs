@@ -0,0 +1,609 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Normalize the url path of the entry span, to keep the endpoint cardinality
+//! bounded, and match the requests to be ignored.
+
+use crate::{
+    dynamic_config,
+    module::{
+        ENDPOINT_NAME_COLLAPSE_IDS, ENDPOINT_NAME_MAX_COUNT, ENDPOINT_NAME_RULES, IGNORE_ENDPOINTS,
+    },
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    mem::size_of,
+    ptr::null_mut,
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tracing::{debug, warn};
+
+/// The endpoint name of the requests exceed `endpoint_name_max_count`.
+pub const OVERFLOW_ENDPOINT_PATH: &str = "/{overflow}";
+
+/// Must be mapped before forking, to be shared by all the processes.
+static ENDPOINT_NAMES: Lazy<Option<EndpointNames>> = Lazy::new(|| {
+    let max_count = *ENDPOINT_NAME_MAX_COUNT;
+    if max_count <= 0 {
+        return None;
+    }
+    let names = EndpointNames::new_shared(max_count as u64);
+    if names.is_none() {
+        warn!(max_count, "endpoint name max count is too large, ignored");
+    }
+    names
+});
+
+static UUID_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new("^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$")
+        .unwrap()
+});
+
+pub enum EndpointNameRule {
+    /// Such as `/orders/{id}/items/*`, the `{name}` and `*` match one segment,
+    /// the trailing `**` matches the rest segments, the path matched is
+    /// replaced by the template.
+    Template(String),
+
+    /// Such as `~^/files/.+$ => /files/{path}`, the path matched is replaced
+    /// by the replacement, which supports the capture groups like `$1`.
+    Regex(Regex, String),
+}
+
+impl EndpointNameRule {
+    fn apply(&self, path: &str) -> Option<String> {
+        match self {
+            EndpointNameRule::Template(template) => {
+                is_template_matched(template, path).then(|| template.clone())
+            }
+            EndpointNameRule::Regex(regex, replacement) => regex
+                .is_match(path)
+                .then(|| regex.replace(path, replacement.as_str()).into_owned()),
+        }
+    }
+}
+
+/// Parse the rules separated by `;`, because the regex may contain `,`.
+pub fn parse_endpoint_name_rules(s: &str) -> Vec<EndpointNameRule> {
+    s.split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| match item.strip_prefix('~') {
+            Some(item) => {
+                let Some((pattern, replacement)) = item.split_once("=>") else {
+                    warn!(item, "endpoint name regex rule lacks the replacement");
+                    return None;
+                };
+                match Regex::new(pattern.trim()) {
+                    Ok(regex) => Some(EndpointNameRule::Regex(
+                        regex,
+                        replacement.trim().to_owned(),
+                    )),
+                    Err(err) => {
+                        warn!(item, ?err, "invalid endpoint name regex rule");
+                        None
+                    }
+                }
+            }
+            None => Some(EndpointNameRule::Template(item.to_owned())),
+        })
+        .collect()
+}
+
+/// Such as `GET|HEAD /static/**`, the methods are optional, `*` and `?` match
+/// the characters except `/`, `**` matches any characters.
+#[derive(Debug)]
+pub struct IgnoreEndpointRule {
+    methods: Vec<String>,
+    pattern: String,
+}
+
+impl IgnoreEndpointRule {
+    fn is_matched(&self, method: &str, path: &str) -> bool {
+        (self.methods.is_empty() || self.methods.iter().any(|m| m.eq_ignore_ascii_case(method)))
+            && is_glob_matched(self.pattern.as_bytes(), path.as_bytes())
+    }
+}
+
+/// Parse the rules separated by `,`.
+pub fn parse_ignore_endpoint_rules(s: &str) -> Vec<IgnoreEndpointRule> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| {
+            let (methods, pattern) = match item.split_once(char::is_whitespace) {
+                Some((methods, pattern)) => (
+                    methods
+                        .split('|')
+                        .map(str::trim)
+                        .filter(|method| !method.is_empty())
+                        .map(ToOwned::to_owned)
+                        .collect(),
+                    pattern.trim(),
+                ),
+                None => (Vec::new(), item),
+            };
+            if !pattern.starts_with('/') {
+                warn!(item, "ignore endpoint rule should start with `/`");
+                return None;
+            }
+            Some(IgnoreEndpointRule {
+                methods,
+                pattern: pattern.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Whether the request matches the ignore endpoint rules, the dynamic rules
+/// take precedence over the ini setting.
+pub fn is_ignored_endpoint(method: &str, path: &str) -> bool {
+    let dynamic_config = dynamic_config::current();
+    let rules = dynamic_config
+        .ignore_path
+        .as_deref()
+        .unwrap_or(&IGNORE_ENDPOINTS[..]);
+    rules.iter().any(|rule| rule.is_matched(method, path))
+}
+
+/// Normalize the url path by the rules, or collapse the id segments if no rule
+/// matched.
+pub fn normalize_path(path: &str) -> String {
+    for rule in &*ENDPOINT_NAME_RULES {
+        if let Some(path) = rule.apply(path) {
+            return path;
+        }
+    }
+
+    if *ENDPOINT_NAME_COLLAPSE_IDS {
+        return collapse_ids(path);
+    }
+
+    path.to_owned()
+}
+
+/// Must be called before forking.
+pub fn init() {
+    Lazy::force(&ENDPOINT_NAMES);
+}
+
+/// The endpoint of the entry span, which is limited by the count of distinct
+/// endpoint names shared by all the processes only when the request finishes,
+/// so the paths renamed by the framework routes don't take the places.
+pub struct Endpoint {
+    method: String,
+    path: String,
+}
+
+impl Endpoint {
+    pub fn new(method: &str, path: String) -> Self {
+        Self {
+            method: method.to_owned(),
+            path,
+        }
+    }
+
+    pub fn rename(&mut self, route: &str) {
+        self.path = route.to_owned();
+    }
+
+    /// The name without limit, such as `GET:/users/{id}`.
+    pub fn name(&self) -> String {
+        Self::format_name(&self.method, &self.path)
+    }
+
+    /// The name limited by the max count, the names exceed the limit are
+    /// replaced with the overflow name.
+    pub fn into_limited_name(self) -> String {
+        self.limit((*ENDPOINT_NAMES).as_ref())
+    }
+
+    fn limit(self, names: Option<&EndpointNames>) -> String {
+        let name = self.name();
+        match names {
+            Some(names) if !names.acquire(&name) => {
+                debug!(name, "endpoint names exceed the max count");
+                Self::format_name(&self.method, OVERFLOW_ENDPOINT_PATH)
+            }
+            _ => name,
+        }
+    }
+
+    fn format_name(method: &str, path: &str) -> String {
+        if method.is_empty() {
+            path.to_owned()
+        } else {
+            format!("{}:{}", method, path)
+        }
+    }
+}
+
+/// The open addressing hash table of the endpoint name hashes, with the count
+/// of the names in the first slot, in the anonymous shared mapping like the
+/// sample window. The names are never removed, so the lookup stops at the
+/// first empty slot.
+struct EndpointNames {
+    count: &'static AtomicU64,
+    slots: &'static [AtomicU64],
+    max_count: u64,
+}
+
+impl EndpointNames {
+    fn new_shared(max_count: u64) -> Option<Self> {
+        let slot_count = Self::slot_count(max_count)?;
+        let len = slot_count + 1;
+        let ptr = unsafe {
+            libc::mmap(
+                null_mut(),
+                len.checked_mul(size_of::<AtomicU64>())?,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            warn!("map shared endpoint names failed, fallback to process local");
+            return Self::new_local(max_count);
+        }
+        // The anonymous mapping is zero filled, which is an empty table.
+        let table = unsafe { slice::from_raw_parts(ptr.cast::<AtomicU64>(), len) };
+        Some(Self::with_table(table, max_count))
+    }
+
+    fn new_local(max_count: u64) -> Option<Self> {
+        let len = Self::slot_count(max_count)? + 1;
+        let table = (0..len).map(|_| AtomicU64::new(0)).collect::<Vec<_>>();
+        Some(Self::with_table(table.leak(), max_count))
+    }
+
+    /// Twice the max count at least, so the probing always reaches an empty
+    /// slot.
+    fn slot_count(max_count: u64) -> Option<usize> {
+        usize::try_from(max_count)
+            .ok()?
+            .checked_mul(2)?
+            .checked_next_power_of_two()
+    }
+
+    fn with_table(table: &'static [AtomicU64], max_count: u64) -> Self {
+        let (count, slots) = table.split_first().unwrap();
+        Self {
+            count,
+            slots,
+            max_count,
+        }
+    }
+
+    /// Return true if the name exists, or is inserted as the count doesn't
+    /// reach the max count.
+    fn acquire(&self, name: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        // Zero means the empty slot.
+        let hash = hasher.finish().max(1);
+
+        let mask = self.slots.len() - 1;
+        let mut is_reserved = false;
+        let mut result = false;
+        for i in 0..self.slots.len() {
+            let slot = &self.slots[(hash as usize).wrapping_add(i) & mask];
+            let mut current = slot.load(Ordering::Acquire);
+            if current == 0 {
+                if !is_reserved {
+                    if self.count.fetch_add(1, Ordering::AcqRel) >= self.max_count {
+                        self.count.fetch_sub(1, Ordering::AcqRel);
+                        return false;
+                    }
+                    is_reserved = true;
+                }
+                match slot.compare_exchange(0, hash, Ordering::AcqRel, Ordering::Acquire) {
+                    Ok(_) => return true,
+                    // Taken by the others concurrently.
+                    Err(actual) => current = actual,
+                }
+            }
+            if current == hash {
+                result = true;
+                break;
+            }
+        }
+        if is_reserved {
+            self.count.fetch_sub(1, Ordering::AcqRel);
+        }
+        result
+    }
+}
+
+fn is_template_matched(template: &str, path: &str) -> bool {
+    let mut template_segments = template.trim_matches('/').split('/');
+    let mut path_segments = path.trim_matches('/').split('/');
+
+    loop {
+        match (template_segments.next(), path_segments.next()) {
+            (Some("**"), Some(segment)) => return !segment.is_empty(),
+            (Some(t), Some(p)) => {
+                let is_wildcard = t == "*" || (t.starts_with('{') && t.ends_with('}'));
+                if (is_wildcard && p.is_empty()) || (!is_wildcard && t != p) {
+                    return false;
+                }
+            }
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum GlobToken {
+    Byte(u8),
+    AnyByte,
+    Star,
+    DoubleStar,
+}
+
+/// Match the glob by tracking all the pattern positions reachable by the
+/// consumed path at once, rather than backtracking, so it's linear in the path
+/// length whatever the wildcards are.
+fn is_glob_matched(pattern: &[u8], path: &[u8]) -> bool {
+    let mut tokens = Vec::with_capacity(pattern.len());
+    let mut pattern = pattern.iter().peekable();
+    while let Some(&b) = pattern.next() {
+        tokens.push(match b {
+            b'*' if pattern.next_if_eq(&&b'*').is_some() => GlobToken::DoubleStar,
+            b'*' => GlobToken::Star,
+            b'?' => GlobToken::AnyByte,
+            b => GlobToken::Byte(b),
+        });
+    }
+
+    // The wildcards can match nothing, so the position after them is
+    // reachable too.
+    let close = |states: &mut [bool]| {
+        for (i, token) in tokens.iter().enumerate() {
+            if states[i] && matches!(token, GlobToken::Star | GlobToken::DoubleStar) {
+                states[i + 1] = true;
+            }
+        }
+    };
+
+    let mut states = vec![false; tokens.len() + 1];
+    let mut next_states = states.clone();
+    states[0] = true;
+    close(&mut states);
+
+    for &c in path {
+        next_states.fill(false);
+        for (i, token) in tokens.iter().enumerate() {
+            if !states[i] {
+                continue;
+            }
+            match *token {
+                GlobToken::Byte(b) if b == c => next_states[i + 1] = true,
+                GlobToken::AnyByte if c != b'/' => next_states[i + 1] = true,
+                GlobToken::Star if c != b'/' => next_states[i] = true,
+                GlobToken::DoubleStar => next_states[i] = true,
+                _ => {}
+            }
+        }
+        close(&mut next_states);
+        if !next_states.contains(&true) {
+            return false;
+        }
+        std::mem::swap(&mut states, &mut next_states);
+    }
+
+    states[tokens.len()]
+}
+
+fn collapse_ids(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit()) {
+                "{id}"
+            } else if UUID_REGEX.is_match(segment) {
+                "{uuid}"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, path: &str) -> bool {
+        is_glob_matched(pattern.as_bytes(), path.as_bytes())
+    }
+
+    #[test]
+    fn test_is_glob_matched() {
+        assert!(glob("/healthz", "/healthz"));
+        assert!(!glob("/healthz", "/healthz/"));
+        assert!(!glob("/healthz", "/health"));
+
+        assert!(glob("/users/?", "/users/1"));
+        assert!(!glob("/users/?", "/users/12"));
+        assert!(!glob("/users/?", "/users//"));
+
+        assert!(glob("/users/*", "/users/"));
+        assert!(glob("/users/*", "/users/123"));
+        assert!(!glob("/users/*", "/users/123/orders"));
+        assert!(glob("/users/*/orders", "/users/123/orders"));
+        assert!(glob("/*.php", "/index.php"));
+        assert!(!glob("/*.php", "/admin/index.php"));
+
+        assert!(glob("/static/**", "/static/"));
+        assert!(glob("/static/**", "/static/js/app.js"));
+        assert!(!glob("/static/**", "/api/static/app.js"));
+        assert!(glob("/**/*.css", "/static/css/app.css"));
+        assert!(!glob("/**/*.css", "/app.css"));
+        assert!(!glob("/**/*.css", "/static/app.js"));
+        assert!(glob("**", ""));
+        assert!(glob("/a**b", "/a/x/b"));
+    }
+
+    #[test]
+    fn test_is_glob_matched_many_wildcards() {
+        let pattern = "/*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b";
+        let path = format!("/{}", "a".repeat(4096));
+        assert!(!glob(pattern, &path));
+
+        let pattern = "/**a**a**a**a**a**a**a**a**a**a**b";
+        let path = format!("/{}", "a/".repeat(4096));
+        assert!(!glob(pattern, &path));
+        assert!(glob(pattern, &format!("{}b", path)));
+    }
+
+    #[test]
+    fn test_parse_ignore_endpoint_rules() {
+        let rules =
+            parse_ignore_endpoint_rules(" /healthz , GET|head /static/**,,static,POST /api/*");
+        assert_eq!(rules.len(), 3);
+
+        assert!(rules[0].methods.is_empty());
+        assert_eq!(rules[0].pattern, "/healthz");
+        assert!(rules[0].is_matched("POST", "/healthz"));
+
+        assert_eq!(rules[1].methods, ["GET", "head"]);
+        assert_eq!(rules[1].pattern, "/static/**");
+        assert!(rules[1].is_matched("HEAD", "/static/app.js"));
+        assert!(rules[1].is_matched("get", "/static/app.js"));
+        assert!(!rules[1].is_matched("POST", "/static/app.js"));
+
+        assert_eq!(rules[2].methods, ["POST"]);
+        assert!(rules[2].is_matched("POST", "/api/users"));
+        assert!(!rules[2].is_matched("POST", "/api/users/1"));
+
+        assert!(parse_ignore_endpoint_rules("").is_empty());
+    }
+
+    #[test]
+    fn test_is_template_matched() {
+        assert!(is_template_matched("/users", "/users"));
+        assert!(is_template_matched("/users", "/users/"));
+        assert!(!is_template_matched("/users", "/users/1"));
+        assert!(is_template_matched("/users/{id}", "/users/1"));
+        assert!(is_template_matched("/users/*/orders", "/users/1/orders"));
+        assert!(!is_template_matched("/users/{id}", "/users//"));
+        assert!(!is_template_matched("/users/{id}/orders", "/users/1"));
+        assert!(is_template_matched("/files/**", "/files/a/b/c"));
+        assert!(is_template_matched("/files/**", "/files/a"));
+        assert!(!is_template_matched("/files/**", "/files"));
+    }
+
+    #[test]
+    fn test_collapse_ids() {
+        assert_eq!(
+            collapse_ids("/users/123/orders/45"),
+            "/users/{id}/orders/{id}"
+        );
+        assert_eq!(
+            collapse_ids("/files/0f8fad5b-d9cb-469f-a165-70867728950e"),
+            "/files/{uuid}"
+        );
+        assert_eq!(collapse_ids("/v1/users/abc1"), "/v1/users/abc1");
+        assert_eq!(collapse_ids("/"), "/");
+        assert_eq!(collapse_ids("/users//"), "/users//");
+    }
+
+    #[test]
+    fn test_parse_endpoint_name_rules() {
+        let rules = parse_endpoint_name_rules(
+            " /users/{id} ; ~^/files/(\\w+)/.+$ => /files/$1/{path};;~^/lack$;~(invalid => /x",
+        );
+        assert_eq!(rules.len(), 2);
+
+        assert_eq!(rules[0].apply("/users/1").as_deref(), Some("/users/{id}"));
+        assert_eq!(rules[0].apply("/orders/1"), None);
+
+        assert_eq!(
+            rules[1].apply("/files/images/a/b.png").as_deref(),
+            Some("/files/images/{path}")
+        );
+        assert_eq!(rules[1].apply("/files/images"), None);
+
+        assert!(parse_endpoint_name_rules("").is_empty());
+    }
+
+    #[test]
+    fn test_endpoint_names() {
+        let names = EndpointNames::new_local(3).unwrap();
+        assert_eq!(names.slots.len(), 8);
+        assert!(names.acquire("GET:/a"));
+        assert!(names.acquire("GET:/b"));
+        assert!(names.acquire("GET:/a"));
+        assert!(names.acquire("POST:/a"));
+        assert!(!names.acquire("GET:/c"));
+        assert!(names.acquire("GET:/b"));
+        assert_eq!(names.count.load(Ordering::Relaxed), 3);
+
+        assert!(EndpointNames::new_local(u64::MAX).is_none());
+    }
+
+    #[test]
+    fn test_shared_endpoint_names_concurrently() {
+        let names = &*Box::leak(Box::new(EndpointNames::new_shared(100).unwrap()));
+        let handles = (0..8)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    (0..200)
+                        .filter(|i| names.acquire(&format!("GET:/{}", i)))
+                        .count()
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert!(handle.join().unwrap() <= 100);
+        }
+        assert_eq!(names.count.load(Ordering::Relaxed), 100);
+        assert_eq!(
+            (0..200)
+                .filter(|i| names.acquire(&format!("GET:/{}", i)))
+                .count(),
+            100
+        );
+    }
+
+    #[test]
+    fn test_endpoint_limit() {
+        let names = EndpointNames::new_local(2).unwrap();
+
+        for id in 1..=3 {
+            let mut endpoint = Endpoint::new("GET", format!("/users/{}", id));
+            assert_eq!(endpoint.name(), format!("GET:/users/{}", id));
+            endpoint.rename("/users/{id}");
+            assert_eq!(endpoint.limit(Some(&names)), "GET:/users/{id}");
+        }
+        // The renamed requests take only one place.
+        assert_eq!(names.count.load(Ordering::Relaxed), 1);
+
+        let endpoint = Endpoint::new("POST", "/users".to_owned());
+        assert_eq!(endpoint.limit(Some(&names)), "POST:/users");
+        let endpoint = Endpoint::new("GET", "/orders".to_owned());
+        assert_eq!(endpoint.limit(Some(&names)), "GET:/{overflow}");
+        let endpoint = Endpoint::new("", "/orders".to_owned());
+        assert_eq!(endpoint.limit(Some(&names)), "/{overflow}");
+
+        let endpoint = Endpoint::new("GET", "/orders".to_owned());
+        assert_eq!(endpoint.limit(None), "GET:/orders");
+    }
+}
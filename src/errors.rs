@@ -27,6 +27,19 @@ pub enum Error {
     Anyhow(#[from] anyhow::Error),
 }
 
+impl Error {
+    /// The suppressed span isn't a failure of the plugin, needn't be logged.
+    pub fn is_span_limit_exceeded(&self) -> bool {
+        matches!(self, Self::Anyhow(err) if err.is::<SpanLimitExceeded>())
+    }
+}
+
+/// The span isn't created, because the span count of the segment reaches
+/// `max_spans_per_segment`.
+#[derive(Debug, thiserror::Error)]
+#[error("span limit of segment exceeded")]
+pub struct SpanLimitExceeded;
+
 impl From<Utf8Error> for Error {
     fn from(e: Utf8Error) -> Self {
         Self::Anyhow(e.into())
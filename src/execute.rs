@@ -125,7 +125,12 @@ unsafe extern "C" fn execute_internal(
         );
 
         let result = catch_unwind_result(AssertUnwindSafe(|| before(request_id, execute_data)));
-        if let Err(err) = &result {
+        // The span suppressed by the span limit isn't a failure.
+        if let Some(err) = result
+            .as_ref()
+            .err()
+            .filter(|err| !err.is_span_limit_exceeded())
+        {
             error!(
                 ?request_id,
                 ?function_name,
@@ -139,9 +144,12 @@ unsafe extern "C" fn execute_internal(
 
         // If before hook return error, don't execute the after hook.
         if let Ok(data) = result {
-            if let Err(err) = catch_unwind_result(AssertUnwindSafe(|| {
+            if let Some(err) = catch_unwind_result(AssertUnwindSafe(|| {
                 after(request_id, data, execute_data, return_value)
-            })) {
+            }))
+            .err()
+            .filter(|err| !err.is_span_limit_exceeded())
+            {
                 error!(
                     ?request_id,
                     ?function_name,
@@ -202,7 +210,12 @@ unsafe extern "C" fn execute_ex(execute_data: *mut sys::zend_execute_data) {
         );
 
         let result = catch_unwind_result(AssertUnwindSafe(|| before(request_id, execute_data)));
-        if let Err(err) = &result {
+        // The span suppressed by the span limit isn't a failure.
+        if let Some(err) = result
+            .as_ref()
+            .err()
+            .filter(|err| !err.is_span_limit_exceeded())
+        {
             error!(
                 ?request_id,
                 ?function_name,
@@ -222,9 +235,12 @@ unsafe extern "C" fn execute_ex(execute_data: *mut sys::zend_execute_data) {
                     Some(return_value) => return_value,
                     None => &mut null,
                 };
-            if let Err(err) = catch_unwind_result(AssertUnwindSafe(|| {
+            if let Some(err) = catch_unwind_result(AssertUnwindSafe(|| {
                 after(request_id, data, execute_data, return_value)
-            })) {
+            }))
+            .err()
+            .filter(|err| !err.is_span_limit_exceeded())
+            {
                 error!(
                     ?request_id,
                     ?function_name,
@@ -305,7 +321,7 @@ fn get_function_and_class_name(
     Ok((function_name, class_name))
 }
 
-fn infer_request_id(execute_data: &mut ExecuteData) -> Option<i64> {
+pub fn infer_request_id(execute_data: &mut ExecuteData) -> Option<i64> {
     if !IS_SWOOLE.load(Ordering::Relaxed) {
         return None;
     }
@@ -433,6 +449,7 @@ pub mod observer {
             let result =
                 match catch_unwind_result(AssertUnwindSafe(|| before(request_id, execute_data))) {
                     Ok(result) => result,
+                    Err(err) if err.is_span_limit_exceeded() => return,
                     Err(err) => {
                         error!(
                             ?request_id,
@@ -495,9 +512,12 @@ pub mod observer {
                 "observer_end infer request id"
             );
 
-            if let Err(err) = catch_unwind_result(AssertUnwindSafe(|| {
+            if let Some(err) = catch_unwind_result(AssertUnwindSafe(|| {
                 after(request_id, result, execute_data, ret)
-            })) {
+            }))
+            .err()
+            .filter(|err| !err.is_span_limit_exceeded())
+            {
                 error!(
                     ?request_id,
                     ?function_name,
@@ -17,23 +17,34 @@
 #![warn(rust_2018_idioms)]
 #![warn(clippy::dbg_macro, clippy::print_stdout)]
 
+mod api;
 mod channel;
 mod component;
 mod context;
+mod dynamic_config;
+mod endpoint;
 mod errors;
 mod execute;
 mod log;
 mod module;
 mod plugin;
 mod request;
+mod runtime_metrics;
+mod sampler;
 mod tag;
 mod util;
 mod worker;
 
-use phper::{ini::Policy, modules::Module, php_get_module};
+use phper::{
+    functions::{Argument, ReturnType},
+    ini::Policy,
+    modules::Module,
+    php_get_module,
+    types::{ArgumentTypeHint, ReturnTypeHint},
+};
 
 use crate::request::HACK_SWOOLE_ON_REQUEST_FUNCTION_NAME;
-pub use errors::{Error, Result};
+pub use errors::{Error, Result, SpanLimitExceeded};
 
 /// Enable agent and report or not.
 const SKYWALKING_AGENT_ENABLE: &str = "skywalking_agent.enable";
@@ -41,7 +52,8 @@ const SKYWALKING_AGENT_ENABLE: &str = "skywalking_agent.enable";
 /// Version of skywalking server.
 const SKYWALKING_AGENT_SKYWALKING_VERSION: &str = "skywalking_agent.skywalking_version";
 
-/// skywalking server address.
+/// skywalking server address, multiple addresses are separated by comma, the
+/// requests are balanced across the reachable servers.
 const SKYWALKING_AGENT_SERVER_ADDR: &str = "skywalking_agent.server_addr";
 
 /// skywalking instance name.
@@ -92,8 +104,9 @@ const SKYWALKING_AGENT_PROPERTIES_REPORT_PERIOD_FACTOR: &str =
 /// PHP8's jit.
 const SKYWALKING_AGENT_ENABLE_ZEND_OBSERVER: &str = "skywalking_agent.enable_zend_observer";
 
-/// Reporter type, optional values are `grpc`, `kafka` and `standalone`, default
-/// is `grpc`.
+/// Reporter type, optional values are `grpc`, `http`, `kafka`, `otlp`, `file`
+/// and `standalone`, default is `grpc`. Multiple reporters except `standalone`
+/// can be separated by comma, such as `grpc,kafka`.
 const SKYWALKING_AGENT_REPORTER_TYPE: &str = "skywalking_agent.reporter_type";
 
 /// A list of host/port pairs to use for establishing the initial connection to
@@ -104,6 +117,43 @@ const SKYWALKING_AGENT_KAFKA_BOOTSTRAP_SERVERS: &str = "skywalking_agent.kafka_b
 /// Only available when the reporter type is `kafka`.
 const SKYWALKING_AGENT_KAFKA_PRODUCER_CONFIG: &str = "skywalking_agent.kafka_producer_config";
 
+/// The base url of the HTTP/JSON receivers of skywalking oap server. Only
+/// available when the reporter type is `http`.
+const SKYWALKING_AGENT_HTTP_SERVER_ADDR: &str = "skywalking_agent.http_server_addr";
+
+/// Whether to compress the request body by gzip. Only available when the
+/// reporter type is `http`.
+const SKYWALKING_AGENT_HTTP_ENABLE_GZIP: &str = "skywalking_agent.http_enable_gzip";
+
+/// Max retry times of a failed request. Only available when the reporter type
+/// is `http`.
+const SKYWALKING_AGENT_HTTP_MAX_RETRIES: &str = "skywalking_agent.http_max_retries";
+
+/// The OTLP/gRPC endpoint of the OpenTelemetry collector, TLS is enabled when
+/// the scheme is `https`. Only available when the reporter type is `otlp`.
+const SKYWALKING_AGENT_OTLP_ENDPOINT: &str = "skywalking_agent.otlp_endpoint";
+
+/// The extra gRPC metadata sent to the OpenTelemetry collector, in the format
+/// `key1=value1,key2=value2`. Only available when the reporter type is `otlp`.
+const SKYWALKING_AGENT_OTLP_HEADERS: &str = "skywalking_agent.otlp_headers";
+
+/// Path of the newline-delimited JSON file, default is `collect.ndjson` in the
+/// `runtime_dir`. Only available when the reporter type is `file`.
+const SKYWALKING_AGENT_FILE_REPORTER_PATH: &str = "skywalking_agent.file_reporter_path";
+
+/// Rotate the file when it exceeds the size in bytes, 0 means never. Only
+/// available when the reporter type is `file`.
+const SKYWALKING_AGENT_FILE_REPORTER_MAX_SIZE: &str = "skywalking_agent.file_reporter_max_size";
+
+/// Rotate the file when it has been written for the seconds, 0 means never.
+/// Only available when the reporter type is `file`.
+const SKYWALKING_AGENT_FILE_REPORTER_ROTATE_INTERVAL: &str =
+    "skywalking_agent.file_reporter_rotate_interval";
+
+/// Max count of the rotated files to keep. Only available when the reporter
+/// type is `file`.
+const SKYWALKING_AGENT_FILE_REPORTER_MAX_FILES: &str = "skywalking_agent.file_reporter_max_files";
+
 /// Whether to enable automatic injection of skywalking context variables (such
 /// as `SW_TRACE_ID`). For `php-fpm` mode, it will be injected into the
 /// `$_SERVER` variable. For `swoole` mode, it will be injected into the
@@ -118,6 +168,163 @@ const SKYWALKING_AGENT_STANDALONE_SOCKET_PATH: &str = "skywalking_agent.standalo
 /// `Info`, Notice`, Warning`, Error`, Critical`, Alert`, Emergency`.
 const SKYWALKING_AGENT_PSR_LOGGING_LEVEL: &str = "skywalking_agent.psr_logging_level";
 
+/// The fixed rate of requests to be sampled, from `0.0` to `1.0`.
+const SKYWALKING_AGENT_SAMPLE_RATE: &str = "skywalking_agent.sample_rate";
+
+/// The max count of requests to be sampled in every `sample_period` seconds,
+/// negative means unlimited.
+const SKYWALKING_AGENT_SAMPLE_N_PER_PERIOD: &str = "skywalking_agent.sample_n_per_period";
+
+/// The period of `sample_n_per_period`. Unit, second.
+const SKYWALKING_AGENT_SAMPLE_PERIOD: &str = "skywalking_agent.sample_period";
+
+/// Always sample the request which carries the upstream `sw8` header.
+const SKYWALKING_AGENT_SAMPLE_FORCE_WITH_PROPAGATION: &str =
+    "skywalking_agent.sample_force_with_propagation";
+
+/// Buffer the spans of sampled request until the request finished, and only
+/// report the traces which match the tail sampling rules.
+const SKYWALKING_AGENT_ENABLE_TAIL_SAMPLING: &str = "skywalking_agent.enable_tail_sampling";
+
+/// Keep the trace whose entry span duration exceeds the threshold, zero means
+/// disabled. Unit, millisecond.
+const SKYWALKING_AGENT_TAIL_SAMPLING_SLOW_THRESHOLD: &str =
+    "skywalking_agent.tail_sampling_slow_threshold";
+
+/// Keep the trace which has error span.
+const SKYWALKING_AGENT_TAIL_SAMPLING_KEEP_ERROR: &str = "skywalking_agent.tail_sampling_keep_error";
+
+/// Keep the trace whose `http.status_code` matches the list, such as
+/// `429,500-599`.
+const SKYWALKING_AGENT_TAIL_SAMPLING_STATUS_CODES: &str =
+    "skywalking_agent.tail_sampling_status_codes";
+
+/// The max count of spans of every segment, the exceeded plugin spans are
+/// suppressed, zero or negative means unlimited.
+const SKYWALKING_AGENT_MAX_SPANS_PER_SEGMENT: &str = "skywalking_agent.max_spans_per_segment";
+
+/// The max bytes of the span tag and span log values, the longer ones are
+/// truncated, zero or negative means unlimited.
+const SKYWALKING_AGENT_MAX_TAG_VALUE_LENGTH: &str = "skywalking_agent.max_tag_value_length";
+
+/// Replace the literals of `db.statement` of PDO and mysqli with `?`, and
+/// collapse the `IN` lists.
+const SKYWALKING_AGENT_OBFUSCATE_SQL: &str = "skywalking_agent.obfuscate_sql";
+
+/// The max bytes of `db.statement` of PDO and mysqli, zero or negative means
+/// unlimited.
+const SKYWALKING_AGENT_SQL_MAX_LENGTH: &str = "skywalking_agent.sql_max_length";
+
+/// Record the bound parameters of PDO and mysqli statements as the
+/// `db.sql.parameters` tag.
+const SKYWALKING_AGENT_TRACE_SQL_PARAMETERS: &str = "skywalking_agent.trace_sql_parameters";
+
+/// The max bytes of `db.sql.parameters`, zero or negative means unlimited.
+const SKYWALKING_AGENT_SQL_PARAMETERS_MAX_LENGTH: &str =
+    "skywalking_agent.sql_parameters_max_length";
+
+/// Trace the cli scripts, every script run is traced as a segment. Swoole
+/// server is always traced no matter this option.
+const SKYWALKING_AGENT_ENABLE_CLI: &str = "skywalking_agent.enable_cli";
+
+/// The functions or methods (`Class::method`) which handle a job in the long
+/// running cli workers, every call of them is traced as a separate segment.
+const SKYWALKING_AGENT_CLI_JOB_FUNCTIONS: &str = "skywalking_agent.cli_job_functions";
+
+/// The json file which lists the functions and methods to be traced as local
+/// spans, refer to the custom enhance doc for the format.
+const SKYWALKING_AGENT_CUSTOM_ENHANCE_FILE: &str = "skywalking_agent.custom_enhance_file";
+
+/// Trace the global and route middleware pipelines of Laravel as local spans.
+const SKYWALKING_AGENT_LARAVEL_TRACE_MIDDLEWARE: &str = "skywalking_agent.laravel_trace_middleware";
+
+/// The rules separated by `;` to normalize the url path of entry span, such as
+/// `/orders/{id};~^/files/.+$ => /files/{path}`.
+const SKYWALKING_AGENT_ENDPOINT_NAME_RULES: &str = "skywalking_agent.endpoint_name_rules";
+
+/// Collapse the numeric and UUID segments of the url path, which isn't matched
+/// by the rules, to `{id}` and `{uuid}`.
+const SKYWALKING_AGENT_ENDPOINT_NAME_COLLAPSE_IDS: &str =
+    "skywalking_agent.endpoint_name_collapse_ids";
+
+/// The max count of distinct endpoint names shared by the processes, counted
+/// by the final names, such as the framework routes, when the requests finish,
+/// the exceeded ones are named `/{overflow}`, zero or negative means unlimited.
+const SKYWALKING_AGENT_ENDPOINT_NAME_MAX_COUNT: &str = "skywalking_agent.endpoint_name_max_count";
+
+/// The requests to be ignored, which aren't traced, separated by `,`, such as
+/// `/healthz,GET|HEAD /static/**`, the path glob may be prefixed with the http
+/// methods separated by `|`.
+const SKYWALKING_AGENT_IGNORE_ENDPOINTS: &str = "skywalking_agent.ignore_endpoints";
+
+/// The request headers to be recorded as the entry span tags, separated by
+/// `,`, `*` means all.
+const SKYWALKING_AGENT_CAPTURE_REQUEST_HEADERS: &str = "skywalking_agent.capture_request_headers";
+
+/// The response headers to be recorded as the entry span tags, separated by
+/// `,`, `*` means all.
+const SKYWALKING_AGENT_CAPTURE_RESPONSE_HEADERS: &str = "skywalking_agent.capture_response_headers";
+
+/// The query params to be recorded as the entry span tags, separated by `,`,
+/// `*` means all.
+const SKYWALKING_AGENT_CAPTURE_QUERY_PARAMS: &str = "skywalking_agent.capture_query_params";
+
+/// The sensitive header and query param names never be recorded.
+const SKYWALKING_AGENT_CAPTURE_DENY_LIST: &str = "skywalking_agent.capture_deny_list";
+
+/// The proxies trusted to set `X-Forwarded-For`, separated by `,`, such as
+/// `10.0.0.0/8,127.0.0.1`.
+const SKYWALKING_AGENT_TRUSTED_PROXIES: &str = "skywalking_agent.trusted_proxies";
+
+/// Whether to spool the collect items on disk under the runtime directory,
+/// when the server is unreachable.
+const SKYWALKING_AGENT_SPOOL_ENABLE: &str = "skywalking_agent.spool_enable";
+
+/// The directory of the disk spool, default is `spool` in the `runtime_dir`.
+/// The directory is locked by one worker, so the workers sharing the
+/// `runtime_dir` must use the different directories.
+const SKYWALKING_AGENT_SPOOL_DIR: &str = "skywalking_agent.spool_dir";
+
+/// The max total size of the spooled items in bytes.
+const SKYWALKING_AGENT_SPOOL_MAX_SIZE: &str = "skywalking_agent.spool_max_size";
+
+/// The capacity of the worker queue of the collect items.
+const SKYWALKING_AGENT_WORKER_QUEUE_CAPACITY: &str = "skywalking_agent.worker_queue_capacity";
+
+/// The count of the collect items to wait for before reporting, the http,
+/// otlp and file reporters send them at once.
+const SKYWALKING_AGENT_WORKER_BATCH_SIZE: &str = "skywalking_agent.worker_batch_size";
+
+/// The max milliseconds to wait for a batch to be filled.
+const SKYWALKING_AGENT_WORKER_BATCH_INTERVAL: &str = "skywalking_agent.worker_batch_interval";
+
+/// The seconds between the worker stats logs.
+const SKYWALKING_AGENT_WORKER_STATS_INTERVAL: &str = "skywalking_agent.worker_stats_interval";
+
+/// The seconds between the worker self telemetry meter reports, 0 means
+/// disabled.
+const SKYWALKING_AGENT_WORKER_SELF_TELEMETRY_INTERVAL: &str =
+    "skywalking_agent.worker_self_telemetry_interval";
+
+/// The seconds between the runtime metrics reports, 0 means disabled.
+const SKYWALKING_AGENT_RUNTIME_METRICS_PERIOD: &str = "skywalking_agent.runtime_metrics_period";
+
+/// The address of the PHP-FPM status page, such as `http://127.0.0.1/status`,
+/// `127.0.0.1:9000` or `unix:/run/php-fpm.sock`, empty means disabled.
+const SKYWALKING_AGENT_FPM_STATUS_ADDRESS: &str = "skywalking_agent.fpm_status_address";
+
+/// The `pm.status_path` of the PHP-FPM pool, used by the FastCGI address.
+const SKYWALKING_AGENT_FPM_STATUS_PATH: &str = "skywalking_agent.fpm_status_path";
+
+/// The seconds between the PHP-FPM pool metrics reports.
+const SKYWALKING_AGENT_FPM_METRICS_PERIOD: &str = "skywalking_agent.fpm_metrics_period";
+
+/// The seconds between the fetches of the dynamic configurations from the
+/// ConfigurationDiscoveryService of the skywalking server, 0 means disabled.
+/// Only available when the reporter types include `grpc`.
+const SKYWALKING_AGENT_CONFIGURATION_DISCOVERY_PERIOD: &str =
+    "skywalking_agent.configuration_discovery_period";
+
 #[php_get_module]
 pub fn get_module() -> Module {
     let mut module = Module::new(
@@ -203,6 +410,43 @@ pub fn get_module() -> Module {
         "{}".to_string(),
         Policy::System,
     );
+    module.add_ini(
+        SKYWALKING_AGENT_HTTP_SERVER_ADDR,
+        "http://127.0.0.1:12800".to_string(),
+        Policy::System,
+    );
+    module.add_ini(SKYWALKING_AGENT_HTTP_ENABLE_GZIP, false, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_HTTP_MAX_RETRIES, 3i64, Policy::System);
+    module.add_ini(
+        SKYWALKING_AGENT_OTLP_ENDPOINT,
+        "http://127.0.0.1:4317".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_OTLP_HEADERS,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_FILE_REPORTER_PATH,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_FILE_REPORTER_MAX_SIZE,
+        100 * 1024 * 1024i64,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_FILE_REPORTER_ROTATE_INTERVAL,
+        0i64,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_FILE_REPORTER_MAX_FILES,
+        5i64,
+        Policy::System,
+    );
     module.add_ini(SKYWALKING_AGENT_INJECT_CONTEXT, false, Policy::System);
     module.add_ini(
         SKYWALKING_AGENT_STANDALONE_SOCKET_PATH,
@@ -214,6 +458,146 @@ pub fn get_module() -> Module {
         "".to_string(),
         Policy::System,
     );
+    module.add_ini(SKYWALKING_AGENT_SAMPLE_RATE, 1.0f64, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_SAMPLE_N_PER_PERIOD, -1i64, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_SAMPLE_PERIOD, 3i64, Policy::System);
+    module.add_ini(
+        SKYWALKING_AGENT_SAMPLE_FORCE_WITH_PROPAGATION,
+        true,
+        Policy::System,
+    );
+    module.add_ini(SKYWALKING_AGENT_ENABLE_TAIL_SAMPLING, false, Policy::System);
+    module.add_ini(
+        SKYWALKING_AGENT_TAIL_SAMPLING_SLOW_THRESHOLD,
+        1000i64,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_TAIL_SAMPLING_KEEP_ERROR,
+        true,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_TAIL_SAMPLING_STATUS_CODES,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(SKYWALKING_AGENT_MAX_SPANS_PER_SEGMENT, 0i64, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_MAX_TAG_VALUE_LENGTH, 0i64, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_OBFUSCATE_SQL, false, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_SQL_MAX_LENGTH, 2048i64, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_TRACE_SQL_PARAMETERS, false, Policy::System);
+    module.add_ini(
+        SKYWALKING_AGENT_SQL_PARAMETERS_MAX_LENGTH,
+        512i64,
+        Policy::System,
+    );
+    module.add_ini(SKYWALKING_AGENT_ENABLE_CLI, false, Policy::System);
+    module.add_ini(
+        SKYWALKING_AGENT_CLI_JOB_FUNCTIONS,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_CUSTOM_ENHANCE_FILE,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_LARAVEL_TRACE_MIDDLEWARE,
+        false,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_ENDPOINT_NAME_RULES,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_ENDPOINT_NAME_COLLAPSE_IDS,
+        false,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_ENDPOINT_NAME_MAX_COUNT,
+        0i64,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_IGNORE_ENDPOINTS,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_CAPTURE_REQUEST_HEADERS,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_CAPTURE_RESPONSE_HEADERS,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_CAPTURE_QUERY_PARAMS,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_CAPTURE_DENY_LIST,
+        "authorization,proxy-authorization,cookie,set-cookie,x-api-key,password,token".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_TRUSTED_PROXIES,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(SKYWALKING_AGENT_SPOOL_ENABLE, false, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_SPOOL_DIR, "".to_string(), Policy::System);
+    module.add_ini(
+        SKYWALKING_AGENT_SPOOL_MAX_SIZE,
+        100 * 1024 * 1024i64,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_WORKER_QUEUE_CAPACITY,
+        255i64,
+        Policy::System,
+    );
+    module.add_ini(SKYWALKING_AGENT_WORKER_BATCH_SIZE, 1i64, Policy::System);
+    module.add_ini(SKYWALKING_AGENT_WORKER_BATCH_INTERVAL, 0i64, Policy::System);
+    module.add_ini(
+        SKYWALKING_AGENT_WORKER_STATS_INTERVAL,
+        60i64,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_WORKER_SELF_TELEMETRY_INTERVAL,
+        0i64,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_RUNTIME_METRICS_PERIOD,
+        0i64,
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_FPM_STATUS_ADDRESS,
+        "".to_string(),
+        Policy::System,
+    );
+    module.add_ini(
+        SKYWALKING_AGENT_FPM_STATUS_PATH,
+        "/status".to_string(),
+        Policy::System,
+    );
+    module.add_ini(SKYWALKING_AGENT_FPM_METRICS_PERIOD, 20i64, Policy::System);
+    module.add_ini(
+        SKYWALKING_AGENT_CONFIGURATION_DISCOVERY_PERIOD,
+        0i64,
+        Policy::System,
+    );
 
     // Hooks.
     module.on_module_init(module::init);
@@ -228,5 +612,36 @@ pub fn get_module() -> Module {
         request::skywalking_hack_swoole_on_request,
     );
 
+    // Userland api.
+    module
+        .add_function(api::TRACE_ID_FUNCTION_NAME, api::skywalking_trace_id)
+        .return_type(ReturnType::new(ReturnTypeHint::String).allow_null());
+    module
+        .add_function(
+            api::GET_SW8_HEADER_FUNCTION_NAME,
+            api::skywalking_get_sw8_header,
+        )
+        .argument(Argument::new("peer").with_type_hint(ArgumentTypeHint::String))
+        .return_type(ReturnType::new(ReturnTypeHint::String).allow_null());
+    module
+        .add_function(api::START_SPAN_FUNCTION_NAME, api::skywalking_start_span)
+        .argument(Argument::new("operation_name").with_type_hint(ArgumentTypeHint::String))
+        .return_type(ReturnType::new(ReturnTypeHint::Bool));
+    module
+        .add_function(api::STOP_SPAN_FUNCTION_NAME, api::skywalking_stop_span)
+        .return_type(ReturnType::new(ReturnTypeHint::Bool));
+    module
+        .add_function(api::ADD_TAG_FUNCTION_NAME, api::skywalking_add_tag)
+        .argument(Argument::new("key").with_type_hint(ArgumentTypeHint::String))
+        .argument(Argument::new("value").with_type_hint(ArgumentTypeHint::String))
+        .return_type(ReturnType::new(ReturnTypeHint::Bool));
+    module
+        .add_function(api::ADD_LOG_FUNCTION_NAME, api::skywalking_add_log)
+        .argument(Argument::new("fields").with_type_hint(ArgumentTypeHint::Array))
+        .return_type(ReturnType::new(ReturnTypeHint::Bool));
+    module
+        .add_function(api::MARK_ERROR_FUNCTION_NAME, api::skywalking_mark_error)
+        .return_type(ReturnType::new(ReturnTypeHint::Bool));
+
     module
 }
@@ -15,9 +15,14 @@
 
 use crate::{
     channel::Reporter,
+    endpoint::{
+        EndpointNameRule, IgnoreEndpointRule, parse_endpoint_name_rules,
+        parse_ignore_endpoint_rules,
+    },
     execute::{register_execute_functions, register_observer_handlers},
     log::PsrLogLevel,
-    util::{IPS, get_sapi_module_name, get_str_ini_with_default},
+    sampler::parse_status_codes,
+    util::{IPS, IpRange, get_sapi_module_name, get_str_ini_with_default, get_str_list_ini},
     worker::init_worker,
     *,
 };
@@ -32,6 +37,7 @@ use skywalking::{
 use std::{
     ffi::{CStr, OsStr},
     fs::{self, OpenOptions},
+    ops::RangeInclusive,
     os::unix::prelude::OsStrExt,
     path::{Path, PathBuf},
     str::FromStr,
@@ -39,7 +45,7 @@ use std::{
     time::SystemTime,
 };
 use time::format_description::well_known::Rfc3339;
-use tracing::{debug, error, info, metadata::LevelFilter};
+use tracing::{debug, error, info, metadata::LevelFilter, warn};
 use tracing_subscriber::{EnvFilter, FmtSubscriber, fmt::time::OffsetTime};
 
 static IS_ENABLE: Lazy<bool> = Lazy::new(|| {
@@ -57,9 +63,20 @@ static IS_ENABLE: Lazy<bool> = Lazy::new(|| {
         return true;
     }
 
+    if *IS_CLI_MODE {
+        return true;
+    }
+
     false
 });
 
+/// Trace the plain cli script, the swoole server isn't included.
+static IS_CLI_MODE: Lazy<bool> = Lazy::new(|| {
+    get_sapi_module_name().to_bytes() == b"cli"
+        && !get_module_registry().exists("swoole")
+        && ini_get::<bool>(SKYWALKING_AGENT_ENABLE_CLI)
+});
+
 pub static SERVER_ADDR: Lazy<String> =
     Lazy::new(|| get_str_ini_with_default(SKYWALKING_AGENT_SERVER_ADDR));
 
@@ -151,6 +168,39 @@ pub static KAFKA_BOOTSTRAP_SERVERS: Lazy<String> =
 pub static KAFKA_PRODUCER_CONFIG: Lazy<String> =
     Lazy::new(|| get_str_ini_with_default(SKYWALKING_AGENT_KAFKA_PRODUCER_CONFIG));
 
+pub static HTTP_SERVER_ADDR: Lazy<String> =
+    Lazy::new(|| get_str_ini_with_default(SKYWALKING_AGENT_HTTP_SERVER_ADDR));
+
+pub static HTTP_ENABLE_GZIP: Lazy<bool> =
+    Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_HTTP_ENABLE_GZIP));
+
+pub static HTTP_MAX_RETRIES: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_HTTP_MAX_RETRIES));
+
+pub static OTLP_ENDPOINT: Lazy<String> =
+    Lazy::new(|| get_str_ini_with_default(SKYWALKING_AGENT_OTLP_ENDPOINT));
+
+pub static OTLP_HEADERS: Lazy<String> =
+    Lazy::new(|| get_str_ini_with_default(SKYWALKING_AGENT_OTLP_HEADERS));
+
+pub static FILE_REPORTER_PATH: Lazy<PathBuf> = Lazy::new(|| {
+    let path = get_str_ini_with_default(SKYWALKING_AGENT_FILE_REPORTER_PATH);
+    if path.is_empty() {
+        RUNTIME_DIR.join("collect.ndjson")
+    } else {
+        PathBuf::from(path)
+    }
+});
+
+pub static FILE_REPORTER_MAX_SIZE: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_FILE_REPORTER_MAX_SIZE));
+
+pub static FILE_REPORTER_ROTATE_INTERVAL: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_FILE_REPORTER_ROTATE_INTERVAL));
+
+pub static FILE_REPORTER_MAX_FILES: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_FILE_REPORTER_MAX_FILES));
+
 pub static INJECT_CONTEXT: Lazy<bool> =
     Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_INJECT_CONTEXT));
 
@@ -167,6 +217,155 @@ pub static PSR_LOGGING_LEVEL: Lazy<PsrLogLevel> = Lazy::new(|| {
         .into()
 });
 
+pub static SAMPLE_RATE: Lazy<f64> = Lazy::new(|| ini_get::<f64>(SKYWALKING_AGENT_SAMPLE_RATE));
+
+pub static SAMPLE_N_PER_PERIOD: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_SAMPLE_N_PER_PERIOD));
+
+pub static SAMPLE_PERIOD: Lazy<i64> = Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_SAMPLE_PERIOD));
+
+pub static SAMPLE_FORCE_WITH_PROPAGATION: Lazy<bool> =
+    Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_SAMPLE_FORCE_WITH_PROPAGATION));
+
+pub static ENABLE_TAIL_SAMPLING: Lazy<bool> =
+    Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_ENABLE_TAIL_SAMPLING));
+
+pub static TAIL_SAMPLING_SLOW_THRESHOLD: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_TAIL_SAMPLING_SLOW_THRESHOLD));
+
+pub static TAIL_SAMPLING_KEEP_ERROR: Lazy<bool> =
+    Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_TAIL_SAMPLING_KEEP_ERROR));
+
+pub static TAIL_SAMPLING_STATUS_CODES: Lazy<Vec<RangeInclusive<i32>>> = Lazy::new(|| {
+    parse_status_codes(&get_str_ini_with_default(
+        SKYWALKING_AGENT_TAIL_SAMPLING_STATUS_CODES,
+    ))
+});
+
+pub static MAX_SPANS_PER_SEGMENT: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_MAX_SPANS_PER_SEGMENT));
+
+pub static MAX_TAG_VALUE_LENGTH: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_MAX_TAG_VALUE_LENGTH));
+
+pub static OBFUSCATE_SQL: Lazy<bool> =
+    Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_OBFUSCATE_SQL));
+
+pub static SQL_MAX_LENGTH: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_SQL_MAX_LENGTH));
+
+pub static TRACE_SQL_PARAMETERS: Lazy<bool> =
+    Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_TRACE_SQL_PARAMETERS));
+
+pub static SQL_PARAMETERS_MAX_LENGTH: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_SQL_PARAMETERS_MAX_LENGTH));
+
+pub static CLI_JOB_FUNCTIONS: Lazy<Vec<String>> =
+    Lazy::new(|| get_str_list_ini(SKYWALKING_AGENT_CLI_JOB_FUNCTIONS));
+
+pub static CUSTOM_ENHANCE_FILE: Lazy<String> =
+    Lazy::new(|| get_str_ini_with_default(SKYWALKING_AGENT_CUSTOM_ENHANCE_FILE));
+
+pub static LARAVEL_TRACE_MIDDLEWARE: Lazy<bool> =
+    Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_LARAVEL_TRACE_MIDDLEWARE));
+
+pub static ENDPOINT_NAME_RULES: Lazy<Vec<EndpointNameRule>> = Lazy::new(|| {
+    parse_endpoint_name_rules(&get_str_ini_with_default(
+        SKYWALKING_AGENT_ENDPOINT_NAME_RULES,
+    ))
+});
+
+pub static ENDPOINT_NAME_COLLAPSE_IDS: Lazy<bool> =
+    Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_ENDPOINT_NAME_COLLAPSE_IDS));
+
+pub static ENDPOINT_NAME_MAX_COUNT: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_ENDPOINT_NAME_MAX_COUNT));
+
+pub static IGNORE_ENDPOINTS: Lazy<Vec<IgnoreEndpointRule>> = Lazy::new(|| {
+    parse_ignore_endpoint_rules(&get_str_ini_with_default(SKYWALKING_AGENT_IGNORE_ENDPOINTS))
+});
+
+pub static CAPTURE_REQUEST_HEADERS: Lazy<Vec<String>> =
+    Lazy::new(|| get_lowercase_str_list_ini(SKYWALKING_AGENT_CAPTURE_REQUEST_HEADERS));
+
+pub static CAPTURE_RESPONSE_HEADERS: Lazy<Vec<String>> =
+    Lazy::new(|| get_lowercase_str_list_ini(SKYWALKING_AGENT_CAPTURE_RESPONSE_HEADERS));
+
+pub static CAPTURE_QUERY_PARAMS: Lazy<Vec<String>> =
+    Lazy::new(|| get_lowercase_str_list_ini(SKYWALKING_AGENT_CAPTURE_QUERY_PARAMS));
+
+pub static CAPTURE_DENY_LIST: Lazy<Vec<String>> =
+    Lazy::new(|| get_lowercase_str_list_ini(SKYWALKING_AGENT_CAPTURE_DENY_LIST));
+
+pub static TRUSTED_PROXIES: Lazy<Vec<IpRange>> = Lazy::new(|| {
+    get_str_list_ini(SKYWALKING_AGENT_TRUSTED_PROXIES)
+        .iter()
+        .filter_map(|proxy| {
+            let range = IpRange::parse(proxy);
+            if range.is_none() {
+                warn!(proxy, "invalid trusted proxy");
+            }
+            range
+        })
+        .collect()
+});
+
+pub static SPOOL_ENABLE: Lazy<bool> = Lazy::new(|| ini_get::<bool>(SKYWALKING_AGENT_SPOOL_ENABLE));
+
+pub static SPOOL_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    let dir = get_str_ini_with_default(SKYWALKING_AGENT_SPOOL_DIR);
+    if dir.is_empty() {
+        RUNTIME_DIR.join("spool")
+    } else {
+        PathBuf::from(dir)
+    }
+});
+
+pub static SPOOL_MAX_SIZE: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_SPOOL_MAX_SIZE));
+
+pub static WORKER_QUEUE_CAPACITY: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_WORKER_QUEUE_CAPACITY));
+
+pub static WORKER_BATCH_SIZE: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_WORKER_BATCH_SIZE));
+
+pub static WORKER_BATCH_INTERVAL: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_WORKER_BATCH_INTERVAL));
+
+pub static WORKER_STATS_INTERVAL: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_WORKER_STATS_INTERVAL));
+
+pub static WORKER_SELF_TELEMETRY_INTERVAL: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_WORKER_SELF_TELEMETRY_INTERVAL));
+
+pub static RUNTIME_METRICS_PERIOD: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_RUNTIME_METRICS_PERIOD));
+
+pub static FPM_STATUS_ADDRESS: Lazy<String> =
+    Lazy::new(|| get_str_ini_with_default(SKYWALKING_AGENT_FPM_STATUS_ADDRESS));
+
+pub static FPM_STATUS_PATH: Lazy<String> =
+    Lazy::new(|| get_str_ini_with_default(SKYWALKING_AGENT_FPM_STATUS_PATH));
+
+pub static FPM_METRICS_PERIOD: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_FPM_METRICS_PERIOD));
+
+pub static CONFIGURATION_DISCOVERY_PERIOD: Lazy<i64> =
+    Lazy::new(|| ini_get::<i64>(SKYWALKING_AGENT_CONFIGURATION_DISCOVERY_PERIOD));
+
+/// The file of the dynamic configurations, written by the worker and read by
+/// the PHP processes, beside the socket file.
+pub static CONFIGURATION_FILE_PATH: Lazy<PathBuf> =
+    Lazy::new(|| SOCKET_FILE_PATH.with_extension("json"));
+
+pub static REPORTER: Lazy<Arc<Reporter>> = Lazy::new(|| {
+    Arc::new(Reporter::new(
+        &*SOCKET_FILE_PATH,
+        (*MAX_TAG_VALUE_LENGTH).max(0) as usize,
+    ))
+});
+
 pub fn init() {
     if !is_enable() {
         return;
@@ -191,8 +390,58 @@ pub fn init() {
     Lazy::force(&REPORTER_TYPE);
     Lazy::force(&KAFKA_BOOTSTRAP_SERVERS);
     Lazy::force(&KAFKA_PRODUCER_CONFIG);
+    Lazy::force(&HTTP_SERVER_ADDR);
+    Lazy::force(&HTTP_ENABLE_GZIP);
+    Lazy::force(&HTTP_MAX_RETRIES);
+    Lazy::force(&OTLP_ENDPOINT);
+    Lazy::force(&OTLP_HEADERS);
+    Lazy::force(&FILE_REPORTER_PATH);
+    Lazy::force(&FILE_REPORTER_MAX_SIZE);
+    Lazy::force(&FILE_REPORTER_ROTATE_INTERVAL);
+    Lazy::force(&FILE_REPORTER_MAX_FILES);
     Lazy::force(&INJECT_CONTEXT);
     Lazy::force(&PSR_LOGGING_LEVEL);
+    Lazy::force(&SAMPLE_RATE);
+    Lazy::force(&SAMPLE_N_PER_PERIOD);
+    Lazy::force(&SAMPLE_PERIOD);
+    Lazy::force(&SAMPLE_FORCE_WITH_PROPAGATION);
+    Lazy::force(&ENABLE_TAIL_SAMPLING);
+    Lazy::force(&TAIL_SAMPLING_SLOW_THRESHOLD);
+    Lazy::force(&TAIL_SAMPLING_KEEP_ERROR);
+    Lazy::force(&TAIL_SAMPLING_STATUS_CODES);
+    Lazy::force(&MAX_SPANS_PER_SEGMENT);
+    Lazy::force(&MAX_TAG_VALUE_LENGTH);
+    Lazy::force(&OBFUSCATE_SQL);
+    Lazy::force(&SQL_MAX_LENGTH);
+    Lazy::force(&TRACE_SQL_PARAMETERS);
+    Lazy::force(&SQL_PARAMETERS_MAX_LENGTH);
+    Lazy::force(&IS_CLI_MODE);
+    Lazy::force(&CLI_JOB_FUNCTIONS);
+    Lazy::force(&CUSTOM_ENHANCE_FILE);
+    Lazy::force(&LARAVEL_TRACE_MIDDLEWARE);
+    Lazy::force(&ENDPOINT_NAME_RULES);
+    Lazy::force(&ENDPOINT_NAME_COLLAPSE_IDS);
+    Lazy::force(&ENDPOINT_NAME_MAX_COUNT);
+    Lazy::force(&IGNORE_ENDPOINTS);
+    Lazy::force(&CAPTURE_REQUEST_HEADERS);
+    Lazy::force(&CAPTURE_RESPONSE_HEADERS);
+    Lazy::force(&CAPTURE_QUERY_PARAMS);
+    Lazy::force(&CAPTURE_DENY_LIST);
+    Lazy::force(&TRUSTED_PROXIES);
+    Lazy::force(&SPOOL_ENABLE);
+    Lazy::force(&SPOOL_DIR);
+    Lazy::force(&SPOOL_MAX_SIZE);
+    Lazy::force(&WORKER_QUEUE_CAPACITY);
+    Lazy::force(&WORKER_BATCH_SIZE);
+    Lazy::force(&WORKER_BATCH_INTERVAL);
+    Lazy::force(&WORKER_STATS_INTERVAL);
+    Lazy::force(&WORKER_SELF_TELEMETRY_INTERVAL);
+    Lazy::force(&RUNTIME_METRICS_PERIOD);
+    Lazy::force(&FPM_STATUS_ADDRESS);
+    Lazy::force(&FPM_STATUS_PATH);
+    Lazy::force(&FPM_METRICS_PERIOD);
+    Lazy::force(&CONFIGURATION_DISCOVERY_PERIOD);
+    Lazy::force(&CONFIGURATION_FILE_PATH);
 
     if let Err(err) = try_init_logger() {
         eprintln!("skywalking_agent: initialize logger failed: {}", err);
@@ -205,6 +454,11 @@ pub fn init() {
         skywalking_version = &*SKYWALKING_VERSION,
         heartbeat_period = &*HEARTBEAT_PERIOD,
         properties_report_period_factor = &*PROPERTIES_REPORT_PERIOD_FACTOR,
+        sample_rate = &*SAMPLE_RATE,
+        sample_n_per_period = &*SAMPLE_N_PER_PERIOD,
+        sample_period = &*SAMPLE_PERIOD,
+        enable_tail_sampling = &*ENABLE_TAIL_SAMPLING,
+        is_cli_mode = &*IS_CLI_MODE,
         "Starting skywalking agent"
     );
 
@@ -231,7 +485,7 @@ pub fn init() {
     // Initialize Agent worker.
     init_worker();
 
-    let reporter = Arc::new(Reporter::new(&*SOCKET_FILE_PATH));
+    let reporter = REPORTER.clone();
 
     tracer::set_global_tracer(Tracer::new(
         &*SERVICE_NAME,
@@ -241,6 +495,11 @@ pub fn init() {
 
     logger::set_global_logger(Logger::new(&*SERVICE_NAME, &*SERVICE_INSTANCE, reporter));
 
+    // Initialize the sampler and the endpoint names before PHP-FPM forks the
+    // pool processes.
+    sampler::init();
+    endpoint::init();
+
     // Hook functions.
     register_execute_functions();
     register_observer_handlers();
@@ -303,6 +562,14 @@ fn try_init_logger() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// The header and query param names are case insensitive.
+fn get_lowercase_str_list_ini(name: &str) -> Vec<String> {
+    get_str_list_ini(name)
+        .into_iter()
+        .map(|item| item.to_lowercase())
+        .collect()
+}
+
 #[inline]
 fn get_module_registry() -> &'static ZArr {
     unsafe { ZArr::from_ptr(&raw const sys::module_registry) }
@@ -313,6 +580,11 @@ pub fn is_enable() -> bool {
     *IS_ENABLE
 }
 
+#[inline]
+pub fn is_cli_mode() -> bool {
+    *IS_CLI_MODE
+}
+
 #[inline]
 pub fn is_standalone_reporter_type() -> bool {
     REPORTER_TYPE.as_str() == "standalone"
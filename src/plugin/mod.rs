@@ -14,7 +14,10 @@
 // limitations under the License.
 
 mod plugin_amqplib;
+mod plugin_cli_job;
 mod plugin_curl;
+mod plugin_custom_enhance;
+mod plugin_laravel;
 mod plugin_memcache;
 mod plugin_memcached;
 mod plugin_mongodb;
@@ -24,17 +27,20 @@ mod plugin_predis;
 mod plugin_psr3;
 mod plugin_redis;
 mod plugin_swoole;
+mod plugin_symfony;
+mod sql;
 mod style;
 
 use crate::{
+    context::RequestContext,
     execute::{AfterExecuteHook, BeforeExecuteHook},
     log::PsrLogLevel,
-    module::PSR_LOGGING_LEVEL,
+    module::{CLI_JOB_FUNCTIONS, CUSTOM_ENHANCE_FILE, PSR_LOGGING_LEVEL, is_cli_mode},
 };
 use once_cell::sync::Lazy;
 use phper::{classes::ClassEntry, eg, objects::ZObj};
 use skywalking::trace::span::HandleSpanObject;
-use std::{collections::HashMap, ops::Deref, sync::Mutex};
+use std::{any::Any, collections::HashMap, ops::Deref, sync::Mutex};
 use tracing::error;
 
 // Register plugins here.
@@ -51,13 +57,36 @@ static PLUGINS: Lazy<Vec<Box<DynPlugin>>> = Lazy::new(|| {
         Box::<plugin_amqplib::AmqplibPlugin>::default(),
         Box::<plugin_mongodb::MongodbPlugin>::default(),
         Box::<plugin_memcache::MemcachePlugin>::default(),
+        Box::<plugin_laravel::LaravelPlugin>::default(),
+        Box::<plugin_symfony::SymfonyPlugin>::default(),
     ];
     if *PSR_LOGGING_LEVEL > PsrLogLevel::Off {
         plugins.push(Box::<plugin_psr3::Psr3Plugin>::default());
     }
+    if is_cli_mode() && !CLI_JOB_FUNCTIONS.is_empty() {
+        plugins.push(Box::<plugin_cli_job::CliJobPlugin>::default());
+    }
+    if !CUSTOM_ENHANCE_FILE.is_empty() {
+        plugins.push(Box::<plugin_custom_enhance::CustomEnhancePlugin>::default());
+    }
     plugins
 });
 
+/// Release the states of the objects kept by the plugins, which hold the
+/// request-scoped values. The hijacked `dtor_obj` isn't called for the objects
+/// destructed by a fatal error or bailout, so clear them before the executor
+/// frees the objects.
+pub fn request_shutdown() {
+    plugin_pdo::clear_bound_parameters();
+    plugin_mysqli::clear_stmts();
+}
+
+/// Release the states of the request kept by the plugins, which are left by
+/// the bailout, before the request context finished.
+pub fn finish_request(request_id: Option<i64>) {
+    plugin_symfony::clear_kernel_frames(request_id);
+}
+
 pub type DynPlugin = dyn Plugin + Send + Sync + 'static;
 
 pub trait Plugin {
@@ -65,6 +94,11 @@ pub trait Plugin {
 
     fn function_name_prefix(&self) -> Option<&'static str>;
 
+    /// The exact names of the functions (not methods) to hook.
+    fn function_names(&self) -> Option<&'static [&'static str]> {
+        None
+    }
+
     fn parent_classes(&self) -> Option<Vec<Option<&'static ClassEntry>>> {
         None
     }
@@ -72,6 +106,22 @@ pub trait Plugin {
     fn hook(
         &self, class_name: Option<&str>, function_name: &str,
     ) -> Option<(Box<BeforeExecuteHook>, Box<AfterExecuteHook>)>;
+
+    /// Whether the hooks are still called for the unsampled request, for the
+    /// plugins which propagate the context to downstream, or don't create
+    /// spans.
+    fn hook_unsampled(&self) -> bool {
+        false
+    }
+
+    /// Whether the hooks of the function are called regardless of the request
+    /// context, even for the unsampled and ignored requests, for recording the
+    /// state of the objects outliving the request, such as the connections
+    /// created between the jobs of cli worker. The hooks must handle the
+    /// missing context by themselves.
+    fn hook_without_context(&self, _class_name: Option<&str>, _function_name: &str) -> bool {
+        false
+    }
 }
 
 #[allow(static_mut_refs)] // TODO: Swith to use thread_local
@@ -95,14 +145,71 @@ pub fn select_plugin_hook(
         HOOK_MAP
             .entry((class_name.map(ToOwned::to_owned), function_name.to_owned()))
             .or_insert_with(|| {
-                select_plugin(class_name, function_name)
-                    .and_then(|plugin| plugin.hook(class_name, function_name))
+                let plugin = select_plugin(class_name, function_name)?;
+                let hooks = plugin.hook(class_name, function_name)?;
+                if plugin.hook_without_context(class_name, function_name) {
+                    return Some(hooks);
+                }
+                let hooks = if plugin.hook_unsampled() {
+                    hooks
+                } else {
+                    skip_unsampled(hooks)
+                };
+                Some(skip_ignored(hooks))
             })
             .as_ref()
             .map(|(before, after)| (before.deref(), after.deref()))
     }
 }
 
+/// The result of before hook, when the hooks are skipped for the unsampled
+/// request.
+struct Unsampled;
+
+fn skip_unsampled(
+    (before, after): (Box<BeforeExecuteHook>, Box<AfterExecuteHook>),
+) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+    (
+        Box::new(move |request_id, execute_data| {
+            if !RequestContext::is_sampled(request_id) {
+                return Ok(Box::new(Unsampled) as Box<dyn Any>);
+            }
+            before(request_id, execute_data)
+        }),
+        Box::new(move |request_id, data, execute_data, return_value| {
+            if data.is::<Unsampled>() {
+                return Ok(());
+            }
+            after(request_id, data, execute_data, return_value)
+        }),
+    )
+}
+
+/// The result of before hook, when the hooks are skipped for the ignored
+/// request.
+struct Ignored;
+
+/// Even the plugins hooking the unsampled request are skipped, since the
+/// ignored request has no request context.
+fn skip_ignored(
+    (before, after): (Box<BeforeExecuteHook>, Box<AfterExecuteHook>),
+) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+    (
+        Box::new(move |request_id, execute_data| {
+            if RequestContext::is_ignored(request_id) {
+                return Ok(Box::new(Ignored) as Box<dyn Any>);
+            }
+            before(request_id, execute_data)
+        }),
+        Box::new(move |request_id, data, execute_data, return_value| {
+            if data.is::<Ignored>() {
+                return Ok(());
+            }
+            after(request_id, data, execute_data, return_value)
+        }),
+    )
+}
+
 fn select_plugin(class_name: Option<&str>, function_name: &str) -> Option<&'static DynPlugin> {
     let mut selected_plugin = None;
 
@@ -126,6 +233,14 @@ fn select_plugin(class_name: Option<&str>, function_name: &str) -> Option<&'stat
                 }
             }
         }
+        if class_name.is_none() {
+            if let Some(function_names) = plugin.function_names() {
+                if function_names.contains(&function_name) {
+                    selected_plugin = Some(plugin);
+                    break 'plugin;
+                }
+            }
+        }
         if let Some(function_name_prefix) = plugin.function_name_prefix() {
             if function_name.starts_with(function_name_prefix) {
                 selected_plugin = Some(plugin);
@@ -140,26 +255,31 @@ fn select_plugin(class_name: Option<&str>, function_name: &str) -> Option<&'stat
 fn log_exception(span: &mut impl HandleSpanObject) -> Option<&mut ZObj> {
     let mut ex = unsafe { ZObj::try_from_mut_ptr(eg!(exception)) };
     if let Some(ex) = ex.as_mut() {
-        let span_object = span.span_object_mut();
-        span_object.is_error = true;
+        log_throwable(span, ex);
+    }
+    ex
+}
 
-        let mut logs = Vec::new();
-        if let Ok(class_name) = ex.get_class().get_name().to_str() {
-            logs.push(("error.kind", class_name.to_owned()));
-        }
-        if let Some(message) = ex.get_property("message").as_z_str() {
-            if let Ok(message) = message.to_str() {
-                logs.push(("message", message.to_owned()));
-            }
-        }
-        if let Ok(stack) = ex.call("getTraceAsString", []) {
-            if let Some(stack) = stack.as_z_str().and_then(|s| s.to_str().ok()) {
-                logs.push(("stack", stack.to_owned()));
-            }
+/// Log the throwable which is caught, so isn't the current exception.
+fn log_throwable(span: &mut impl HandleSpanObject, ex: &mut ZObj) {
+    let span_object = span.span_object_mut();
+    span_object.is_error = true;
+
+    let mut logs = Vec::new();
+    if let Ok(class_name) = ex.get_class().get_name().to_str() {
+        logs.push(("error.kind", class_name.to_owned()));
+    }
+    if let Some(message) = ex.get_property("message").as_z_str() {
+        if let Ok(message) = message.to_str() {
+            logs.push(("message", message.to_owned()));
         }
-        if !logs.is_empty() {
-            span_object.add_log(logs);
+    }
+    if let Ok(stack) = ex.call("getTraceAsString", []) {
+        if let Some(stack) = stack.as_z_str().and_then(|s| s.to_str().ok()) {
+            logs.push(("stack", stack.to_owned()));
         }
     }
-    ex
+    if !logs.is_empty() {
+        span_object.add_log(logs);
+    }
 }
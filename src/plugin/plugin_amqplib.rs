@@ -131,7 +131,7 @@ impl AmqplibPlugin {
         request_id: Option<i64>, class_name: &str, function_name: &str, peer: &str, exchange: &str,
         routing_key: &str,
     ) -> crate::Result<Span> {
-        let mut span = RequestContext::try_with_global_ctx(request_id, |ctx| {
+        let mut span = RequestContext::try_create_span(request_id, |ctx| {
             Ok(ctx.create_exit_span(&format!("{}->{}", class_name, function_name), peer))
         })?;
 
@@ -0,0 +1,136 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Plugin, log_exception, style::ApiStyle};
+use crate::{
+    context::RequestContext,
+    execute::{AfterExecuteHook, BeforeExecuteHook},
+    module::CLI_JOB_FUNCTIONS,
+    request::create_cli_context,
+};
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CLI_JOB_CLASS_NAMES: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    CLI_JOB_FUNCTIONS
+        .iter()
+        .filter_map(|f| f.split_once("::"))
+        .map(|(class_name, _)| class_name)
+        .collect()
+});
+
+static CLI_JOB_FUNCTION_NAMES: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    CLI_JOB_FUNCTIONS
+        .iter()
+        .filter(|f| !f.contains("::"))
+        .map(String::as_str)
+        .collect()
+});
+
+/// Whether a job is running, the nested job calls are traced as part of the
+/// outer job.
+static IS_IN_JOB: AtomicBool = AtomicBool::new(false);
+
+/// Split the long running cli worker (such as queue consumer) into segments by
+/// the configured job functions.
+#[derive(Default, Clone)]
+pub struct CliJobPlugin;
+
+impl Plugin for CliJobPlugin {
+    #[inline]
+    fn class_names(&self) -> Option<&'static [&'static str]> {
+        Some(CLI_JOB_CLASS_NAMES.as_slice())
+    }
+
+    #[inline]
+    fn function_name_prefix(&self) -> Option<&'static str> {
+        None
+    }
+
+    #[inline]
+    fn function_names(&self) -> Option<&'static [&'static str]> {
+        Some(CLI_JOB_FUNCTION_NAMES.as_slice())
+    }
+
+    fn hook(
+        &self, class_name: Option<&str>, function_name: &str,
+    ) -> Option<(Box<BeforeExecuteHook>, Box<AfterExecuteHook>)> {
+        let is_job = CLI_JOB_FUNCTIONS
+            .iter()
+            .any(|f| match (class_name, f.split_once("::")) {
+                (Some(class_name), Some((job_class_name, job_function_name))) => {
+                    class_name == job_class_name
+                        && function_name.eq_ignore_ascii_case(job_function_name)
+                }
+                (None, None) => function_name.eq_ignore_ascii_case(f),
+                _ => false,
+            });
+        if !is_job {
+            return None;
+        }
+
+        let style = if class_name.is_some() {
+            ApiStyle::OO
+        } else {
+            ApiStyle::Procedural
+        };
+        Some(self.hook_job(style.generate_operation_name(class_name, function_name)))
+    }
+
+    /// The job boundaries should be handled no matter the previous context is
+    /// sampled or not.
+    #[inline]
+    fn hook_unsampled(&self) -> bool {
+        true
+    }
+}
+
+impl CliJobPlugin {
+    fn hook_job(&self, operation_name: String) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(move |request_id, _| {
+                if IS_IN_JOB.swap(true, Ordering::Relaxed) {
+                    return Ok(Box::new(false));
+                }
+
+                // Finish the context of the script, or the work between jobs.
+                if let Some(ctx) = RequestContext::remove_global(request_id) {
+                    ctx.finish();
+                }
+
+                if let Err(err) = create_cli_context(&operation_name) {
+                    IS_IN_JOB.store(false, Ordering::Relaxed);
+                    return Err(err);
+                }
+
+                Ok(Box::new(true))
+            }),
+            Box::new(move |request_id, is_job_started, _, _| {
+                if !*is_job_started.downcast::<bool>().unwrap() {
+                    return Ok(());
+                }
+
+                IS_IN_JOB.store(false, Ordering::Relaxed);
+
+                if let Some(mut ctx) = RequestContext::remove_global(request_id) {
+                    log_exception(&mut ctx.entry_span);
+                    ctx.finish();
+                }
+
+                Ok(())
+            }),
+        )
+    }
+}
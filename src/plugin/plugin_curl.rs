@@ -101,6 +101,13 @@ impl Plugin for CurlPlugin {
             _ => None,
         }
     }
+
+    /// Inject the `sw8` header for the unsampled request too, the spans of
+    /// the unsampled request are discarded.
+    #[inline]
+    fn hook_unsampled(&self) -> bool {
+        true
+    }
 }
 
 impl CurlPlugin {
@@ -420,7 +427,7 @@ impl CurlPlugin {
     }
 
     fn create_exit_span(request_id: Option<i64>, info: &CurlInfo) -> crate::Result<Span> {
-        let mut span = RequestContext::try_with_global_ctx(request_id, |ctx| {
+        let mut span = RequestContext::try_create_span(request_id, |ctx| {
             Ok(ctx.create_exit_span(info.url.path(), &info.peer))
         })?;
 
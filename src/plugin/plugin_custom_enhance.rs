@@ -0,0 +1,186 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Plugin, log_exception, style::ApiStyle};
+use crate::{
+    component::COMPONENT_PHP_ID,
+    context::RequestContext,
+    execute::{AfterExecuteHook, BeforeExecuteHook},
+    module::CUSTOM_ENHANCE_FILE,
+    util::z_val_to_scalar_string,
+};
+use anyhow::bail;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use skywalking::trace::span::{HandleSpanObject, Span};
+use std::fs;
+use tracing::error;
+
+static CUSTOM_ENHANCES: Lazy<Vec<Enhance>> = Lazy::new(|| match load_enhances() {
+    Ok(enhances) => enhances,
+    Err(err) => {
+        error!(
+            ?err,
+            file = &*CUSTOM_ENHANCE_FILE,
+            "load custom enhance file failed"
+        );
+        Vec::new()
+    }
+});
+
+static CUSTOM_ENHANCE_CLASS_NAMES: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    CUSTOM_ENHANCES
+        .iter()
+        .filter_map(|enhance| match &enhance.target {
+            Target::Method { class, .. } => Some(class.as_str()),
+            Target::Function { .. } => None,
+        })
+        .collect()
+});
+
+static CUSTOM_ENHANCE_FUNCTION_NAMES: Lazy<Vec<&'static str>> = Lazy::new(|| {
+    CUSTOM_ENHANCES
+        .iter()
+        .filter_map(|enhance| match &enhance.target {
+            Target::Function { function } => Some(function.as_str()),
+            Target::Method { .. } => None,
+        })
+        .collect()
+});
+
+/// The item of custom enhance file.
+#[derive(Deserialize)]
+struct Enhance {
+    #[serde(flatten)]
+    target: Target,
+
+    /// Default is `Class->method` or `function`.
+    operation_name: Option<String>,
+
+    /// Tag the span with the arguments.
+    #[serde(default)]
+    tags: Vec<ArgumentTag>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Target {
+    Method { class: String, method: String },
+    Function { function: String },
+}
+
+#[derive(Deserialize, Clone)]
+struct ArgumentTag {
+    key: String,
+    /// The index of argument, starts from 0.
+    argument: usize,
+}
+
+fn load_enhances() -> anyhow::Result<Vec<Enhance>> {
+    let content = fs::read_to_string(&*CUSTOM_ENHANCE_FILE)?;
+    let enhances: Vec<Enhance> = serde_json::from_str(&content)?;
+    for enhance in &enhances {
+        if let Target::Method { class, .. } = &enhance.target {
+            if class.starts_with('\\') {
+                bail!("class name shouldn't start with `\\`: {}", class);
+            }
+        }
+    }
+    Ok(enhances)
+}
+
+/// Trace the functions and methods listed in the custom enhance file as local
+/// spans.
+#[derive(Default, Clone)]
+pub struct CustomEnhancePlugin;
+
+impl Plugin for CustomEnhancePlugin {
+    #[inline]
+    fn class_names(&self) -> Option<&'static [&'static str]> {
+        Some(CUSTOM_ENHANCE_CLASS_NAMES.as_slice())
+    }
+
+    #[inline]
+    fn function_name_prefix(&self) -> Option<&'static str> {
+        None
+    }
+
+    #[inline]
+    fn function_names(&self) -> Option<&'static [&'static str]> {
+        Some(CUSTOM_ENHANCE_FUNCTION_NAMES.as_slice())
+    }
+
+    fn hook(
+        &self, class_name: Option<&str>, function_name: &str,
+    ) -> Option<(Box<BeforeExecuteHook>, Box<AfterExecuteHook>)> {
+        let enhance =
+            CUSTOM_ENHANCES
+                .iter()
+                .find(|enhance| match (class_name, &enhance.target) {
+                    (Some(class_name), Target::Method { class, method }) => {
+                        class_name == class && function_name.eq_ignore_ascii_case(method)
+                    }
+                    (None, Target::Function { function }) => {
+                        function_name.eq_ignore_ascii_case(function)
+                    }
+                    _ => false,
+                })?;
+
+        let operation_name = enhance.operation_name.clone().unwrap_or_else(|| {
+            let style = if class_name.is_some() {
+                ApiStyle::OO
+            } else {
+                ApiStyle::Procedural
+            };
+            style.generate_operation_name(class_name, function_name)
+        });
+
+        Some(self.hook_enhance(operation_name, enhance.tags.clone()))
+    }
+}
+
+impl CustomEnhancePlugin {
+    fn hook_enhance(
+        &self, operation_name: String, tags: Vec<ArgumentTag>,
+    ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(move |request_id, execute_data| {
+                let mut span = RequestContext::try_create_span(request_id, |ctx| {
+                    Ok(ctx.create_local_span(&operation_name))
+                })?;
+
+                let span_object = span.span_object_mut();
+                span_object.component_id = COMPONENT_PHP_ID;
+                for tag in &tags {
+                    if tag.argument >= execute_data.num_args() {
+                        continue;
+                    }
+                    if let Some(value) =
+                        z_val_to_scalar_string(execute_data.get_parameter(tag.argument))
+                    {
+                        span_object.add_tag(&tag.key, value);
+                    }
+                }
+
+                Ok(Box::new(span))
+            }),
+            Box::new(move |_, span, _, _| {
+                let mut span = span.downcast::<Span>().unwrap();
+                log_exception(&mut *span);
+                Ok(())
+            }),
+        )
+    }
+}
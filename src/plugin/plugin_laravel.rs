@@ -0,0 +1,184 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Plugin, log_exception};
+use crate::{
+    component::COMPONENT_PHP_ID,
+    context::RequestContext,
+    execute::{AfterExecuteHook, BeforeExecuteHook, validate_num_args},
+    module::LARAVEL_TRACE_MIDDLEWARE,
+};
+use anyhow::Context;
+use phper::values::{ExecuteData, ZVal};
+use skywalking::trace::span::{HandleSpanObject, Span};
+use std::any::Any;
+use tracing::debug;
+
+#[derive(Default, Clone)]
+pub struct LaravelPlugin;
+
+impl Plugin for LaravelPlugin {
+    #[inline]
+    fn class_names(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            r"Illuminate\Foundation\Http\Kernel",
+            r"Illuminate\Routing\Router",
+            r"Illuminate\Routing\ControllerDispatcher",
+            r"Illuminate\Routing\Route",
+        ])
+    }
+
+    #[inline]
+    fn function_name_prefix(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn hook(
+        &self, class_name: Option<&str>, function_name: &str,
+    ) -> Option<(Box<BeforeExecuteHook>, Box<AfterExecuteHook>)> {
+        match (class_name, function_name) {
+            (Some(r"Illuminate\Foundation\Http\Kernel"), "sendRequestThroughRouter")
+                if *LARAVEL_TRACE_MIDDLEWARE =>
+            {
+                Some(self.hook_kernel_send_request_through_router())
+            }
+            (Some(r"Illuminate\Routing\Router"), "runRouteWithinStack") => {
+                Some(self.hook_router_run_route_within_stack())
+            }
+            (Some(r"Illuminate\Routing\ControllerDispatcher"), "dispatch") => {
+                Some(self.hook_controller_dispatcher_dispatch())
+            }
+            (Some(r"Illuminate\Routing\Route"), "runCallable") => {
+                Some(self.hook_route_run_callable())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl LaravelPlugin {
+    /// The global middleware pipeline.
+    fn hook_kernel_send_request_through_router(
+        &self,
+    ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(|request_id, _| {
+                let span = create_laravel_local_span(request_id, "Laravel/Middleware/Global")?;
+                Ok(Box::new(span))
+            }),
+            Box::new(after_hook),
+        )
+    }
+
+    /// Rename the entry span by the matched route, and optionally trace the
+    /// route middleware pipeline.
+    fn hook_router_run_route_within_stack(
+        &self,
+    ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(|request_id, execute_data| {
+                validate_num_args(execute_data, 1)?;
+
+                let route = execute_data
+                    .get_mut_parameter(0)
+                    .as_mut_z_obj()
+                    .context("route isn't object")?
+                    .call("uri", [])?;
+                let route = route.expect_z_str()?.to_str()?;
+                let route = format!("/{}", route.trim_start_matches('/'));
+
+                debug!(route, "laravel route matched");
+
+                RequestContext::rename_by_route(request_id, &route)?;
+
+                let span = if *LARAVEL_TRACE_MIDDLEWARE {
+                    Some(create_laravel_local_span(
+                        request_id,
+                        "Laravel/Middleware/Route",
+                    )?)
+                } else {
+                    None
+                };
+                Ok(Box::new(span))
+            }),
+            Box::new(|_, span, _, _| {
+                if let Some(mut span) = *span.downcast::<Option<Span>>().unwrap() {
+                    log_exception(&mut span);
+                }
+                Ok(())
+            }),
+        )
+    }
+
+    /// The controller action, named like
+    /// `App\Http\Controllers\UserController@show`.
+    fn hook_controller_dispatcher_dispatch(
+        &self,
+    ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(|request_id, execute_data| {
+                validate_num_args(execute_data, 3)?;
+
+                let operation_name = get_controller_action(execute_data)?;
+                let span = create_laravel_local_span(request_id, &operation_name)?;
+                Ok(Box::new(span))
+            }),
+            Box::new(after_hook),
+        )
+    }
+
+    /// The closure route action.
+    fn hook_route_run_callable(&self) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(|request_id, _| {
+                let span = create_laravel_local_span(request_id, "Closure")?;
+                Ok(Box::new(span))
+            }),
+            Box::new(after_hook),
+        )
+    }
+}
+
+fn get_controller_action(execute_data: &mut ExecuteData) -> crate::Result<String> {
+    let controller = execute_data
+        .get_parameter(1)
+        .as_z_obj()
+        .context("controller isn't object")?
+        .get_class()
+        .get_name()
+        .to_str()?
+        .to_owned();
+    let method = execute_data.get_parameter(2).expect_z_str()?.to_str()?;
+    Ok(format!("{}@{}", controller, method))
+}
+
+fn create_laravel_local_span(request_id: Option<i64>, operation_name: &str) -> crate::Result<Span> {
+    let mut span = RequestContext::try_create_span(request_id, |ctx| {
+        Ok(ctx.create_local_span(operation_name))
+    })?;
+    span.span_object_mut().component_id = COMPONENT_PHP_ID;
+    Ok(span)
+}
+
+fn after_hook(
+    _request_id: Option<i64>, span: Box<dyn Any>, _execute_data: &mut ExecuteData,
+    _return_value: &mut ZVal,
+) -> crate::Result<()> {
+    let mut span = span.downcast::<Span>().unwrap();
+
+    log_exception(&mut *span);
+
+    Ok(())
+}
@@ -247,7 +247,7 @@ fn create_exit_span(
     style: ApiStyle, request_id: Option<i64>, class_name: Option<&str>, function_name: &str,
     remote_peer: &str, tag_info: &TagInfo<'_>, key: Option<&str>,
 ) -> anyhow::Result<Span> {
-    RequestContext::try_with_global_ctx(request_id, |ctx| {
+    RequestContext::try_create_span(request_id, |ctx| {
         let mut span = ctx.create_exit_span(
             &style.generate_operation_name(class_name, function_name),
             remote_peer,
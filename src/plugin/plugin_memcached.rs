@@ -344,7 +344,7 @@ fn create_exit_span(
     request_id: Option<i64>, class_name: &str, function_name: &str, remote_peer: &str,
     tag_info: &TagInfo<'_>, key: Option<&str>,
 ) -> anyhow::Result<Span> {
-    RequestContext::try_with_global_ctx(request_id, |ctx| {
+    RequestContext::try_create_span(request_id, |ctx| {
         let mut span =
             ctx.create_exit_span(&format!("{}->{}", class_name, function_name), remote_peer);
 
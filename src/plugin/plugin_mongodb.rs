@@ -115,7 +115,7 @@ fn before_manager_crud_hook(
     let handle = this.handle();
     debug!(handle, function_name, "call MongoDB Manager CRUD method");
 
-    let mut span = RequestContext::try_with_global_ctx(request_id, |ctx| {
+    let mut span = RequestContext::try_create_span(request_id, |ctx| {
         // Since the driver connects to the database lazily, peer here is empty and
         // reset it in after hook.
         Ok(ctx.create_exit_span(&format!("{}->{}", MANAGER_CLASS_NAME, function_name), ""))
@@ -13,31 +13,55 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{Plugin, log_exception, style::ApiStyle};
+use super::{
+    Plugin, log_exception,
+    sql::{BoundParameters, ParameterKey, format_array_parameters, sanitize_statement},
+    style::ApiStyle,
+};
 use crate::{
     component::COMPONENT_PHP_MYSQLI_ID,
     context::RequestContext,
-    execute::{AfterExecuteHook, BeforeExecuteHook},
+    execute::{AfterExecuteHook, BeforeExecuteHook, Noop},
+    module::TRACE_SQL_PARAMETERS,
+    tag::{TAG_DB_AFFECTED_ROWS, TAG_DB_SQL_PARAMETERS, TAG_DB_STATEMENT},
 };
+use once_cell::sync::OnceCell;
 use phper::{
     alloc::ToRefOwned,
+    classes::ClassEntry,
     functions::call,
     objects::ZObj,
+    sys,
     values::{ExecuteData, ZVal},
 };
 use skywalking::{
     proto::v3::SpanLayer,
     trace::span::{HandleSpanObject, Span},
 };
+use std::{cell::RefCell, collections::HashMap};
 use tracing::{debug, error};
 
+thread_local! {
+    static STMT_MAP: RefCell<HashMap<u32, StmtInfo>> = Default::default();
+}
+
+/// The original `dtor_obj` of the mysqli classes, which share the handlers.
+static ORI_DTOR: OnceCell<sys::zend_object_dtor_obj_t> = OnceCell::new();
+
+/// The `mysqli_stmt` created by `prepare`.
+struct StmtInfo {
+    peer: String,
+    statement: String,
+    parameters: BoundParameters,
+}
+
 #[derive(Default, Clone)]
 pub struct MySQLImprovedPlugin;
 
 impl Plugin for MySQLImprovedPlugin {
     #[inline]
     fn class_names(&self) -> Option<&'static [&'static str]> {
-        Some(&["mysqli"])
+        Some(&["mysqli", "mysqli_stmt"])
     }
 
     #[inline]
@@ -79,6 +103,18 @@ impl Plugin for MySQLImprovedPlugin {
             {
                 Some(self.hook_mysqli_methods(class_name, function_name, ApiStyle::Procedural))
             }
+            (Some("mysqli_stmt"), "bind_param") if *TRACE_SQL_PARAMETERS => {
+                Some(self.hook_mysqli_stmt_bind_param(ApiStyle::OO))
+            }
+            (None, "mysqli_stmt_bind_param") if *TRACE_SQL_PARAMETERS => {
+                Some(self.hook_mysqli_stmt_bind_param(ApiStyle::Procedural))
+            }
+            (Some("mysqli_stmt"), "execute" | "get_result" | "fetch") => {
+                Some(self.hook_mysqli_stmt_methods(class_name, function_name, ApiStyle::OO))
+            }
+            (None, "mysqli_stmt_execute" | "mysqli_stmt_get_result" | "mysqli_stmt_fetch") => {
+                Some(self.hook_mysqli_stmt_methods(class_name, function_name, ApiStyle::Procedural))
+            }
             _ => None,
         }
     }
@@ -159,19 +195,35 @@ impl MySQLImprovedPlugin {
                     style,
                 )?;
 
-                if execute_data.num_args() >= 1 {
-                    if let Some(statement) = execute_data.get_parameter(0).as_z_str() {
-                        span.add_tag("db.statement", statement.to_str()?);
+                if style.num_args(execute_data) >= 1 {
+                    if let Some(statement) = style.get_mut_parameter(execute_data, 0).as_z_str() {
+                        span.add_tag(
+                            TAG_DB_STATEMENT,
+                            sanitize_statement(statement.to_str()?, true),
+                        );
                     }
                 }
 
                 Ok(Box::new(span) as _)
             }),
-            Box::new(move |_, span, _, return_value| {
+            Box::new(move |_, span, execute_data, return_value| {
                 let mut span = span.downcast::<Span>().unwrap();
                 if let Some(b) = return_value.as_bool() {
                     if !b {
                         span.span_object_mut().is_error = true;
+                        log_error(&mut span, style.get_this_mut(execute_data)?, "mysqli")?;
+                    }
+                }
+                if let Some(stmt) = return_value.as_mut_z_obj() {
+                    let stmt_class = ClassEntry::from_globals("mysqli_stmt").unwrap();
+                    if stmt.get_class().is_instance_of(stmt_class) {
+                        let statement = style
+                            .get_mut_parameter(execute_data, 0)
+                            .as_z_str()
+                            .and_then(|statement| statement.to_str().ok())
+                            .unwrap_or_default()
+                            .to_owned();
+                        register_stmt(stmt, span.span_object().peer.clone(), statement);
                     }
                 }
                 log_exception(&mut *span);
@@ -179,13 +231,199 @@ impl MySQLImprovedPlugin {
             }),
         )
     }
+
+    /// Keep the bound variables until the statement is executed.
+    fn hook_mysqli_stmt_bind_param(
+        &self, style: ApiStyle,
+    ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(move |_, execute_data| {
+                let handle = style.get_this_mut(execute_data)?.handle();
+
+                // The first argument is the types.
+                let values = (1..style.num_args(execute_data))
+                    .map(|i| style.get_mut_parameter(execute_data, i).clone())
+                    .collect::<Vec<_>>();
+
+                STMT_MAP.with(|map| {
+                    // Skip the statement not prepared by the hooked functions.
+                    if let Some(info) = map.borrow_mut().get_mut(&handle) {
+                        info.parameters.clear();
+                        for (i, value) in values.into_iter().enumerate() {
+                            info.parameters.bind(ParameterKey::Index(i as i64), value);
+                        }
+                    }
+                });
+
+                Ok(Box::new(()))
+            }),
+            Noop::noop(),
+        )
+    }
+
+    /// The execution and the fetching of the statement, tagged with the
+    /// statement captured by `prepare`.
+    fn hook_mysqli_stmt_methods(
+        &self, class_name: Option<&str>, function_name: &str, style: ApiStyle,
+    ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        let class_name = class_name.map(ToOwned::to_owned);
+        let function_name = function_name.to_owned();
+        let is_execute = function_name.ends_with("execute");
+        (
+            Box::new(move |request_id, execute_data| {
+                let handle = style.get_this_mut(execute_data)?.handle();
+
+                debug!(handle, class_name, function_name, "call mysqli_stmt method");
+
+                // The parameters passed to `execute` (PHP 8.1+) replace the bound ones.
+                let array_parameters = if is_execute && style.num_args(execute_data) >= 1 {
+                    style
+                        .get_mut_parameter(execute_data, 0)
+                        .as_z_arr()
+                        .map(format_array_parameters)
+                } else {
+                    None
+                };
+
+                let (peer, statement, parameters) =
+                    STMT_MAP.with(|map| match map.borrow().get(&handle) {
+                        Some(info) => (
+                            info.peer.clone(),
+                            Some(sanitize_statement(&info.statement, true)),
+                            Some(info.parameters.format()),
+                        ),
+                        None => Default::default(),
+                    });
+
+                let mut span = create_mysqli_exit_span(
+                    request_id,
+                    class_name.as_deref(),
+                    &function_name,
+                    &peer,
+                    style,
+                )?;
+
+                if let Some(statement) = statement {
+                    span.add_tag(TAG_DB_STATEMENT, statement);
+                }
+                if is_execute && *TRACE_SQL_PARAMETERS {
+                    if let Some(parameters) = array_parameters.or(parameters) {
+                        span.add_tag(TAG_DB_SQL_PARAMETERS, parameters);
+                    }
+                }
+
+                Ok(Box::new(span) as _)
+            }),
+            Box::new(move |_, span, execute_data, return_value| {
+                let mut span = span.downcast::<Span>().unwrap();
+                if log_exception(&mut *span).is_some() {
+                    return Ok(());
+                }
+
+                // The `fetch` returns null if there are no more rows.
+                match return_value.as_bool() {
+                    Some(false) => {
+                        span.span_object_mut().is_error = true;
+                        log_error(&mut span, style.get_this_mut(execute_data)?, "mysqli_stmt")?;
+                    }
+                    Some(true) if is_execute => {
+                        let this = style.get_this_mut(execute_data)?;
+                        // Negative for the select statements.
+                        if let Some(rows) = call_with_this(this, "mysqli_stmt", "affected_rows")?
+                            .as_long()
+                            .filter(|rows| *rows >= 0)
+                        {
+                            span.add_tag(TAG_DB_AFFECTED_ROWS, rows.to_string());
+                        }
+                    }
+                    _ => {}
+                }
+                Ok(())
+            }),
+        )
+    }
+}
+
+/// Log the error of `mysqli` or `mysqli_stmt`, by the procedural functions such
+/// as `mysqli_stmt_errno`, like `PDO::errorInfo`.
+fn log_error(span: &mut Span, this: &mut ZObj, prefix: &str) -> crate::Result<()> {
+    let code = call_with_this(this, prefix, "errno")?.expect_long()?;
+    if code == 0 {
+        return Ok(());
+    }
+    let error = call_with_this(this, prefix, "error")?;
+    let state = call_with_this(this, prefix, "sqlstate")?;
+
+    span.span_object_mut().add_log([
+        ("SQLSTATE", state.expect_z_str()?.to_str()?),
+        ("Error Code", &code.to_string()),
+        ("Error", error.expect_z_str()?.to_str()?),
+    ]);
+
+    Ok(())
+}
+
+/// Call the procedural function of `mysqli` or `mysqli_stmt`, the properties
+/// can't be read by `ZObj::get_property`, whose handler requires the return
+/// buffer.
+fn call_with_this(this: &mut ZObj, prefix: &str, name: &str) -> phper::Result<ZVal> {
+    call(
+        &format!("{}_{}", prefix, name),
+        [ZVal::from(this.to_ref_owned())],
+    )
+}
+
+/// Keep the peer and the statement of `mysqli_stmt` for the execution, removed
+/// when the object is destroyed.
+fn register_stmt(stmt: &mut ZObj, peer: String, statement: String) {
+    let handle = stmt.handle();
+    debug!(handle, peer, statement, "register mysqli_stmt");
+
+    unsafe {
+        let handlers = (*stmt.as_mut_ptr()).handlers as *mut sys::zend_object_handlers;
+        ORI_DTOR.get_or_init(|| {
+            let ori_dtor = (*handlers).dtor_obj;
+            (*handlers).dtor_obj = Some(mysqli_dtor);
+            ori_dtor
+        });
+    }
+
+    let info = StmtInfo {
+        peer,
+        statement,
+        parameters: Default::default(),
+    };
+    let ori_info = STMT_MAP.with(|map| map.borrow_mut().insert(handle, info));
+    drop(ori_info);
+}
+
+unsafe extern "C" fn mysqli_dtor(object: *mut sys::zend_object) {
+    unsafe {
+        let handle = ZObj::from_ptr(object).handle();
+
+        // Release the bound variables out of the borrow, in case of reentrance
+        // by their destructors.
+        let info = STMT_MAP.with(|map| map.borrow_mut().remove(&handle));
+        drop(info);
+
+        if let Some(Some(dtor)) = ORI_DTOR.get().copied() {
+            dtor(object);
+        }
+    }
+}
+
+pub(super) fn clear_stmts() {
+    // Release the bound variables out of the borrow, in case of reentrance by
+    // their destructors.
+    let map = STMT_MAP.with(|map| map.take());
+    drop(map);
 }
 
 fn create_mysqli_exit_span(
     request_id: Option<i64>, class_name: Option<&str>, function_name: &str, peer: &str,
     style: ApiStyle,
 ) -> anyhow::Result<Span> {
-    RequestContext::try_with_global_ctx(request_id, |ctx| {
+    RequestContext::try_create_span(request_id, |ctx| {
         let mut span = ctx.create_exit_span(
             &style.generate_operation_name(class_name, function_name),
             peer,
@@ -13,12 +13,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{Plugin, log_exception};
+use super::{
+    Plugin, log_exception,
+    sql::{BoundParameters, ParameterKey, format_array_parameters, sanitize_statement},
+};
 use crate::{
     component::COMPONENT_PHP_PDO_ID,
     context::RequestContext,
-    execute::{AfterExecuteHook, BeforeExecuteHook, get_this_mut, validate_num_args},
-    tag::{TAG_DB_STATEMENT, TAG_DB_TYPE},
+    execute::{AfterExecuteHook, BeforeExecuteHook, Noop, get_this_mut, validate_num_args},
+    module::TRACE_SQL_PARAMETERS,
+    tag::{TAG_DB_AFFECTED_ROWS, TAG_DB_SQL_PARAMETERS, TAG_DB_STATEMENT, TAG_DB_TYPE},
 };
 use anyhow::Context;
 use dashmap::DashMap;
@@ -34,12 +38,16 @@ use skywalking::{
     proto::v3::SpanLayer,
     trace::span::{HandleSpanObject, Span},
 };
-use std::{any::Any, str::FromStr};
+use std::{any::Any, cell::RefCell, collections::HashMap, str::FromStr};
 use tracing::{debug, warn};
 
 static DSN_MAP: Lazy<DashMap<u32, Dsn>> = Lazy::new(Default::default);
 static DTOR_MAP: Lazy<DashMap<u32, sys::zend_object_dtor_obj_t>> = Lazy::new(Default::default);
 
+thread_local! {
+    static BOUND_PARAMETERS_MAP: RefCell<HashMap<u32, BoundParameters>> = Default::default();
+}
+
 #[derive(Default, Clone)]
 pub struct PdoPlugin;
 
@@ -78,12 +86,22 @@ impl Plugin for PdoPlugin {
             {
                 Some(self.hook_pdo_statement_methods(function_name))
             }
+            (Some("PDOStatement"), "bindValue" | "bindParam") if *TRACE_SQL_PARAMETERS => {
+                Some(self.hook_pdo_statement_bind())
+            }
             _ => None,
         }
     }
+
+    fn hook_without_context(&self, class_name: Option<&str>, function_name: &str) -> bool {
+        matches!((class_name, function_name), (Some("PDO"), "__construct"))
+    }
 }
 
 impl PdoPlugin {
+    /// The dsn is recorded even without the span, such as the connection
+    /// created before the jobs of cli worker, so the spans of the connection
+    /// in the later requests have the peer.
     fn hook_pdo_construct(&self) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
         (
             Box::new(|request_id, execute_data| {
@@ -100,15 +118,27 @@ impl PdoPlugin {
                 let dsn: Dsn = dsn.parse()?;
                 debug!(?dsn, "parse PDO dsn");
 
-                let span = create_exit_span_with_dsn(request_id, "PDO", "__construct", &dsn)?;
+                let span = (RequestContext::is_sampled(request_id)
+                    && !RequestContext::is_ignored(request_id))
+                .then(|| create_exit_span_with_dsn(request_id, "PDO", "__construct", &dsn))
+                .transpose();
 
                 DSN_MAP.insert(handle, dsn);
 
-                Ok(Box::new(span))
+                match span {
+                    Ok(Some(span)) => Ok(Box::new(span) as Box<dyn Any>),
+                    Ok(None) => Ok(Box::new(())),
+                    // Without the request context, or the span is suppressed.
+                    Err(err) => {
+                        debug!(?err, "skip the span of PDO construct");
+                        Ok(Box::new(()))
+                    }
+                }
             }),
             Box::new(move |_, span, _, _| {
-                let mut span = span.downcast::<Span>().unwrap();
-                log_exception(&mut *span);
+                if let Ok(mut span) = span.downcast::<Span>() {
+                    log_exception(&mut *span);
+                }
                 Ok(())
             }),
         )
@@ -118,25 +148,40 @@ impl PdoPlugin {
         &self, function_name: &str,
     ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
         let function_name = function_name.to_owned();
+        let is_exec = function_name == "exec";
         (
             Box::new(move |request_id, execute_data| {
                 let handle = get_this_mut(execute_data)?.handle();
 
                 debug!(handle, function_name, "call PDO method");
 
-                let mut span = with_dsn(handle, |dsn| {
-                    create_exit_span_with_dsn(request_id, "PDO", &function_name, dsn)
+                let (mut span, is_mysql) = with_dsn(handle, |dsn| {
+                    let span = create_exit_span_with_dsn(request_id, "PDO", &function_name, dsn)?;
+                    Ok((span, dsn.is_mysql()))
                 })?;
 
                 if execute_data.num_args() >= 1 {
                     if let Some(statement) = execute_data.get_parameter(0).as_z_str() {
-                        span.add_tag(TAG_DB_STATEMENT, statement.to_str()?);
+                        span.add_tag(
+                            TAG_DB_STATEMENT,
+                            sanitize_statement(statement.to_str()?, is_mysql),
+                        );
                     }
                 }
 
                 Ok(Box::new(span) as _)
             }),
-            Box::new(after_hook),
+            Box::new(move |request_id, mut span, execute_data, return_value| {
+                // `PDO::exec` returns the count of affected rows.
+                if is_exec {
+                    if let Some(rows) = return_value.as_long() {
+                        span.downcast_mut::<Span>()
+                            .unwrap()
+                            .add_tag(TAG_DB_AFFECTED_ROWS, rows.to_string());
+                    }
+                }
+                after_hook(request_id, span, execute_data, return_value)
+            }),
         )
     }
 
@@ -144,6 +189,7 @@ impl PdoPlugin {
         &self, function_name: &str,
     ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
         let function_name = function_name.to_owned();
+        let is_execute = function_name == "execute";
         (
             Box::new(move |request_id, execute_data| {
                 let this = get_this_mut(execute_data)?;
@@ -151,19 +197,90 @@ impl PdoPlugin {
 
                 debug!(handle, function_name, "call PDOStatement method");
 
-                let mut span = with_dsn(handle, |dsn| {
-                    create_exit_span_with_dsn(request_id, "PDOStatement", &function_name, dsn)
+                let (mut span, is_mysql) = with_dsn(handle, |dsn| {
+                    let span =
+                        create_exit_span_with_dsn(request_id, "PDOStatement", &function_name, dsn)?;
+                    Ok((span, dsn.is_mysql()))
                 })?;
 
                 if let Some(query) = this.get_property("queryString").as_z_str() {
-                    span.add_tag(TAG_DB_STATEMENT, query.to_str()?);
+                    span.add_tag(
+                        TAG_DB_STATEMENT,
+                        sanitize_statement(query.to_str()?, is_mysql),
+                    );
                 } else {
                     warn!("PDOStatement queryString is empty");
                 }
 
+                if is_execute && *TRACE_SQL_PARAMETERS {
+                    // The parameters passed to `execute` replace the bound ones.
+                    let parameters = if execute_data.num_args() >= 1 {
+                        execute_data
+                            .get_parameter(0)
+                            .as_z_arr()
+                            .map(format_array_parameters)
+                    } else {
+                        None
+                    };
+                    let parameters = parameters.or_else(|| {
+                        BOUND_PARAMETERS_MAP
+                            .with(|map| map.borrow().get(&handle).map(BoundParameters::format))
+                    });
+                    if let Some(parameters) = parameters {
+                        span.add_tag(TAG_DB_SQL_PARAMETERS, parameters);
+                    }
+                }
+
                 Ok(Box::new(span) as _)
             }),
-            Box::new(after_hook),
+            Box::new(move |request_id, mut span, execute_data, return_value| {
+                if is_execute && return_value.as_bool() == Some(true) {
+                    let this = get_this_mut(execute_data)?;
+                    // The statements with the result set, such as select, have columns, and
+                    // their row counts aren't the affected rows.
+                    let has_columns = this
+                        .call("columnCount", [])?
+                        .as_long()
+                        .is_some_and(|count| count > 0);
+                    let rows = if has_columns {
+                        None
+                    } else {
+                        this.call("rowCount", [])?.as_long()
+                    };
+                    if let Some(rows) = rows {
+                        span.downcast_mut::<Span>()
+                            .unwrap()
+                            .add_tag(TAG_DB_AFFECTED_ROWS, rows.to_string());
+                    }
+                }
+                after_hook(request_id, span, execute_data, return_value)
+            }),
+        )
+    }
+
+    /// Keep the bound values, or the bound variables of `bindParam`, until the
+    /// statement is executed.
+    fn hook_pdo_statement_bind(&self) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(|_, execute_data| {
+                validate_num_args(execute_data, 2)?;
+
+                let handle = get_this_mut(execute_data)?.handle();
+                // The statement isn't hooked, so the parameters can't be cleaned.
+                if !DSN_MAP.contains_key(&handle) {
+                    return Ok(Box::new(()));
+                }
+
+                let key = ParameterKey::from_z_val(execute_data.get_parameter(0))
+                    .context("parameter isn't int or str")?;
+                let value = execute_data.get_parameter(1).clone();
+
+                BOUND_PARAMETERS_MAP
+                    .with(|map| map.borrow_mut().entry(handle).or_default().bind(key, value));
+
+                Ok(Box::new(()))
+            }),
+            Noop::noop(),
         )
     }
 }
@@ -197,12 +314,23 @@ unsafe extern "C" fn dtor(object: *mut sys::zend_object) {
         let handle = ZObj::from_ptr(object).handle();
 
         DSN_MAP.remove(&handle);
+        // Release the bound variables out of the borrow, in case of reentrance by
+        // their destructors.
+        let parameters = BOUND_PARAMETERS_MAP.with(|map| map.borrow_mut().remove(&handle));
+        drop(parameters);
         if let Some((_, Some(dtor))) = DTOR_MAP.remove(&handle) {
             dtor(object);
         }
     }
 }
 
+pub(super) fn clear_bound_parameters() {
+    // Release the bound variables out of the borrow, in case of reentrance by
+    // their destructors.
+    let map = BOUND_PARAMETERS_MAP.with(|map| map.take());
+    drop(map);
+}
+
 fn after_hook(
     _: Option<i64>, span: Box<dyn Any>, execute_data: &mut ExecuteData, return_value: &mut ZVal,
 ) -> crate::Result<()> {
@@ -274,7 +402,7 @@ fn get_error_info_item(info: &ZArr, i: u64) -> anyhow::Result<&ZVal> {
 fn create_exit_span_with_dsn(
     request_id: Option<i64>, class_name: &str, function_name: &str, dsn: &Dsn,
 ) -> anyhow::Result<Span> {
-    RequestContext::try_with_global_ctx(request_id, |ctx| {
+    RequestContext::try_create_span(request_id, |ctx| {
         let mut span =
             ctx.create_exit_span(&format!("{}->{}", class_name, function_name), &dsn.peer);
 
@@ -302,6 +430,12 @@ struct Dsn {
     peer: String,
 }
 
+impl Dsn {
+    fn is_mysql(&self) -> bool {
+        self.db_type == "mysql"
+    }
+}
+
 impl FromStr for Dsn {
     type Err = anyhow::Error;
 
@@ -222,7 +222,7 @@ impl PredisPlugin {
                 debug!(handle, cmd, key, op, "call redis command");
 
                 let mut span =
-                    RequestContext::try_with_global_ctx(request_id, |ctx| {
+                    RequestContext::try_create_span(request_id, |ctx| {
                         Ok(ctx.create_exit_span(
                             &format!("{}->{}", class_name, &function_name,),
                             &peer,
@@ -255,7 +255,7 @@ impl RedisPlugin {
                 debug!(addr, "Get redis peer");
                 PEER_MAP.insert(this.handle(), Peer { addr: addr.clone() });
 
-                let mut span = RequestContext::try_with_global_ctx(request_id, |ctx| {
+                let mut span = RequestContext::try_create_span(request_id, |ctx| {
                     Ok(ctx.create_exit_span(&format!("{}->{}", class_name, function_name), &addr))
                 })?;
 
@@ -300,7 +300,7 @@ impl RedisPlugin {
 
                 debug!(handle, cmd = function_name, key, op, "call redis command");
 
-                let mut span = RequestContext::try_with_global_ctx(request_id, |ctx| {
+                let mut span = RequestContext::try_create_span(request_id, |ctx| {
                     Ok(ctx.create_exit_span(&format!("{}->{}", class_name, function_name), &peer))
                 })?;
 
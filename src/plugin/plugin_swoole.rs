@@ -51,6 +51,11 @@ impl Plugin for SwooleServerPlugin {
             _ => None,
         }
     }
+
+    #[inline]
+    fn hook_unsampled(&self) -> bool {
+        true
+    }
 }
 
 impl SwooleServerPlugin {
@@ -130,6 +135,11 @@ impl Plugin for SwooleHttpResponsePlugin {
             _ => None,
         }
     }
+
+    #[inline]
+    fn hook_unsampled(&self) -> bool {
+        true
+    }
 }
 
 impl SwooleHttpResponsePlugin {
@@ -0,0 +1,250 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Plugin, log_exception, log_throwable};
+use crate::{
+    component::COMPONENT_PHP_ID,
+    context::RequestContext,
+    execute::{AfterExecuteHook, BeforeExecuteHook, Noop, validate_num_args},
+};
+use anyhow::Context;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use phper::{objects::ZObj, values::ZVal};
+use skywalking::trace::span::{HandleSpanObject, Span};
+use tracing::debug;
+
+/// `HttpKernelInterface::MAIN_REQUEST`.
+const MAIN_REQUEST: i64 = 1;
+
+/// The stack of `HttpKernel::handle` calls of every request, the sub requests
+/// (such as the fragments) are nested.
+static KERNEL_FRAMES: Lazy<DashMap<Option<i64>, Vec<KernelFrame>>> = Lazy::new(DashMap::new);
+
+struct KernelFrame {
+    is_main_request: bool,
+    /// Created when the controller is resolved, finished when the kernel
+    /// handling finished.
+    controller_span: Option<Span>,
+}
+
+#[derive(Default, Clone)]
+pub struct SymfonyPlugin;
+
+impl Plugin for SymfonyPlugin {
+    #[inline]
+    fn class_names(&self) -> Option<&'static [&'static str]> {
+        Some(&[
+            r"Symfony\Component\HttpKernel\HttpKernel",
+            r"Symfony\Component\HttpKernel\Controller\ControllerResolver",
+        ])
+    }
+
+    #[inline]
+    fn function_name_prefix(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn hook(
+        &self, class_name: Option<&str>, function_name: &str,
+    ) -> Option<(Box<BeforeExecuteHook>, Box<AfterExecuteHook>)> {
+        match (class_name, function_name) {
+            (Some(r"Symfony\Component\HttpKernel\HttpKernel"), "handle") => {
+                Some(self.hook_kernel_handle())
+            }
+            (Some(r"Symfony\Component\HttpKernel\HttpKernel"), "handleThrowable") => {
+                Some(self.hook_kernel_handle_throwable())
+            }
+            (
+                Some(r"Symfony\Component\HttpKernel\Controller\ControllerResolver"),
+                "getController",
+            ) => Some(self.hook_controller_resolver_get_controller()),
+            _ => None,
+        }
+    }
+}
+
+impl SymfonyPlugin {
+    fn hook_kernel_handle(&self) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(|request_id, execute_data| {
+                let request_type = if execute_data.num_args() >= 2 {
+                    execute_data.get_parameter(1).as_long()
+                } else {
+                    None
+                };
+
+                let is_main_request = request_type.unwrap_or(MAIN_REQUEST) == MAIN_REQUEST;
+
+                KERNEL_FRAMES
+                    .entry(request_id)
+                    .or_default()
+                    .push(KernelFrame {
+                        is_main_request,
+                        controller_span: None,
+                    });
+
+                Ok(Box::new(()))
+            }),
+            Box::new(|request_id, _, _, _| {
+                let Some(frame) = pop_kernel_frame(request_id) else {
+                    return Ok(());
+                };
+
+                if let Some(mut span) = frame.controller_span {
+                    log_exception(&mut span);
+                }
+
+                // The exception isn't caught by kernel, when `$catch` is false.
+                if frame.is_main_request {
+                    RequestContext::try_with_global(request_id, |ctx| {
+                        log_exception(&mut ctx.entry_span);
+                        Ok(())
+                    })?;
+                }
+
+                Ok(())
+            }),
+        )
+    }
+
+    /// The exception caught by kernel is converted to the error response, so
+    /// log it here.
+    fn hook_kernel_handle_throwable(&self) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(|request_id, execute_data| {
+                validate_num_args(execute_data, 1)?;
+
+                let ex = execute_data
+                    .get_mut_parameter(0)
+                    .as_mut_z_obj()
+                    .context("throwable isn't object")?;
+                RequestContext::try_with_global(request_id, |ctx| {
+                    log_throwable(&mut ctx.entry_span, ex);
+                    Ok(())
+                })?;
+
+                Ok(Box::new(()))
+            }),
+            Noop::noop(),
+        )
+    }
+
+    /// Rename the entry span by the `_route` attribute, and start the
+    /// controller span, after the controller resolved.
+    fn hook_controller_resolver_get_controller(
+        &self,
+    ) -> (Box<BeforeExecuteHook>, Box<AfterExecuteHook>) {
+        (
+            Box::new(|_, execute_data| {
+                validate_num_args(execute_data, 1)?;
+                Ok(Box::new(()))
+            }),
+            Box::new(|request_id, _, execute_data, return_value| {
+                let Some(is_main_request) = KERNEL_FRAMES
+                    .get(&request_id)
+                    .and_then(|frames| frames.last().map(|frame| frame.is_main_request))
+                else {
+                    return Ok(());
+                };
+
+                if is_main_request {
+                    let request = execute_data
+                        .get_mut_parameter(0)
+                        .as_mut_z_obj()
+                        .context("request isn't object")?;
+                    if let Some(route) = get_route(request)? {
+                        debug!(route, "symfony route matched");
+                        RequestContext::rename_by_route(request_id, &route)?;
+                    }
+                }
+
+                let Some(controller) = get_controller_name(return_value) else {
+                    return Ok(());
+                };
+                let mut span = RequestContext::try_create_span(request_id, |ctx| {
+                    Ok(ctx.create_local_span(&controller))
+                })?;
+                span.span_object_mut().component_id = COMPONENT_PHP_ID;
+
+                if let Some(mut frames) = KERNEL_FRAMES.get_mut(&request_id) {
+                    if let Some(frame) = frames.last_mut() {
+                        frame.controller_span = Some(span);
+                    }
+                }
+
+                Ok(())
+            }),
+        )
+    }
+}
+
+/// Finish the controller spans of the kernel handling which never returned,
+/// such as bailed out by the fatal error, must be called before the request
+/// context finished.
+pub(super) fn clear_kernel_frames(request_id: Option<i64>) {
+    if let Some((_, mut frames)) = KERNEL_FRAMES.remove(&request_id) {
+        debug!(count = frames.len(), "symfony kernel frames are left");
+        // The nested spans are finished first.
+        while let Some(frame) = frames.pop() {
+            drop(frame);
+        }
+    }
+}
+
+fn pop_kernel_frame(request_id: Option<i64>) -> Option<KernelFrame> {
+    let frame = KERNEL_FRAMES.get_mut(&request_id)?.pop();
+    KERNEL_FRAMES.remove_if(&request_id, |_, frames| frames.is_empty());
+    frame
+}
+
+/// Get the `_route` attribute of the request.
+fn get_route(request: &mut ZObj) -> crate::Result<Option<String>> {
+    let route = request
+        .get_mut_property("attributes")
+        .as_mut_z_obj()
+        .context("request attributes isn't object")?
+        .call("get", [ZVal::from("_route")])?;
+    Ok(route
+        .as_z_str()
+        .and_then(|route| route.to_str().ok())
+        .map(ToOwned::to_owned))
+}
+
+/// The controller is a callable, such as `[$object, 'method']`,
+/// `'Class::method'`, the invokable object or closure.
+fn get_controller_name(controller: &ZVal) -> Option<String> {
+    if let Some(controller) = controller.as_z_str() {
+        return controller.to_str().ok().map(ToOwned::to_owned);
+    }
+
+    if let Some(controller) = controller.as_z_obj() {
+        let class_name = controller.get_class().get_name().to_str().ok()?;
+        return Some(if class_name == "Closure" {
+            class_name.to_owned()
+        } else {
+            format!("{}::__invoke", class_name)
+        });
+    }
+
+    let controller = controller.as_z_arr()?;
+    let class_name = controller.get(0u64)?;
+    let class_name = match class_name.as_z_obj() {
+        Some(object) => object.get_class().get_name().to_str().ok()?,
+        None => class_name.as_z_str()?.to_str().ok()?,
+    };
+    let method = controller.get(1u64)?.as_z_str()?.to_str().ok()?;
+    Some(format!("{}::{}", class_name, method))
+}
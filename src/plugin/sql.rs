@@ -0,0 +1,224 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sanitize the statements and format the bound parameters, for the database
+//! plugins.
+
+use crate::{
+    module::{OBFUSCATE_SQL, SQL_MAX_LENGTH, SQL_PARAMETERS_MAX_LENGTH},
+    util::{truncate_str, z_val_to_scalar_string},
+};
+use once_cell::sync::Lazy;
+use phper::{
+    arrays::{IterKey, ZArr},
+    values::ZVal,
+};
+use regex::Regex;
+use std::{collections::BTreeMap, iter::Peekable, str::Chars};
+
+static IN_LIST_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(IN)\s*\(\s*\?(?:\s*,\s*\?)*\s*\)").unwrap());
+
+/// Get the `db.statement` tag value, obfuscated and truncated by the ini
+/// settings.
+///
+/// The double quoted text is string in MySQL, but identifier in the others.
+pub fn sanitize_statement(statement: &str, is_mysql: bool) -> String {
+    let mut statement = if *OBFUSCATE_SQL {
+        obfuscate(statement, is_mysql)
+    } else {
+        statement.to_owned()
+    };
+    if *SQL_MAX_LENGTH > 0 {
+        truncate_str(&mut statement, *SQL_MAX_LENGTH as usize);
+    }
+    statement
+}
+
+/// Replace the string and number literals with `?`, then collapse the `IN`
+/// lists to `IN (?)`, the identifiers, placeholders and comments are kept.
+fn obfuscate(statement: &str, is_mysql: bool) -> String {
+    let mut result = String::with_capacity(statement.len());
+    let mut chars = statement.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                skip_quoted(&mut chars, c, is_mysql);
+                result.push('?');
+            }
+            '"' if is_mysql => {
+                skip_quoted(&mut chars, c, true);
+                result.push('?');
+            }
+            '"' | '`' => {
+                result.push(c);
+                copy_quoted(&mut chars, c, &mut result);
+            }
+            '-' if chars.peek() == Some(&'-') => copy_line_comment(&mut chars, c, &mut result),
+            '#' if is_mysql => copy_line_comment(&mut chars, c, &mut result),
+            '/' if chars.next_if_eq(&'*').is_some() => {
+                result.push_str("/*");
+                let mut last = ' ';
+                for c in chars.by_ref() {
+                    result.push(c);
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            c if c.is_ascii_digit() => {
+                // Also the hex `0x1F` and the exponent `1.5e10`.
+                while chars
+                    .next_if(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '_')
+                    .is_some()
+                {}
+                result.push('?');
+            }
+            c if c.is_alphabetic() || c == '_' || c == '$' => {
+                // The identifiers such as `t1` and the placeholders such as
+                // `$1`, which contain digits.
+                result.push(c);
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_' || *c == '$')
+                {
+                    result.push(c);
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+
+    IN_LIST_REGEX.replace_all(&result, "$1 (?)").into_owned()
+}
+
+fn skip_quoted(chars: &mut Peekable<Chars<'_>>, quote: char, backslash_escape: bool) {
+    while let Some(c) = chars.next() {
+        if backslash_escape && c == '\\' {
+            chars.next();
+        } else if c == quote && chars.next_if_eq(&quote).is_none() {
+            break;
+        }
+    }
+}
+
+fn copy_quoted(chars: &mut Peekable<Chars<'_>>, quote: char, result: &mut String) {
+    while let Some(c) = chars.next() {
+        result.push(c);
+        if c == quote {
+            match chars.next_if_eq(&quote) {
+                Some(c) => result.push(c),
+                None => break,
+            }
+        }
+    }
+}
+
+fn copy_line_comment(chars: &mut Peekable<Chars<'_>>, first: char, result: &mut String) {
+    result.push(first);
+    for c in chars.by_ref() {
+        result.push(c);
+        if c == '\n' {
+            break;
+        }
+    }
+}
+
+/// The key of the bound parameter, the 1-based (PDO) or 0-based (mysqli)
+/// position of `?`, or the name of `:name`.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum ParameterKey {
+    Index(i64),
+    Name(String),
+}
+
+impl ParameterKey {
+    pub fn from_z_val(zv: &ZVal) -> Option<Self> {
+        if let Some(index) = zv.as_long() {
+            Some(Self::Index(index))
+        } else {
+            zv.as_z_str()
+                .and_then(|name| name.to_str().ok())
+                .map(|name| Self::Name(name.to_owned()))
+        }
+    }
+}
+
+/// The parameters bound to a statement, the values are formatted when the
+/// statement is executed, since the variables are bound by reference by
+/// `PDOStatement::bindParam` and `mysqli_stmt::bind_param`.
+#[derive(Default)]
+pub struct BoundParameters(BTreeMap<ParameterKey, ZVal>);
+
+impl BoundParameters {
+    pub fn bind(&mut self, key: ParameterKey, value: ZVal) {
+        self.0.insert(key, value);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn format(&self) -> String {
+        format_parameters(self.0.iter().map(|(key, value)| {
+            let name = match key {
+                ParameterKey::Index(_) => None,
+                ParameterKey::Name(name) => Some(name.clone()),
+            };
+            (name, value)
+        }))
+    }
+}
+
+/// Format the list or the map passed to `execute`.
+pub fn format_array_parameters(parameters: &ZArr) -> String {
+    format_parameters(parameters.iter().map(|(key, value)| {
+        let name = match key {
+            IterKey::Index(_) => None,
+            IterKey::ZStr(name) => Some(name.to_str().unwrap_or_default().to_owned()),
+        };
+        (name, value)
+    }))
+}
+
+/// Format the parameters as the `db.sql.parameters` tag value, such as
+/// `[1,abc,:name=def]`, truncated by the ini settings.
+fn format_parameters<'a>(parameters: impl Iterator<Item = (Option<String>, &'a ZVal)>) -> String {
+    let mut result = parameters
+        .map(|(name, value)| match name {
+            Some(name) => format!("{}={}", name, format_value(value)),
+            None => format_value(value),
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    result.insert(0, '[');
+    result.push(']');
+
+    if *SQL_PARAMETERS_MAX_LENGTH > 0 {
+        truncate_str(&mut result, *SQL_PARAMETERS_MAX_LENGTH as usize);
+    }
+    result
+}
+
+fn format_value(value: &ZVal) -> String {
+    let value = value.as_z_ref().map(|r| r.val()).unwrap_or(value);
+    let type_info = value.get_type_info();
+    if type_info.is_null() {
+        "null".to_owned()
+    } else {
+        z_val_to_scalar_string(value)
+            .unwrap_or_else(|| format!("<{}>", type_info.get_base_type_name().to_string_lossy()))
+    }
+}
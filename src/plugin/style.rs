@@ -48,6 +48,14 @@ impl ApiStyle {
         execute_data.get_mut_parameter(index)
     }
 
+    /// The count of arguments, except the object of procedural style.
+    pub fn num_args(self, execute_data: &ExecuteData) -> usize {
+        match self {
+            ApiStyle::OO => execute_data.num_args(),
+            ApiStyle::Procedural => execute_data.num_args().saturating_sub(1),
+        }
+    }
+
     pub fn validate_num_args(
         self, execute_data: &mut ExecuteData, num: usize,
     ) -> anyhow::Result<()> {
@@ -16,20 +16,42 @@
 use crate::{
     component::COMPONENT_PHP_ID,
     context::RequestContext,
-    module::{INJECT_CONTEXT, SKYWALKING_VERSION, is_enable},
-    util::{catch_unwind_result, get_sapi_module_name, z_val_to_string},
+    dynamic_config,
+    endpoint::{self, Endpoint},
+    module::{
+        CAPTURE_DENY_LIST, CAPTURE_QUERY_PARAMS, CAPTURE_REQUEST_HEADERS, CAPTURE_RESPONSE_HEADERS,
+        ENABLE_TAIL_SAMPLING, INJECT_CONTEXT, MAX_SPANS_PER_SEGMENT, SKYWALKING_VERSION,
+        TRUSTED_PROXIES, is_cli_mode, is_enable,
+    },
+    plugin, runtime_metrics,
+    sampler::{self, SegmentBuffer},
+    tag::{
+        TAG_HTTP_CLIENT_IP, TAG_HTTP_PROTOCOL, TAG_HTTP_QUERY_PREFIX, TAG_HTTP_REQUEST_BODY_SIZE,
+        TAG_HTTP_REQUEST_HEADER_PREFIX, TAG_HTTP_RESPONSE_HEADER_PREFIX, TAG_HTTP_USER_AGENT,
+    },
+    util::{IpRange, catch_unwind_result, get_sapi_module_name, z_val_to_string},
 };
 use anyhow::{Context, anyhow};
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
-use phper::{arrays::ZArr, eg, pg, sg, sys, values::ZVal};
-use skywalking::trace::{propagation::decoder::decode_propagation, span::HandleSpanObject, tracer};
+use phper::{
+    arrays::{IterKey, ZArr},
+    eg,
+    functions::call,
+    pg, sg, sys,
+    values::ZVal,
+};
+use skywalking::{
+    proto::v3::SpanLayer,
+    trace::{propagation::decoder::decode_propagation, span::HandleSpanObject, tracer},
+};
 use std::{
+    net::IpAddr,
     panic::AssertUnwindSafe,
     ptr::null_mut,
     sync::atomic::{AtomicBool, AtomicPtr, Ordering},
 };
-use tracing::{error, instrument, trace, warn};
+use tracing::{debug, error, instrument, trace, warn};
 use url::Url;
 
 const INJECT_CONTEXT_SERVICE_NAME: &str = "SW_SERVICE_NAME";
@@ -45,6 +67,10 @@ pub fn init() {
         if let Err(err) = catch_unwind_result(request_init_for_fpm) {
             error!(mode = "fpm", ?err, "request init failed");
         }
+    } else if is_cli_mode() {
+        if let Err(err) = catch_unwind_result(request_init_for_cli) {
+            error!(mode = "cli", ?err, "request init failed");
+        }
     }
 }
 
@@ -53,15 +79,29 @@ pub fn shutdown() {
     if !is_enable() {
         return;
     }
+    plugin::request_shutdown();
+
     if get_sapi_module_name().to_bytes() == b"fpm-fcgi" {
         if let Err(err) = catch_unwind_result(request_shutdown_for_fpm) {
             error!(mode = "fpm", ?err, "request shutdown failed");
         }
+    } else if is_cli_mode() {
+        if let Err(err) = catch_unwind_result(request_shutdown_for_cli) {
+            error!(mode = "cli", ?err, "request shutdown failed");
+        }
+    } else {
+        return;
+    }
+
+    if let Err(err) = catch_unwind_result(runtime_metrics::report) {
+        error!(?err, "report runtime metrics failed");
     }
 }
 
 fn request_init_for_fpm() -> crate::Result<()> {
     jit_initialization();
+    // Before the ignore check, which reads the dynamic configurations.
+    dynamic_config::refresh();
 
     let server = get_page_request_server()?;
 
@@ -69,18 +109,88 @@ fn request_init_for_fpm() -> crate::Result<()> {
     let url = get_page_request_url(server)?;
     let method = get_page_request_method(server);
 
+    if endpoint::is_ignored_endpoint(&method, url.path()) {
+        debug!(
+            mode = "fpm",
+            method,
+            path = url.path(),
+            "request is ignored"
+        );
+        RequestContext::set_ignored(None);
+        return Ok(());
+    }
+
     create_request_context(None, header.as_deref(), &method, &url)?;
 
-    inject_server_var_for_fpm()
+    if let Err(err) = capture_request_tags(None, get_page_request_headers(server), &url) {
+        warn!(mode = "fpm", ?err, "capture request tags failed");
+    }
+
+    if let Err(err) = add_client_tags(None, get_page_client_info(server)) {
+        warn!(mode = "fpm", ?err, "add client tags failed");
+    }
+
+    inject_server_var_for_page()
 }
 
 fn request_shutdown_for_fpm() -> crate::Result<()> {
+    if RequestContext::remove_ignored(None) {
+        return Ok(());
+    }
+
     let status_code = unsafe { sg!(sapi_headers).http_response_code };
 
+    if !CAPTURE_RESPONSE_HEADERS.is_empty() {
+        if let Err(err) =
+            get_page_response_headers().and_then(|headers| capture_response_tags(None, headers))
+        {
+            warn!(mode = "fpm", ?err, "capture response tags failed");
+        }
+    }
+
     finish_request_context(None, status_code)
 }
 
-fn inject_server_var_for_fpm() -> crate::Result<()> {
+fn request_init_for_cli() -> crate::Result<()> {
+    jit_initialization();
+
+    let server = get_page_request_server()?;
+
+    let argv = get_cli_argv(server);
+    let operation_name = get_cli_operation_name(&argv);
+
+    create_cli_context(&operation_name)?;
+
+    RequestContext::try_with_global(None, |ctx| {
+        ctx.entry_span.add_tag("cli.argv", argv.join(" "));
+        Ok(())
+    })?;
+
+    inject_server_var_for_page()
+}
+
+fn request_shutdown_for_cli() -> crate::Result<()> {
+    // The context of script may be finished already, by the job boundary of long
+    // running worker.
+    plugin::finish_request(None);
+
+    let Some(mut ctx) = RequestContext::remove_global(None) else {
+        return Ok(());
+    };
+
+    let exit_status = unsafe { eg!(exit_status) };
+    ctx.entry_span
+        .add_tag("cli.exit_status", exit_status.to_string());
+    if exit_status != 0 {
+        ctx.entry_span.span_object_mut().is_error = true;
+    }
+
+    ctx.finish();
+
+    Ok(())
+}
+
+fn inject_server_var_for_page() -> crate::Result<()> {
     if *INJECT_CONTEXT {
         let server = get_mut_page_request_server()?;
         inject_server_var(None, server)?;
@@ -149,6 +259,79 @@ fn get_page_request_method(server: &ZArr) -> String {
         .unwrap_or_else(|| "UNKNOWN".to_string())
 }
 
+fn get_cli_argv(server: &ZArr) -> Vec<String> {
+    server
+        .get("argv")
+        .and_then(|argv| argv.as_z_arr())
+        .map(|argv| {
+            argv.iter()
+                .filter_map(|(_, arg)| z_val_to_string(arg))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Use the script path and the first argument (usually the sub command, such as
+/// `artisan queue:work`) as the operation name, the rest arguments may contain
+/// the variables, which will explode the endpoints.
+fn get_cli_operation_name(argv: &[String]) -> String {
+    let mut argv = argv.iter();
+    let mut operation_name = argv
+        .next()
+        .cloned()
+        .unwrap_or_else(|| "unknown".to_string());
+    if let Some(command) = argv.find(|arg| !arg.starts_with('-')) {
+        operation_name.push(' ');
+        operation_name.push_str(command);
+    }
+    operation_name
+}
+
+/// The request headers are the `HTTP_*` keys of `$_SERVER`, such as
+/// `HTTP_USER_AGENT`.
+fn get_page_request_headers(server: &ZArr) -> impl Iterator<Item = (String, String)> + '_ {
+    server.iter().filter_map(|(key, value)| {
+        let IterKey::ZStr(key) = key else {
+            return None;
+        };
+        let name = key.to_str().ok()?.strip_prefix("HTTP_")?;
+        Some((
+            name.replace('_', "-").to_lowercase(),
+            z_val_to_string(value)?,
+        ))
+    })
+}
+
+/// The client information of php-fpm, from `$_SERVER`.
+fn get_page_client_info(server: &ZArr) -> ClientInfo {
+    let get = |key: &str| server.get(key).and_then(z_val_to_string);
+    ClientInfo {
+        remote_addr: get("REMOTE_ADDR"),
+        forwarded_for: get("HTTP_X_FORWARDED_FOR"),
+        user_agent: get("HTTP_USER_AGENT"),
+        protocol: get("SERVER_PROTOCOL"),
+        body_size: get("CONTENT_LENGTH"),
+    }
+}
+
+/// Parse the result of `headers_list()`, such as `Content-Type: text/html`.
+fn get_page_response_headers() -> crate::Result<Vec<(String, String)>> {
+    let headers = call("headers_list", [])?;
+    Ok(headers
+        .as_z_arr()
+        .map(|headers| {
+            headers
+                .iter()
+                .filter_map(|(_, header)| {
+                    let header = z_val_to_string(header)?;
+                    let (name, value) = header.split_once(':')?;
+                    Some((name.trim().to_lowercase(), value.trim().to_owned()))
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
 fn get_page_request_server<'a>() -> anyhow::Result<&'a ZArr> {
     unsafe {
         let symbol_table = ZArr::from_mut_ptr(&raw mut eg!(symbol_table));
@@ -213,12 +396,18 @@ pub fn skywalking_hack_swoole_on_request(args: &mut [ZVal]) -> phper::Result<ZVa
         })) {
             error!(mode = "swoole", ?err, "request shutdown failed");
         }
+
+        if let Err(err) = catch_unwind_result(runtime_metrics::report) {
+            error!(mode = "swoole", ?err, "report runtime metrics failed");
+        }
     }
 
     return_value
 }
 
 fn request_init_for_swoole(request: &mut ZVal) -> crate::Result<()> {
+    dynamic_config::refresh();
+
     let request = request
         .as_mut_z_obj()
         .context("swoole request isn't object")?;
@@ -243,8 +432,27 @@ fn request_init_for_swoole(request: &mut ZVal) -> crate::Result<()> {
     let method = get_swoole_request_method(server);
     let url = get_swoole_request_url(server, headers)?;
 
+    if endpoint::is_ignored_endpoint(&method, url.path()) {
+        debug!(
+            mode = "swoole",
+            method,
+            path = url.path(),
+            "request is ignored"
+        );
+        RequestContext::set_ignored(Some(fd));
+        return Ok(());
+    }
+
     create_request_context(Some(fd), header.as_deref(), &method, &url)?;
 
+    if let Err(err) = capture_request_tags(Some(fd), get_swoole_headers(headers), &url) {
+        warn!(mode = "swoole", ?err, "capture request tags failed");
+    }
+
+    if let Err(err) = add_client_tags(Some(fd), get_swoole_client_info(server, headers)) {
+        warn!(mode = "swoole", ?err, "add client tags failed");
+    }
+
     let server = request
         .get_mut_property("server")
         .as_mut_z_arr()
@@ -263,6 +471,17 @@ fn request_shutdown_for_swoole(response: &mut ZVal) -> crate::Result<()> {
         .as_long()
         .context("swoole request fd not exists")?;
 
+    if RequestContext::remove_ignored(Some(fd)) {
+        SWOOLE_RESPONSE_STATUS_MAP.remove(&fd);
+        return Ok(());
+    }
+
+    if let Some(headers) = response.get_property("header").as_z_arr() {
+        if let Err(err) = capture_response_tags(Some(fd), get_swoole_headers(headers)) {
+            warn!(mode = "swoole", ?err, "capture response tags failed");
+        }
+    }
+
     finish_request_context(
         Some(fd),
         SWOOLE_RESPONSE_STATUS_MAP
@@ -292,6 +511,27 @@ fn get_swoole_request_header(header: &ZArr) -> Option<String> {
     }
 }
 
+fn get_swoole_headers(headers: &ZArr) -> impl Iterator<Item = (String, String)> + '_ {
+    headers.iter().filter_map(|(key, value)| {
+        let IterKey::ZStr(key) = key else {
+            return None;
+        };
+        Some((key.to_str().ok()?.to_lowercase(), z_val_to_string(value)?))
+    })
+}
+
+fn get_swoole_client_info(server: &ZArr, headers: &ZArr) -> ClientInfo {
+    let get_server = |key: &str| server.get(key).and_then(z_val_to_string);
+    let get_header = |key: &str| headers.get(key).and_then(z_val_to_string);
+    ClientInfo {
+        remote_addr: get_server("remote_addr"),
+        forwarded_for: get_header("x-forwarded-for"),
+        user_agent: get_header("user-agent"),
+        protocol: get_server("server_protocol"),
+        body_size: get_header("content-length"),
+    }
+}
+
 fn get_swoole_request_url(server: &ZArr, headers: &ZArr) -> crate::Result<Url> {
     let addr = headers
         .get("host")
@@ -328,6 +568,39 @@ fn get_swoole_request_method(server: &ZArr) -> String {
 fn create_request_context(
     request_id: Option<i64>, header: Option<&str>, method: &str, url: &Url,
 ) -> crate::Result<()> {
+    let endpoint = Endpoint::new(method, endpoint::normalize_path(url.path()));
+    let mut ctx = new_request_context(header, &endpoint.name())?;
+    ctx.endpoint = Some(endpoint);
+
+    let span_object = ctx.entry_span.span_object_mut();
+    span_object.component_id = COMPONENT_PHP_ID;
+    span_object.add_tag("url", url.to_string());
+    span_object.add_tag("http.method", method);
+
+    RequestContext::set_global(request_id, ctx);
+
+    Ok(())
+}
+
+/// Create the context of cli script or job, which hasn't request id.
+pub fn create_cli_context(operation_name: &str) -> crate::Result<()> {
+    // The cli script and every job of the long running worker.
+    dynamic_config::refresh();
+
+    let mut ctx = new_request_context(None, operation_name)?;
+
+    let span_object = ctx.entry_span.span_object_mut();
+    span_object.set_span_layer(SpanLayer::Unknown);
+    span_object.component_id = COMPONENT_PHP_ID;
+
+    RequestContext::set_global(None, ctx);
+
+    Ok(())
+}
+
+fn new_request_context(
+    header: Option<&str>, operation_name: &str,
+) -> crate::Result<RequestContext> {
     let propagation = header
         .map(decode_propagation)
         .transpose()
@@ -335,43 +608,184 @@ fn create_request_context(
 
     trace!("Propagation: {:?}", &propagation);
 
-    let mut ctx = tracer::create_trace_context();
+    let is_sampled = sampler::sample(propagation.is_some());
+    let segment_buffer = (is_sampled && *ENABLE_TAIL_SAMPLING).then(SegmentBuffer::new);
+    let mut ctx = match &segment_buffer {
+        Some(segment_buffer) => segment_buffer.create_trace_context(),
+        None if is_sampled => tracer::create_trace_context(),
+        None => sampler::create_unsampled_trace_context(),
+    };
 
-    let operation_name = format!("{}:{}", method, url.path());
-    let mut span = match propagation {
-        Some(propagation) => ctx.create_entry_span_with_propagation(&operation_name, &propagation),
-        None => ctx.create_entry_span(&operation_name),
+    let span = match propagation {
+        Some(propagation) => ctx.create_entry_span_with_propagation(operation_name, &propagation),
+        None => ctx.create_entry_span(operation_name),
     };
 
-    let span_object = span.span_object_mut();
-    span_object.component_id = COMPONENT_PHP_ID;
-    span_object.add_tag("url", url.to_string());
-    span_object.add_tag("http.method", method);
+    Ok(RequestContext {
+        tracing_context: ctx,
+        entry_span: span,
+        is_sampled,
+        segment_buffer,
+        local_spans: Vec::new(),
+        span_count: 1,
+        max_span_count: dynamic_config::current()
+            .span_limit_per_segment
+            .unwrap_or(*MAX_SPANS_PER_SEGMENT)
+            .max(0) as usize,
+        suppressed_span_count: 0,
+        endpoint: None,
+    })
+}
+
+/// Record the request headers and query params in the capture lists as the
+/// entry span tags.
+fn capture_request_tags(
+    request_id: Option<i64>, headers: impl Iterator<Item = (String, String)>, url: &Url,
+) -> crate::Result<()> {
+    let mut tags = Vec::new();
+
+    if !CAPTURE_REQUEST_HEADERS.is_empty() {
+        tags.extend(
+            headers
+                .filter(|(name, _)| is_captured(&CAPTURE_REQUEST_HEADERS, name))
+                .map(|(name, value)| {
+                    (format!("{}{}", TAG_HTTP_REQUEST_HEADER_PREFIX, name), value)
+                }),
+        );
+    }
 
-    RequestContext::set_global(
-        request_id,
-        RequestContext {
-            tracing_context: ctx,
-            entry_span: span,
-        },
-    );
+    if !CAPTURE_QUERY_PARAMS.is_empty() {
+        tags.extend(
+            url.query_pairs()
+                .filter(|(name, _)| is_captured(&CAPTURE_QUERY_PARAMS, &name.to_lowercase()))
+                .map(|(name, value)| {
+                    (
+                        format!("{}{}", TAG_HTTP_QUERY_PREFIX, name),
+                        value.into_owned(),
+                    )
+                }),
+        );
+    }
 
-    Ok(())
+    add_entry_span_tags(request_id, tags)
+}
+
+/// Record the response headers in the capture list as the entry span tags.
+fn capture_response_tags(
+    request_id: Option<i64>, headers: impl IntoIterator<Item = (String, String)>,
+) -> crate::Result<()> {
+    if CAPTURE_RESPONSE_HEADERS.is_empty() {
+        return Ok(());
+    }
+
+    let tags = headers
+        .into_iter()
+        .filter(|(name, _)| is_captured(&CAPTURE_RESPONSE_HEADERS, name))
+        .map(|(name, value)| {
+            (
+                format!("{}{}", TAG_HTTP_RESPONSE_HEADER_PREFIX, name),
+                value,
+            )
+        })
+        .collect();
+
+    add_entry_span_tags(request_id, tags)
+}
+
+/// The name should be lowercase.
+fn is_captured(capture_list: &[String], name: &str) -> bool {
+    !CAPTURE_DENY_LIST.iter().any(|deny| deny == name)
+        && capture_list.iter().any(|item| item == "*" || item == name)
+}
+
+/// The client information collected from the different SAPI.
+struct ClientInfo {
+    remote_addr: Option<String>,
+    forwarded_for: Option<String>,
+    user_agent: Option<String>,
+    protocol: Option<String>,
+    body_size: Option<String>,
+}
+
+fn add_client_tags(request_id: Option<i64>, info: ClientInfo) -> crate::Result<()> {
+    let mut tags = Vec::new();
+
+    if let Some(client_ip) = info.remote_addr.as_deref().and_then(|remote_addr| {
+        resolve_client_ip(remote_addr, info.forwarded_for.as_deref(), &TRUSTED_PROXIES)
+    }) {
+        tags.push((TAG_HTTP_CLIENT_IP.to_owned(), client_ip));
+    }
+    if let Some(user_agent) = info.user_agent.filter(|s| !s.is_empty()) {
+        tags.push((TAG_HTTP_USER_AGENT.to_owned(), user_agent));
+    }
+    if let Some(protocol) = info.protocol.filter(|s| !s.is_empty()) {
+        tags.push((TAG_HTTP_PROTOCOL.to_owned(), protocol));
+    }
+    if let Some(body_size) = info.body_size.filter(|s| s.parse::<u64>().is_ok()) {
+        tags.push((TAG_HTTP_REQUEST_BODY_SIZE.to_owned(), body_size));
+    }
+
+    add_entry_span_tags(request_id, tags)
+}
+
+/// The `X-Forwarded-For` is only honored when the remote address is a trusted
+/// proxy, then the addresses are walked from right to left, the first one
+/// isn't a trusted proxy is the client.
+fn resolve_client_ip(
+    remote_addr: &str, forwarded_for: Option<&str>, trusted_proxies: &[IpRange],
+) -> Option<String> {
+    let remote_addr = remote_addr.trim();
+    let is_trusted = |addr: &IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(addr));
+
+    let Ok(remote_ip) = remote_addr.parse::<IpAddr>() else {
+        return (!remote_addr.is_empty()).then(|| remote_addr.to_owned());
+    };
+    if !is_trusted(&remote_ip) {
+        return Some(remote_ip.to_string());
+    }
+
+    let Some(forwarded_for) = forwarded_for else {
+        return Some(remote_ip.to_string());
+    };
+    let mut client_ip = remote_ip;
+    for addr in forwarded_for.rsplit(',') {
+        let Ok(addr) = addr.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client_ip = addr;
+        if !is_trusted(&addr) {
+            break;
+        }
+    }
+    Some(client_ip.to_string())
+}
+
+fn add_entry_span_tags(request_id: Option<i64>, tags: Vec<(String, String)>) -> crate::Result<()> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+
+    Ok(RequestContext::try_with_global(request_id, |ctx| {
+        for (key, value) in tags {
+            ctx.entry_span.add_tag(key, value);
+        }
+        Ok(())
+    })?)
 }
 
 fn finish_request_context(request_id: Option<i64>, status_code: i32) -> crate::Result<()> {
-    let RequestContext {
-        tracing_context,
-        mut entry_span,
-    } = RequestContext::remove_global(request_id).context("request context not exists")?;
+    plugin::finish_request(request_id);
 
-    entry_span.add_tag("http.status_code", status_code.to_string());
+    let mut ctx =
+        RequestContext::remove_global(request_id).context("request context not exists")?;
+
+    ctx.entry_span
+        .add_tag("http.status_code", status_code.to_string());
     if status_code >= 400 {
-        entry_span.span_object_mut().is_error = true;
+        ctx.entry_span.span_object_mut().is_error = true;
     }
 
-    drop(entry_span);
-    drop(tracing_context);
+    ctx.finish();
 
     Ok(())
 }
@@ -384,3 +798,47 @@ fn inject_server_var(request_id: Option<i64>, server: &mut ZArr) -> crate::Resul
         Ok(())
     })?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(remote_addr: &str, forwarded_for: Option<&str>) -> Option<String> {
+        let trusted_proxies = ["10.0.0.0/8", "::1"]
+            .into_iter()
+            .filter_map(IpRange::parse)
+            .collect::<Vec<_>>();
+        resolve_client_ip(remote_addr, forwarded_for, &trusted_proxies)
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        assert_eq!(resolve("", None), None);
+        assert_eq!(resolve("unix:", None).as_deref(), Some("unix:"));
+        assert_eq!(resolve(" 1.2.3.4 ", None).as_deref(), Some("1.2.3.4"));
+
+        // The untrusted remote address can't forge the client.
+        assert_eq!(
+            resolve("1.2.3.4", Some("5.6.7.8")).as_deref(),
+            Some("1.2.3.4")
+        );
+
+        assert_eq!(resolve("10.0.0.1", None).as_deref(), Some("10.0.0.1"));
+        assert_eq!(
+            resolve("10.0.0.1", Some("5.6.7.8")).as_deref(),
+            Some("5.6.7.8")
+        );
+        assert_eq!(
+            resolve("10.0.0.1", Some("9.9.9.9, 5.6.7.8, 10.0.0.2")).as_deref(),
+            Some("5.6.7.8")
+        );
+        assert_eq!(
+            resolve("::1", Some("10.0.0.3,10.0.0.2")).as_deref(),
+            Some("10.0.0.3")
+        );
+        assert_eq!(
+            resolve("10.0.0.1", Some("5.6.7.8, invalid, 10.0.0.2")).as_deref(),
+            Some("10.0.0.2")
+        );
+    }
+}
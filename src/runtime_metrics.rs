@@ -0,0 +1,173 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Collect the runtime metrics at request shutdown, which are sent to the
+//! worker as a single batch and aggregated by the worker.
+
+use crate::module::{REPORTER, RUNTIME_METRICS_PERIOD, SERVICE_INSTANCE, SERVICE_NAME};
+use phper::{arrays::ZArr, functions::call, values::ZVal};
+use skywalking::reporter::{CollectItem, Report};
+use skywalking_php_worker::runtime_metrics::{
+    RUNTIME_METER_GC_COLLECTED, RUNTIME_METER_GC_RUNS, RUNTIME_METER_INCLUDED_FILES,
+    RUNTIME_METER_MEMORY_PEAK_USAGE, RUNTIME_METER_OPCACHE_FREE_MEMORY,
+    RUNTIME_METER_OPCACHE_HIT_RATE, RUNTIME_METER_OPCACHE_USED_MEMORY, RUNTIME_METER_REQUESTS,
+    batch_runtime_metrics,
+};
+use std::{
+    sync::atomic::{AtomicI64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::debug;
+
+/// The `gc_status()` values are accumulated in the process, so record the
+/// values of the last request to calculate the delta.
+static LAST_GC_RUNS: AtomicI64 = AtomicI64::new(0);
+static LAST_GC_COLLECTED: AtomicI64 = AtomicI64::new(0);
+
+/// The min milliseconds between the samples of the included files and the
+/// opcache status in the process, which are too costly to collect at every
+/// request.
+const SAMPLE_INTERVAL_MILLIS: i64 = 1000;
+
+/// The timestamp in milliseconds of the last sample.
+static LAST_SAMPLE_TIME: AtomicI64 = AtomicI64::new(i64::MIN);
+
+pub fn is_enable() -> bool {
+    *RUNTIME_METRICS_PERIOD > 0
+}
+
+pub fn report() -> crate::Result<()> {
+    if !is_enable() {
+        return Ok(());
+    }
+
+    let mut metrics = vec![(RUNTIME_METER_REQUESTS, 1.)];
+
+    if let Some(peak) = call("memory_get_peak_usage", [ZVal::from(true)])?.as_long() {
+        metrics.push((RUNTIME_METER_MEMORY_PEAK_USAGE, peak as f64));
+    }
+
+    // The `gc_status()` is available since php 7.3.
+    if let Ok(status) = call("gc_status", []) {
+        if let Some(status) = status.as_z_arr() {
+            if let Some(runs) = status.get("runs").and_then(ZVal::as_long) {
+                let last = LAST_GC_RUNS.swap(runs, Ordering::Relaxed);
+                metrics.push((RUNTIME_METER_GC_RUNS, (runs - last).max(0) as f64));
+            }
+            if let Some(collected) = status.get("collected").and_then(ZVal::as_long) {
+                let last = LAST_GC_COLLECTED.swap(collected, Ordering::Relaxed);
+                metrics.push((RUNTIME_METER_GC_COLLECTED, (collected - last).max(0) as f64));
+            }
+        }
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_millis() as i64)
+        .unwrap_or_default();
+
+    if try_acquire_sample(&LAST_SAMPLE_TIME, timestamp) {
+        let mut files = call("get_included_files", [])?;
+        if let Some(files) = files.as_mut_z_arr() {
+            metrics.push((RUNTIME_METER_INCLUDED_FILES, files.len() as f64));
+        }
+
+        collect_opcache_metrics(&mut metrics)?;
+    }
+
+    debug!(?metrics, "Report runtime metrics");
+
+    REPORTER.report(CollectItem::Meter(Box::new(batch_runtime_metrics(
+        SERVICE_NAME.clone(),
+        SERVICE_INSTANCE.clone(),
+        timestamp,
+        &metrics,
+    ))));
+
+    Ok(())
+}
+
+/// Whether the sample interval has elapsed since the last sample, the winner of
+/// the concurrent threads takes the sample.
+fn try_acquire_sample(last_sample_time: &AtomicI64, now: i64) -> bool {
+    let last = last_sample_time.load(Ordering::Relaxed);
+    now.saturating_sub(last) >= SAMPLE_INTERVAL_MILLIS
+        && last_sample_time
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+}
+
+/// The `opcache_get_status()` returns false when the opcache is disabled.
+fn collect_opcache_metrics(metrics: &mut Vec<(&'static str, f64)>) -> crate::Result<()> {
+    let exists = call("function_exists", [ZVal::from("opcache_get_status")])?;
+    if exists.as_bool() != Some(true) {
+        return Ok(());
+    }
+
+    let status = call("opcache_get_status", [ZVal::from(false)])?;
+    let Some(status) = status.as_z_arr() else {
+        return Ok(());
+    };
+
+    if let Some(memory_usage) = status.get("memory_usage").and_then(ZVal::as_z_arr) {
+        if let Some(used) = get_number(memory_usage, "used_memory") {
+            metrics.push((RUNTIME_METER_OPCACHE_USED_MEMORY, used));
+        }
+        if let Some(free) = get_number(memory_usage, "free_memory") {
+            metrics.push((RUNTIME_METER_OPCACHE_FREE_MEMORY, free));
+        }
+    }
+    if let Some(statistics) = status.get("opcache_statistics").and_then(ZVal::as_z_arr) {
+        if let Some(hit_rate) = get_number(statistics, "opcache_hit_rate") {
+            metrics.push((RUNTIME_METER_OPCACHE_HIT_RATE, hit_rate));
+        }
+    }
+
+    Ok(())
+}
+
+fn get_number(arr: &ZArr, key: &str) -> Option<f64> {
+    let value = arr.get(key)?;
+    value
+        .as_long()
+        .map(|value| value as f64)
+        .or_else(|| value.as_double())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_acquire_sample() {
+        let last_sample_time = AtomicI64::new(i64::MIN);
+        assert!(try_acquire_sample(&last_sample_time, 0));
+        assert!(!try_acquire_sample(&last_sample_time, 0));
+        assert!(!try_acquire_sample(
+            &last_sample_time,
+            SAMPLE_INTERVAL_MILLIS - 1
+        ));
+        assert!(try_acquire_sample(
+            &last_sample_time,
+            SAMPLE_INTERVAL_MILLIS
+        ));
+        assert!(!try_acquire_sample(
+            &last_sample_time,
+            SAMPLE_INTERVAL_MILLIS + 1
+        ));
+        // The clock going backward doesn't sample.
+        assert!(!try_acquire_sample(&last_sample_time, 0));
+    }
+}
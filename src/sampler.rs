@@ -0,0 +1,432 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    dynamic_config,
+    module::{
+        REPORTER, SAMPLE_FORCE_WITH_PROPAGATION, SAMPLE_N_PER_PERIOD, SAMPLE_PERIOD, SAMPLE_RATE,
+        SERVICE_INSTANCE, SERVICE_NAME, TAIL_SAMPLING_KEEP_ERROR, TAIL_SAMPLING_SLOW_THRESHOLD,
+        TAIL_SAMPLING_STATUS_CODES,
+    },
+};
+use once_cell::sync::Lazy;
+use skywalking::{
+    proto::v3::SegmentObject,
+    reporter::{CollectItem, Report},
+    trace::{trace_context::TracingContext, tracer::Tracer},
+};
+use std::{
+    cell::Cell,
+    mem::{size_of, take},
+    ops::RangeInclusive,
+    process,
+    ptr::null_mut,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+use tracing::{debug, warn};
+
+/// The sampling window, shared by all the processes forked from the process
+/// which calls [init], such as the PHP-FPM pool processes and the swoole
+/// worker processes, so that `sample_n_per_period` limits the whole
+/// instance rather than a single process.
+static SAMPLE_WINDOW: Lazy<&'static SampleWindow> = Lazy::new(SampleWindow::new_shared);
+
+/// The tracer of unsampled requests, the segments of it are discarded.
+static UNSAMPLED_TRACER: Lazy<Tracer> =
+    Lazy::new(|| Tracer::new(&*SERVICE_NAME, &*SERVICE_INSTANCE, ()));
+
+thread_local! {
+    static RANDOM_SEED: Cell<u64> = Cell::new(random_seed());
+}
+
+/// Must be called before forking.
+pub fn init() {
+    Lazy::force(&SAMPLE_WINDOW);
+    Lazy::force(&UNSAMPLED_TRACER);
+}
+
+/// Decide whether the request should be sampled, the dynamic configurations
+/// take precedence over the ini settings.
+pub fn sample(has_propagation: bool) -> bool {
+    let dynamic_config = dynamic_config::current();
+    if dynamic_config.enable_tracing == Some(false) {
+        debug!("request isn't sampled as tracing is disabled");
+        return false;
+    }
+
+    let sample_n_per_period = dynamic_config
+        .sample_n_per_period(*SAMPLE_PERIOD)
+        .unwrap_or(*SAMPLE_N_PER_PERIOD);
+
+    if has_propagation && *SAMPLE_FORCE_WITH_PROPAGATION {
+        SAMPLE_WINDOW.acquire(sample_n_per_period);
+        return true;
+    }
+
+    let sample_rate = dynamic_config.sample_rate.unwrap_or(*SAMPLE_RATE);
+    if sample_rate < 1.0 && random_f64() >= sample_rate {
+        debug!(sample_rate, "request isn't sampled by sample rate");
+        return false;
+    }
+
+    if SAMPLE_WINDOW.acquire(sample_n_per_period) {
+        true
+    } else {
+        debug!(sample_n_per_period, "request isn't sampled by sample limit");
+        false
+    }
+}
+
+/// Create the tracing context for unsampled request, which can still be used
+/// to propagate the context to downstream, but will never be reported.
+pub fn create_unsampled_trace_context() -> TracingContext {
+    UNSAMPLED_TRACER.create_trace_context()
+}
+
+/// Buffer the segment of the sampled request until the request finished, then
+/// decide whether to report it by the tail sampling rules.
+pub struct SegmentBuffer {
+    tracer: Tracer,
+    reporter: BufferReporter,
+}
+
+impl SegmentBuffer {
+    pub fn new() -> Self {
+        let reporter = BufferReporter::default();
+        Self {
+            tracer: Tracer::new(&*SERVICE_NAME, &*SERVICE_INSTANCE, reporter.clone()),
+            reporter,
+        }
+    }
+
+    pub fn create_trace_context(&self) -> TracingContext {
+        self.tracer.create_trace_context()
+    }
+
+    /// Report the buffered segments which match the tail sampling rules, should
+    /// be called after the tracing context dropped.
+    pub fn flush(self) {
+        let items = match self.reporter.0.lock() {
+            Ok(mut items) => take(&mut *items),
+            Err(err) => {
+                warn!(?err, "get segment buffer lock failed");
+                return;
+            }
+        };
+
+        let rules = TailSamplingRules {
+            slow_threshold: *TAIL_SAMPLING_SLOW_THRESHOLD,
+            keep_error: *TAIL_SAMPLING_KEEP_ERROR,
+            status_codes: &TAIL_SAMPLING_STATUS_CODES,
+        };
+        for item in items {
+            if let CollectItem::Trace(segment) = &item {
+                if !is_kept(segment, &rules) {
+                    debug!(
+                        trace_id = &*segment.trace_id,
+                        "segment isn't kept by tail sampling"
+                    );
+                    continue;
+                }
+            }
+            REPORTER.report(item);
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+struct BufferReporter(Arc<Mutex<Vec<CollectItem>>>);
+
+impl Report for BufferReporter {
+    fn report(&self, item: CollectItem) {
+        match self.0.lock() {
+            Ok(mut items) => items.push(item),
+            Err(err) => warn!(?err, "get segment buffer lock failed"),
+        }
+    }
+}
+
+/// The tail sampling rules of the ini settings.
+struct TailSamplingRules<'a> {
+    /// The milliseconds, zero means disabled.
+    slow_threshold: i64,
+    keep_error: bool,
+    status_codes: &'a [RangeInclusive<i32>],
+}
+
+/// Keep the segment whose entry span is slow, or has error span, or whose
+/// status code matches.
+fn is_kept(segment: &SegmentObject, rules: &TailSamplingRules<'_>) -> bool {
+    let Some(entry_span) = segment.spans.iter().find(|span| span.parent_span_id < 0) else {
+        return true;
+    };
+
+    let slow_threshold = rules.slow_threshold;
+    if slow_threshold > 0 && entry_span.end_time - entry_span.start_time >= slow_threshold {
+        return true;
+    }
+
+    if rules.keep_error && segment.spans.iter().any(|span| span.is_error) {
+        return true;
+    }
+
+    entry_span
+        .tags
+        .iter()
+        .filter(|tag| tag.key == "http.status_code")
+        .filter_map(|tag| tag.value.parse::<i32>().ok())
+        .any(|status_code| {
+            rules
+                .status_codes
+                .iter()
+                .any(|codes| codes.contains(&status_code))
+        })
+}
+
+/// Parse the status codes like `429,500-599`.
+pub fn parse_status_codes(s: &str) -> Vec<RangeInclusive<i32>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| {
+            let codes = match item.split_once('-') {
+                Some((start, end)) => start
+                    .trim()
+                    .parse()
+                    .ok()
+                    .zip(end.trim().parse().ok())
+                    .map(|(start, end)| start..=end),
+                None => item.parse().ok().map(|code| code..=code),
+            };
+            if codes.is_none() {
+                warn!(item, "invalid status codes");
+            }
+            codes
+        })
+        .collect()
+}
+
+/// The period in the high 32 bits and the count of the period in the low 32
+/// bits, so they are updated together by a single CAS.
+#[repr(C)]
+struct SampleWindow {
+    state: AtomicU64,
+}
+
+impl SampleWindow {
+    fn new_shared() -> &'static Self {
+        let ptr = unsafe {
+            libc::mmap(
+                null_mut(),
+                size_of::<Self>(),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            warn!("map shared sample window failed, fallback to process local");
+            return Box::leak(Box::new(Self {
+                state: AtomicU64::new(0),
+            }));
+        }
+        // The anonymous mapping is zero filled, which is a valid window.
+        unsafe { &*ptr.cast::<Self>() }
+    }
+
+    /// Take a place in current period, return false if the places of current
+    /// period are used up.
+    fn acquire(&self, limit: i64) -> bool {
+        if limit < 0 {
+            return true;
+        }
+        // The count of periods since epoch fits in u32 even for one second period.
+        let period = current_timestamp() / (*SAMPLE_PERIOD).max(1) as u64;
+        self.acquire_in_period(period as u32, limit)
+    }
+
+    fn acquire_in_period(&self, period: u32, limit: i64) -> bool {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            let count = if (state >> 32) as u32 == period {
+                state as u32
+            } else {
+                0
+            };
+            if i64::from(count) >= limit {
+                return false;
+            }
+            let new_state = (u64::from(period) << 32) | u64::from(count.saturating_add(1));
+            match self.state.compare_exchange_weak(
+                state,
+                new_state,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(current) => state = current,
+            }
+        }
+    }
+}
+
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|dur| dur.as_secs())
+        .unwrap_or_default()
+}
+
+fn random_seed() -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|dur| dur.as_nanos() as u64)
+        .unwrap_or_default();
+    (nanos ^ ((process::id() as u64) << 32)) | 1
+}
+
+/// Xorshift, good enough for sampling.
+fn random_f64() -> f64 {
+    RANDOM_SEED.with(|seed| {
+        let mut x = seed.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        seed.set(x);
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skywalking::proto::v3::{KeyStringValuePair, SpanObject};
+    use std::thread;
+
+    #[test]
+    fn test_sample_window() {
+        let window = SampleWindow {
+            state: AtomicU64::new(0),
+        };
+        assert!(window.acquire_in_period(1, 2));
+        assert!(window.acquire_in_period(1, 2));
+        assert!(!window.acquire_in_period(1, 2));
+        assert!(!window.acquire_in_period(1, 2));
+
+        // The count is reset in the new period.
+        assert!(window.acquire_in_period(2, 2));
+        assert!(window.acquire_in_period(2, 2));
+        assert!(!window.acquire_in_period(2, 2));
+
+        assert!(!window.acquire_in_period(3, 0));
+    }
+
+    #[test]
+    fn test_shared_sample_window_concurrently() {
+        let window = SampleWindow::new_shared();
+        let acquired = thread::scope(|scope| {
+            let handles = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..1000)
+                            .filter(|_| window.acquire_in_period(1, 100))
+                            .count()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .sum::<usize>()
+        });
+        assert_eq!(acquired, 100);
+    }
+
+    #[test]
+    fn test_random_f64() {
+        // The xorshift state must never be zero.
+        assert_eq!(random_seed() & 1, 1);
+
+        let values = (0..10000).map(|_| random_f64()).collect::<Vec<_>>();
+        assert!(values.iter().all(|value| (0.0..1.0).contains(value)));
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        assert!((0.45..0.55).contains(&mean), "mean: {}", mean);
+        let below_tenth = values.iter().filter(|value| **value < 0.1).count();
+        assert!((800..1200).contains(&below_tenth), "below: {}", below_tenth);
+    }
+
+    #[test]
+    fn test_parse_status_codes() {
+        assert_eq!(parse_status_codes("429, 500-599,,"), [429..=429, 500..=599]);
+        assert_eq!(parse_status_codes(" 400 - 404 ,x,5xx,1-"), [400..=404]);
+        assert!(parse_status_codes("").is_empty());
+    }
+
+    fn segment(duration: i64, status_code: &str, is_error: bool) -> SegmentObject {
+        SegmentObject {
+            spans: vec![
+                SpanObject {
+                    span_id: 1,
+                    parent_span_id: 0,
+                    is_error,
+                    ..Default::default()
+                },
+                SpanObject {
+                    span_id: 0,
+                    parent_span_id: -1,
+                    start_time: 1000,
+                    end_time: 1000 + duration,
+                    tags: vec![KeyStringValuePair {
+                        key: "http.status_code".to_owned(),
+                        value: status_code.to_owned(),
+                    }],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_is_kept() {
+        let status_codes = parse_status_codes("429,500-599");
+        let rules = TailSamplingRules {
+            slow_threshold: 100,
+            keep_error: true,
+            status_codes: &status_codes,
+        };
+        assert!(!is_kept(&segment(99, "200", false), &rules));
+        assert!(is_kept(&segment(100, "200", false), &rules));
+        assert!(is_kept(&segment(10, "200", true), &rules));
+        assert!(is_kept(&segment(10, "503", false), &rules));
+        assert!(is_kept(&segment(10, "429", false), &rules));
+        assert!(!is_kept(&segment(10, "404", false), &rules));
+        // The segment without entry span, such as the cli job, is kept.
+        assert!(is_kept(&SegmentObject::default(), &rules));
+
+        let rules = TailSamplingRules {
+            slow_threshold: 0,
+            keep_error: false,
+            status_codes: &[],
+        };
+        assert!(!is_kept(&segment(10000, "200", false), &rules));
+        assert!(!is_kept(&segment(10, "500", true), &rules));
+    }
+}
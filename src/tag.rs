@@ -46,7 +46,22 @@ impl Display for CacheOp {
 
 pub const TAG_DB_STATEMENT: &str = "db.statement";
 pub const TAG_DB_TYPE: &str = "db.type";
+pub const TAG_DB_SQL_PARAMETERS: &str = "db.sql.parameters";
+pub const TAG_DB_AFFECTED_ROWS: &str = "db.affected_rows";
 
 pub const TAG_MQ_BROKER: &str = "mq.broker";
 pub const TAG_MQ_TOPIC: &str = "mq.topic";
 pub const TAG_MQ_QUEUE: &str = "mq.queue";
+
+pub const TAG_HTTP_REQUEST_HEADER_PREFIX: &str = "http.request.header.";
+pub const TAG_HTTP_RESPONSE_HEADER_PREFIX: &str = "http.response.header.";
+pub const TAG_HTTP_QUERY_PREFIX: &str = "http.query.";
+
+pub const TAG_HTTP_CLIENT_IP: &str = "http.client_ip";
+pub const TAG_HTTP_USER_AGENT: &str = "http.user_agent";
+pub const TAG_HTTP_PROTOCOL: &str = "http.protocol";
+pub const TAG_HTTP_REQUEST_BODY_SIZE: &str = "http.request.body_size";
+
+/// The count of plugin spans suppressed by `max_spans_per_segment`, tagged on
+/// the entry span.
+pub const TAG_SUPPRESSED_SPANS: &str = "span_limit.suppressed";
@@ -18,6 +18,7 @@ use once_cell::sync::Lazy;
 use phper::{ini::ini_get, sys, values::ZVal};
 use std::{
     ffi::CStr,
+    net::IpAddr as StdIpAddr,
     panic::{UnwindSafe, catch_unwind},
 };
 use systemstat::{IpAddr, Platform, System};
@@ -60,6 +61,38 @@ pub fn z_val_to_string(zv: &ZVal) -> Option<String> {
         .map(|s| s.to_string())
 }
 
+/// Format the scalar value as string, for the tags and logs.
+pub fn z_val_to_scalar_string(zv: &ZVal) -> Option<String> {
+    if let Some(s) = zv.as_z_str() {
+        s.to_str().ok().map(ToOwned::to_owned)
+    } else if let Some(l) = zv.as_long() {
+        Some(l.to_string())
+    } else if let Some(d) = zv.as_double() {
+        Some(d.to_string())
+    } else {
+        zv.as_bool().map(|b| b.to_string())
+    }
+}
+
+/// Truncate the string longer than `max_length` bytes at the char boundary,
+/// with `...` appended within `max_length`.
+pub fn truncate_str(s: &mut String, max_length: usize) {
+    const ELLIPSIS: &str = "...";
+
+    if s.len() <= max_length {
+        return;
+    }
+    let (mut index, ellipsis) = match max_length.checked_sub(ELLIPSIS.len()) {
+        Some(index) => (index, ELLIPSIS),
+        None => (max_length, ""),
+    };
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    s.truncate(index);
+    s.push_str(ellipsis);
+}
+
 pub fn catch_unwind_result<F: FnOnce() -> crate::Result<R> + UnwindSafe, R>(
     f: F,
 ) -> crate::Result<R> {
@@ -85,3 +118,112 @@ pub fn get_str_ini_with_default(name: &str) -> String {
         .map(ToOwned::to_owned)
         .unwrap_or_default()
 }
+
+/// Get the comma separated list ini, the empty items are skipped.
+pub fn get_str_list_ini(name: &str) -> Vec<String> {
+    get_str_ini_with_default(name)
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// The ip address or CIDR, such as `10.0.0.1` or `10.0.0.0/8`.
+pub struct IpRange {
+    addr: StdIpAddr,
+    prefix_len: u32,
+}
+
+impl IpRange {
+    pub fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, prefix_len)) => (
+                addr.trim().parse().ok()?,
+                Some(prefix_len.trim().parse().ok()?),
+            ),
+            None => (s.trim().parse().ok()?, None),
+        };
+        let max_prefix_len = match addr {
+            StdIpAddr::V4(_) => 32,
+            StdIpAddr::V6(_) => 128,
+        };
+        let prefix_len = prefix_len.unwrap_or(max_prefix_len);
+        if prefix_len > max_prefix_len {
+            return None;
+        }
+        Some(Self { addr, prefix_len })
+    }
+
+    pub fn contains(&self, addr: &StdIpAddr) -> bool {
+        match (self.addr, addr) {
+            (StdIpAddr::V4(net), StdIpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(net) & mask == u32::from(*addr) & mask
+            }
+            (StdIpAddr::V6(net), StdIpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(net) & mask == u128::from(*addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn truncated(s: &str, max_length: usize) -> String {
+        let mut s = s.to_owned();
+        truncate_str(&mut s, max_length);
+        s
+    }
+
+    #[test]
+    fn test_truncate_str() {
+        assert_eq!(truncated("hello", 5), "hello");
+        assert_eq!(truncated("hello", 10), "hello");
+        assert_eq!(truncated("hello world", 8), "hello...");
+        assert_eq!(truncated("hello", 4), "h...");
+        assert_eq!(truncated("hello", 3), "...");
+        assert_eq!(truncated("hello", 2), "he");
+        assert_eq!(truncated("hello", 0), "");
+    }
+
+    #[test]
+    fn test_truncate_str_at_char_boundary() {
+        // "中" takes 3 bytes.
+        assert_eq!(truncated("中文字符", 9), "中文...");
+        assert_eq!(truncated("中文字符", 8), "中...");
+        assert_eq!(truncated("中文字符", 5), "...");
+        assert_eq!(truncated("中文", 2), "");
+        for max_length in 0..16 {
+            assert!(truncated("a中文字符b", max_length).len() <= max_length);
+        }
+    }
+
+    #[test]
+    fn test_ip_range() {
+        let contains = |range: &str, addr: &str| {
+            IpRange::parse(range)
+                .unwrap()
+                .contains(&addr.parse().unwrap())
+        };
+
+        assert!(contains("10.0.0.1", "10.0.0.1"));
+        assert!(!contains("10.0.0.1", "10.0.0.2"));
+        assert!(contains(" 10.0.0.0 / 8 ", "10.255.0.1"));
+        assert!(!contains("10.0.0.0/8", "11.0.0.1"));
+        assert!(contains("0.0.0.0/0", "1.2.3.4"));
+        assert!(contains("fd00::/8", "fd12::1"));
+        assert!(!contains("fd00::/8", "fe80::1"));
+        assert!(contains("::/0", "::1"));
+        assert!(!contains("10.0.0.0/8", "::ffff:10.0.0.1"));
+
+        assert!(IpRange::parse("10.0.0.0/33").is_none());
+        assert!(IpRange::parse("::/129").is_none());
+        assert!(IpRange::parse("10.0.0.0/").is_none());
+        assert!(IpRange::parse("localhost").is_none());
+    }
+}
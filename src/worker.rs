@@ -13,21 +13,41 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::module::{
-    AUTHENTICATION, ENABLE_TLS, HEARTBEAT_PERIOD, PROPERTIES_REPORT_PERIOD_FACTOR, REPORTER_TYPE,
-    SERVER_ADDR, SERVICE_INSTANCE, SERVICE_NAME, SOCKET_FILE_PATH, SSL_CERT_CHAIN_PATH,
-    SSL_KEY_PATH, SSL_TRUSTED_CA_PATH, WORKER_THREADS, is_standalone_reporter_type,
-};
 #[cfg(feature = "kafka-reporter")]
 use crate::module::{KAFKA_BOOTSTRAP_SERVERS, KAFKA_PRODUCER_CONFIG};
+use crate::{
+    module::{
+        AUTHENTICATION, CONFIGURATION_DISCOVERY_PERIOD, CONFIGURATION_FILE_PATH, ENABLE_TLS,
+        FILE_REPORTER_MAX_FILES, FILE_REPORTER_MAX_SIZE, FILE_REPORTER_PATH,
+        FILE_REPORTER_ROTATE_INTERVAL, FPM_METRICS_PERIOD, FPM_STATUS_ADDRESS, FPM_STATUS_PATH,
+        HEARTBEAT_PERIOD, HTTP_ENABLE_GZIP, HTTP_MAX_RETRIES, HTTP_SERVER_ADDR, OTLP_ENDPOINT,
+        OTLP_HEADERS, PROPERTIES_REPORT_PERIOD_FACTOR, REPORTER_TYPE, RUNTIME_METRICS_PERIOD,
+        SERVER_ADDR, SERVICE_INSTANCE, SERVICE_NAME, SOCKET_FILE_PATH, SPOOL_DIR, SPOOL_ENABLE,
+        SPOOL_MAX_SIZE, SSL_CERT_CHAIN_PATH, SSL_KEY_PATH, SSL_TRUSTED_CA_PATH,
+        WORKER_BATCH_INTERVAL, WORKER_BATCH_SIZE, WORKER_QUEUE_CAPACITY,
+        WORKER_SELF_TELEMETRY_INTERVAL, WORKER_STATS_INTERVAL, WORKER_THREADS,
+        is_standalone_reporter_type,
+    },
+    util::get_sapi_module_name,
+};
 #[cfg(feature = "kafka-reporter")]
 use skywalking_php_worker::reporter::KafkaReporterConfiguration;
 use skywalking_php_worker::{
-    HeartBeatConfiguration, WorkerConfiguration, new_tokio_runtime,
-    reporter::{GrpcReporterConfiguration, ReporterConfiguration},
+    HeartBeatConfiguration, QueueConfiguration, WorkerConfiguration,
+    configuration_discovery::ConfigurationDiscoveryConfiguration,
+    fpm_metrics::FpmMetricsConfiguration,
+    new_tokio_runtime,
+    reporter::{
+        FileReporterConfiguration, GrpcReporterConfiguration, HttpReporterConfiguration,
+        OtlpReporterConfiguration, ReporterConfiguration,
+    },
+    spool::SpoolConfiguration,
     start_worker,
+    telemetry::SelfTelemetryConfiguration,
+};
+use std::{
+    cmp::Ordering, num::NonZeroUsize, process::exit, thread::available_parallelism, time::Duration,
 };
-use std::{cmp::Ordering, num::NonZeroUsize, process::exit, thread::available_parallelism};
 use tracing::error;
 
 pub fn init_worker() {
@@ -50,25 +70,18 @@ pub fn init_worker() {
                 #[cfg(target_os = "linux")]
                 libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM);
 
-                let reporter_config = match REPORTER_TYPE.as_str() {
-                    "grpc" => ReporterConfiguration::Grpc(GrpcReporterConfiguration {
-                        authentication: AUTHENTICATION.clone(),
-                        enable_tls: *ENABLE_TLS,
-                        server_addr: SERVER_ADDR.clone(),
-                        ssl_cert_chain_path: SSL_CERT_CHAIN_PATH.clone(),
-                        ssl_key_path: SSL_KEY_PATH.clone(),
-                        ssl_trusted_ca_path: SSL_TRUSTED_CA_PATH.clone(),
-                    }),
-                    #[cfg(feature = "kafka-reporter")]
-                    "kafka" => ReporterConfiguration::Kafka(KafkaReporterConfiguration {
-                        kafka_bootstrap_servers: KAFKA_BOOTSTRAP_SERVERS.clone(),
-                        kafka_producer_config: KAFKA_PRODUCER_CONFIG.clone(),
-                    }),
-                    typ => {
-                        error!("unknown reporter type, {}", typ);
-                        exit(1);
-                    }
-                };
+                let reporter_configs = REPORTER_TYPE
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|typ| !typ.is_empty())
+                    .map(|typ| {
+                        reporter_config(typ).unwrap_or_else(|| {
+                            error!("unknown reporter type, {}", typ);
+                            exit(1);
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let configuration_discovery = configuration_discovery_config(&reporter_configs);
 
                 let config = WorkerConfiguration {
                     socket_file_path: SOCKET_FILE_PATH.to_path_buf(),
@@ -78,7 +91,15 @@ pub fn init_worker() {
                         heartbeat_period: *HEARTBEAT_PERIOD,
                         properties_report_period_factor: *PROPERTIES_REPORT_PERIOD_FACTOR,
                     }),
-                    reporter_config,
+                    reporter_configs,
+                    spool: spool_config(),
+                    queue: queue_config(),
+                    self_telemetry: self_telemetry_config(),
+                    runtime_metrics_period: Duration::from_secs(
+                        (*RUNTIME_METRICS_PERIOD).max(0) as u64
+                    ),
+                    fpm_metrics: fpm_metrics_config(),
+                    configuration_discovery,
                 };
 
                 // Run the worker in subprocess.
@@ -98,6 +119,121 @@ pub fn init_worker() {
     }
 }
 
+fn reporter_config(typ: &str) -> Option<ReporterConfiguration> {
+    let config = match typ {
+        "grpc" => ReporterConfiguration::Grpc(grpc_reporter_config()),
+        "http" => ReporterConfiguration::Http(HttpReporterConfiguration {
+            server_addr: HTTP_SERVER_ADDR.clone(),
+            authentication: AUTHENTICATION.clone(),
+            enable_gzip: *HTTP_ENABLE_GZIP,
+            max_retries: (*HTTP_MAX_RETRIES).max(0) as u32,
+        }),
+        "otlp" => ReporterConfiguration::Otlp(OtlpReporterConfiguration {
+            endpoint: OTLP_ENDPOINT.clone(),
+            headers: OTLP_HEADERS.clone(),
+        }),
+        "file" => ReporterConfiguration::File(FileReporterConfiguration {
+            path: FILE_REPORTER_PATH.clone(),
+            max_size: (*FILE_REPORTER_MAX_SIZE).max(0) as u64,
+            rotate_interval: Duration::from_secs((*FILE_REPORTER_ROTATE_INTERVAL).max(0) as u64),
+            max_files: (*FILE_REPORTER_MAX_FILES).max(0) as usize,
+        }),
+        #[cfg(feature = "kafka-reporter")]
+        "kafka" => ReporterConfiguration::Kafka(KafkaReporterConfiguration {
+            kafka_bootstrap_servers: KAFKA_BOOTSTRAP_SERVERS.clone(),
+            kafka_producer_config: KAFKA_PRODUCER_CONFIG.clone(),
+        }),
+        _ => return None,
+    };
+    Some(config)
+}
+
+fn grpc_reporter_config() -> GrpcReporterConfiguration {
+    GrpcReporterConfiguration {
+        authentication: AUTHENTICATION.clone(),
+        enable_tls: *ENABLE_TLS,
+        server_addr: SERVER_ADDR.clone(),
+        ssl_cert_chain_path: SSL_CERT_CHAIN_PATH.clone(),
+        ssl_key_path: SSL_KEY_PATH.clone(),
+        ssl_trusted_ca_path: SSL_TRUSTED_CA_PATH.clone(),
+    }
+}
+
+fn spool_config() -> Option<SpoolConfiguration> {
+    if !*SPOOL_ENABLE || *SPOOL_MAX_SIZE <= 0 {
+        return None;
+    }
+    Some(SpoolConfiguration {
+        dir: SPOOL_DIR.clone(),
+        max_size: *SPOOL_MAX_SIZE as u64,
+    })
+}
+
+fn queue_config() -> QueueConfiguration {
+    let default = QueueConfiguration::default();
+    QueueConfiguration {
+        capacity: usize::try_from(*WORKER_QUEUE_CAPACITY)
+            .ok()
+            .filter(|capacity| *capacity > 0)
+            .unwrap_or(default.capacity),
+        batch_size: usize::try_from(*WORKER_BATCH_SIZE)
+            .ok()
+            .filter(|size| *size > 0)
+            .unwrap_or(default.batch_size),
+        batch_interval: Duration::from_millis((*WORKER_BATCH_INTERVAL).max(0) as u64),
+        stats_interval: Duration::from_secs((*WORKER_STATS_INTERVAL).max(0) as u64),
+    }
+}
+
+fn self_telemetry_config() -> Option<SelfTelemetryConfiguration> {
+    let interval = *WORKER_SELF_TELEMETRY_INTERVAL;
+    if interval <= 0 {
+        return None;
+    }
+    Some(SelfTelemetryConfiguration {
+        service_name: SERVICE_NAME.clone(),
+        service_instance: SERVICE_INSTANCE.clone(),
+        report_interval: Duration::from_secs(interval as u64),
+    })
+}
+
+fn fpm_metrics_config() -> Option<FpmMetricsConfiguration> {
+    if get_sapi_module_name().to_bytes() != b"fpm-fcgi"
+        || FPM_STATUS_ADDRESS.is_empty()
+        || *FPM_METRICS_PERIOD <= 0
+    {
+        return None;
+    }
+    Some(FpmMetricsConfiguration {
+        service_name: SERVICE_NAME.clone(),
+        service_instance: SERVICE_INSTANCE.clone(),
+        status_address: FPM_STATUS_ADDRESS.clone(),
+        status_path: FPM_STATUS_PATH.clone(),
+        period: Duration::from_secs(*FPM_METRICS_PERIOD as u64),
+    })
+}
+
+/// The configurations are fetched from the server of the grpc reporter, so it's
+/// disabled without the grpc reporter.
+fn configuration_discovery_config(
+    reporter_configs: &[ReporterConfiguration],
+) -> Option<ConfigurationDiscoveryConfiguration> {
+    let period = *CONFIGURATION_DISCOVERY_PERIOD;
+    if period <= 0 {
+        return None;
+    }
+    let grpc = reporter_configs.iter().find_map(|config| match config {
+        ReporterConfiguration::Grpc(grpc) => Some(grpc.clone()),
+        _ => None,
+    })?;
+    Some(ConfigurationDiscoveryConfiguration {
+        service_name: SERVICE_NAME.clone(),
+        grpc,
+        period: Duration::from_secs(period as u64),
+        path: CONFIGURATION_FILE_PATH.clone(),
+    })
+}
+
 fn worker_threads() -> usize {
     let worker_threads = *WORKER_THREADS;
     if worker_threads <= 0 {
@@ -39,6 +39,46 @@ require_once dirname(__DIR__) . "/vendor/autoload.php";
     Assert::same(count($rs), 2);
 }
 
+{
+    $mysqli = new mysqli("127.0.0.1", "root", "password", "skywalking", 3306);
+    $stmt = $mysqli->prepare("SELECT * FROM `mysql`.`user` WHERE `User` = ?");
+    $user = "root";
+    $stmt->bind_param("s", $user);
+    Assert::true($stmt->execute());
+    $result = $stmt->get_result();
+    Assert::same(count($result->fetch_all()), 2);
+}
+
+{
+    $mysqli = mysqli_connect("127.0.0.1", "root", "password", "skywalking", 3306);
+    $stmt = mysqli_prepare($mysqli, "SELECT `User` FROM `mysql`.`user` WHERE `User` = ?");
+    $user = "root";
+    mysqli_stmt_bind_param($stmt, "s", $user);
+    Assert::true(mysqli_stmt_execute($stmt));
+    mysqli_stmt_bind_result($stmt, $name);
+    $count = 0;
+    while (mysqli_stmt_fetch($stmt)) {
+        Assert::same($name, "root");
+        $count++;
+    }
+    Assert::same($count, 2);
+}
+
+{
+    mysqli_report(MYSQLI_REPORT_OFF);
+    $mysqli = new mysqli("127.0.0.1", "root", "password", "skywalking", 3306);
+    Assert::true($mysqli->query("CREATE TEMPORARY TABLE `stmt_test` (`id` INT PRIMARY KEY)"));
+    $stmt = $mysqli->prepare("INSERT INTO `stmt_test` (`id`) VALUES (?), (?)");
+    $id1 = 1;
+    $id2 = 2;
+    $stmt->bind_param("ii", $id1, $id2);
+    Assert::true($stmt->execute());
+    Assert::same($stmt->affected_rows, 2);
+    // Duplicate entry.
+    Assert::false($stmt->execute());
+    Assert::same($stmt->errno, 1062);
+}
+
 {
     mysqli_report(MYSQLI_REPORT_OFF);
     $mysqli = mysqli_init();
@@ -0,0 +1,171 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fetch the dynamic configurations of the service from the
+//! ConfigurationDiscoveryService of the OAP, and publish them to the PHP
+//! processes by a JSON file, such as `{"agent.sample_rate":"0.5"}`, which is
+//! read by the PHP processes at the start of the requests.
+
+use crate::reporter::{GrpcReporterConfiguration, create_balance_channel};
+use anyhow::Context;
+use serde_json::{Map, Value};
+use skywalking::proto::v3::Commands;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{
+    fs,
+    time::{interval, timeout},
+};
+use tonic::{
+    Request, client::Grpc, codec::ProstCodec, codegen::http::uri::PathAndQuery,
+    metadata::AsciiMetadataValue, transport::Channel,
+};
+use tracing::{debug, error, info, warn};
+
+const FETCH_CONFIGURATIONS_PATH: &str =
+    "/skywalking.v3.ConfigurationDiscoveryService/fetchConfigurations";
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+const CONFIGURATION_DISCOVERY_COMMAND: &str = "ConfigurationDiscoveryCommand";
+
+const UUID_KEY: &str = "UUID";
+
+const SERIAL_NUMBER_KEY: &str = "SerialNumber";
+
+/// The message isn't compiled by the skywalking crate, the field tags are the
+/// same as `ConfigurationDiscoveryService.proto`.
+#[derive(Clone, PartialEq, prost::Message)]
+struct ConfigurationSyncRequest {
+    #[prost(string, tag = "1")]
+    service: String,
+    #[prost(string, tag = "2")]
+    uuid: String,
+}
+
+pub struct ConfigurationDiscoveryConfiguration {
+    pub service_name: String,
+    /// The OAP servers to fetch from, the same as the grpc reporter.
+    pub grpc: GrpcReporterConfiguration,
+    pub period: Duration,
+    /// The file which the fetched configurations are written to.
+    pub path: PathBuf,
+}
+
+/// Fetch the configurations every period, the file is rewritten only when the
+/// configurations changed, which is told by the uuid.
+pub async fn fetch_configurations(config: ConfigurationDiscoveryConfiguration) {
+    let channel = match create_balance_channel(&config.grpc).await {
+        Ok((channel, _)) => channel,
+        Err(err) => {
+            error!(?err, "Create configuration discovery channel failed");
+            return;
+        }
+    };
+    let mut grpc = Grpc::new(channel);
+
+    let mut uuid = String::new();
+    let mut ticker = interval(config.period);
+
+    loop {
+        ticker.tick().await;
+
+        let commands = match timeout(FETCH_TIMEOUT, fetch(&mut grpc, &config, &uuid)).await {
+            Ok(Ok(commands)) => commands,
+            Ok(Err(err)) => {
+                warn!(?err, "Fetch configurations failed");
+                continue;
+            }
+            Err(_) => {
+                warn!("Fetch configurations timeout");
+                continue;
+            }
+        };
+
+        // No command means the configurations are unchanged.
+        let Some(command) = commands
+            .commands
+            .into_iter()
+            .find(|command| command.command == CONFIGURATION_DISCOVERY_COMMAND)
+        else {
+            continue;
+        };
+
+        let mut new_uuid = String::new();
+        let mut configurations = Map::new();
+        for arg in command.args {
+            match &*arg.key {
+                UUID_KEY => new_uuid = arg.value,
+                SERIAL_NUMBER_KEY => {}
+                _ => {
+                    configurations.insert(arg.key, Value::String(arg.value));
+                }
+            }
+        }
+
+        debug!(?configurations, "Fetched configurations");
+        let count = configurations.len();
+        match write_configurations(&config.path, Value::Object(configurations)).await {
+            Ok(()) => {
+                info!(uuid = new_uuid, count, "Dynamic configurations updated");
+                uuid = new_uuid;
+            }
+            Err(err) => {
+                warn!(?err, "Write configurations failed");
+            }
+        }
+    }
+}
+
+async fn fetch(
+    grpc: &mut Grpc<Channel>, config: &ConfigurationDiscoveryConfiguration, uuid: &str,
+) -> anyhow::Result<Commands> {
+    grpc.ready().await?;
+
+    let mut request = Request::new(ConfigurationSyncRequest {
+        service: config.service_name.clone(),
+        uuid: uuid.to_owned(),
+    });
+    if !config.grpc.authentication.is_empty() {
+        request.metadata_mut().insert(
+            "authentication",
+            AsciiMetadataValue::try_from(&config.grpc.authentication)?,
+        );
+    }
+
+    let response = grpc
+        .unary(
+            request,
+            PathAndQuery::from_static(FETCH_CONFIGURATIONS_PATH),
+            ProstCodec::<ConfigurationSyncRequest, Commands>::default(),
+        )
+        .await?;
+    Ok(response.into_inner())
+}
+
+/// Write to a temporary file then rename, so the readers never see a partial
+/// file.
+async fn write_configurations(path: &Path, configurations: Value) -> anyhow::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, serde_json::to_vec(&configurations)?)
+        .await
+        .with_context(|| format!("write file {:?} failed", tmp_path))?;
+    fs::rename(&tmp_path, path)
+        .await
+        .with_context(|| format!("rename file {:?} failed", tmp_path))?;
+    Ok(())
+}
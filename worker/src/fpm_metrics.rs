@@ -0,0 +1,457 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sample the PHP-FPM pool state from the status page, and report it as the
+//! meters.
+
+use anyhow::{Context, anyhow, bail};
+use serde_json::Value;
+use skywalking::{
+    proto::v3::{Label, MeterData, MeterSingleValue, meter_data::Metric},
+    reporter::CollectItem,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpStream, UnixStream},
+    sync::mpsc,
+    time::{interval, timeout},
+};
+use tracing::{debug, warn};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The fields of the status page, and the meter names.
+const FPM_METERS: [(&str, &str); 8] = [
+    ("accepted conn", "php_fpm_accepted_connections"),
+    ("listen queue", "php_fpm_listen_queue"),
+    ("max listen queue", "php_fpm_max_listen_queue"),
+    ("idle processes", "php_fpm_idle_processes"),
+    ("active processes", "php_fpm_active_processes"),
+    ("total processes", "php_fpm_total_processes"),
+    ("max children reached", "php_fpm_max_children_reached"),
+    ("slow requests", "php_fpm_slow_requests"),
+];
+
+pub struct FpmMetricsConfiguration {
+    pub service_name: String,
+    pub service_instance: String,
+    /// The address of the status page, such as `http://127.0.0.1/status`
+    /// (HTTP), `127.0.0.1:9000` or `unix:/run/php-fpm.sock` (FastCGI).
+    pub status_address: String,
+    /// The `pm.status_path` of the pool, only used by FastCGI.
+    pub status_path: String,
+    pub period: Duration,
+}
+
+/// Report the pool metrics every period.
+pub async fn report_fpm_metrics(config: FpmMetricsConfiguration, tx: mpsc::Sender<CollectItem>) {
+    let mut ticker = interval(config.period);
+    // The first tick completes immediately, wait for the pool to start.
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let status = match timeout(FETCH_TIMEOUT, fetch_status(&config)).await {
+            Ok(Ok(status)) => status,
+            Ok(Err(err)) => {
+                warn!(?err, "Fetch fpm status failed");
+                continue;
+            }
+            Err(_) => {
+                warn!("Fetch fpm status timeout");
+                continue;
+            }
+        };
+
+        let meters = match parse_status(&config, &status) {
+            Ok(meters) => meters,
+            Err(err) => {
+                warn!(?err, "Parse fpm status failed");
+                continue;
+            }
+        };
+
+        debug!(count = meters.len(), "Report fpm metrics");
+        for meter in meters {
+            if let Err(err) = tx.try_send(CollectItem::Meter(Box::new(meter))) {
+                warn!(?err, "Send fpm metrics failed");
+                break;
+            }
+        }
+    }
+}
+
+fn parse_status(config: &FpmMetricsConfiguration, status: &[u8]) -> anyhow::Result<Vec<MeterData>> {
+    let status = serde_json::from_slice::<Value>(status)?;
+    let pool = status
+        .get("pool")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|dur| dur.as_millis() as i64)
+        .unwrap_or_default();
+
+    Ok(FPM_METERS
+        .iter()
+        .filter_map(|(field, name)| {
+            let value = status.get(field)?.as_f64()?;
+            Some(MeterData {
+                service: config.service_name.clone(),
+                service_instance: config.service_instance.clone(),
+                timestamp,
+                metric: Some(Metric::SingleValue(MeterSingleValue {
+                    name: name.to_string(),
+                    labels: vec![Label {
+                        name: "pool".to_owned(),
+                        value: pool.to_owned(),
+                    }],
+                    value,
+                })),
+            })
+        })
+        .collect())
+}
+
+/// Fetch the status page in json.
+async fn fetch_status(config: &FpmMetricsConfiguration) -> anyhow::Result<Vec<u8>> {
+    let address = config.status_address.as_str();
+
+    if let Some(url) = address.strip_prefix("http://") {
+        let (host, path) = url.split_once('/').unwrap_or((url, ""));
+        let host_port = if host.contains(':') {
+            host.to_owned()
+        } else {
+            format!("{}:80", host)
+        };
+        let stream = TcpStream::connect(host_port).await?;
+        return fetch_status_by_http(stream, host, &format!("/{}", path)).await;
+    }
+
+    if let Some(path) = address.strip_prefix("unix:") {
+        let stream = UnixStream::connect(path).await?;
+        return fetch_status_by_fastcgi(stream, &config.status_path).await;
+    }
+
+    let stream = TcpStream::connect(address).await?;
+    fetch_status_by_fastcgi(stream, &config.status_path).await
+}
+
+async fn fetch_status_by_http(
+    mut stream: impl AsyncRead + AsyncWrite + Unpin, host: &str, path: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let request = format!(
+        "GET {}?json HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let (head, body) = split_head_body(&response)?;
+    let status_line = head.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        bail!("unexpected http status: {}", status_line);
+    }
+    Ok(body.to_vec())
+}
+
+const FCGI_VERSION: u8 = 1;
+const FCGI_BEGIN_REQUEST: u8 = 1;
+const FCGI_END_REQUEST: u8 = 3;
+const FCGI_PARAMS: u8 = 4;
+const FCGI_STDIN: u8 = 5;
+const FCGI_STDOUT: u8 = 6;
+const FCGI_RESPONDER: u16 = 1;
+const FCGI_REQUEST_ID: u16 = 1;
+
+async fn fetch_status_by_fastcgi(
+    mut stream: impl AsyncRead + AsyncWrite + Unpin, path: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let mut begin_request = Vec::with_capacity(8);
+    begin_request.extend_from_slice(&FCGI_RESPONDER.to_be_bytes());
+    begin_request.extend_from_slice(&[0; 6]);
+
+    let mut params = Vec::new();
+    for (name, value) in [
+        ("REQUEST_METHOD", "GET"),
+        ("SCRIPT_NAME", path),
+        ("SCRIPT_FILENAME", path),
+        ("QUERY_STRING", "json"),
+    ] {
+        encode_fastcgi_length(&mut params, name.len());
+        encode_fastcgi_length(&mut params, value.len());
+        params.extend_from_slice(name.as_bytes());
+        params.extend_from_slice(value.as_bytes());
+    }
+
+    let mut request = Vec::new();
+    encode_fastcgi_record(&mut request, FCGI_BEGIN_REQUEST, &begin_request)?;
+    encode_fastcgi_record(&mut request, FCGI_PARAMS, &params)?;
+    encode_fastcgi_record(&mut request, FCGI_PARAMS, &[])?;
+    encode_fastcgi_record(&mut request, FCGI_STDIN, &[])?;
+    stream.write_all(&request).await?;
+
+    let mut stdout = Vec::new();
+    loop {
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header).await?;
+        let content_length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        let padding_length = header[6] as usize;
+
+        let mut content = vec![0u8; content_length + padding_length];
+        stream.read_exact(&mut content).await?;
+        content.truncate(content_length);
+
+        match header[1] {
+            FCGI_STDOUT => stdout.extend_from_slice(&content),
+            FCGI_END_REQUEST => break,
+            _ => {}
+        }
+    }
+
+    // The response is CGI style, the status header exists only when it isn't
+    // 200.
+    let (head, body) = split_head_body(&stdout)?;
+    if let Some(status) = head
+        .lines()
+        .find_map(|line| line.strip_prefix("Status:"))
+        .map(str::trim)
+        .filter(|status| !status.starts_with("200"))
+    {
+        bail!("unexpected fastcgi status: {}", status);
+    }
+    Ok(body.to_vec())
+}
+
+fn encode_fastcgi_record(buf: &mut Vec<u8>, typ: u8, content: &[u8]) -> anyhow::Result<()> {
+    let content_length = u16::try_from(content.len()).context("fastcgi record is too large")?;
+    buf.extend_from_slice(&[FCGI_VERSION, typ]);
+    buf.extend_from_slice(&FCGI_REQUEST_ID.to_be_bytes());
+    buf.extend_from_slice(&content_length.to_be_bytes());
+    buf.extend_from_slice(&[0, 0]);
+    buf.extend_from_slice(content);
+    Ok(())
+}
+
+fn encode_fastcgi_length(buf: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        buf.push(len as u8);
+    } else {
+        buf.extend_from_slice(&(len as u32 | 0x8000_0000).to_be_bytes());
+    }
+}
+
+fn split_head_body(response: &[u8]) -> anyhow::Result<(String, &[u8])> {
+    let pos = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("response head isn't complete"))?;
+    Ok((
+        String::from_utf8_lossy(&response[..pos]).into_owned(),
+        &response[pos + 4..],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    fn config() -> FpmMetricsConfiguration {
+        FpmMetricsConfiguration {
+            service_name: "service".to_owned(),
+            service_instance: "instance".to_owned(),
+            status_address: "127.0.0.1:9000".to_owned(),
+            status_path: "/status".to_owned(),
+            period: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let status = br#"{"pool":"www","accepted conn":12,"listen queue":0,"idle processes":2,"active processes":1,"start time":1700000000}"#;
+        let meters = parse_status(&config(), status).unwrap();
+
+        let values = meters
+            .iter()
+            .map(|meter| {
+                assert_eq!(meter.service, "service");
+                assert_eq!(meter.service_instance, "instance");
+                match &meter.metric {
+                    Some(Metric::SingleValue(value)) => {
+                        assert_eq!(value.labels[0].name, "pool");
+                        assert_eq!(value.labels[0].value, "www");
+                        (value.name.as_str(), value.value)
+                    }
+                    _ => panic!("unexpected metric"),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            [
+                ("php_fpm_accepted_connections", 12.),
+                ("php_fpm_listen_queue", 0.),
+                ("php_fpm_idle_processes", 2.),
+                ("php_fpm_active_processes", 1.),
+            ]
+        );
+
+        assert!(parse_status(&config(), b"pool: www").is_err());
+    }
+
+    #[test]
+    fn test_encode_fastcgi_length() {
+        let mut buf = Vec::new();
+        encode_fastcgi_length(&mut buf, 0x7f);
+        encode_fastcgi_length(&mut buf, 0x80);
+        encode_fastcgi_length(&mut buf, 0x1234);
+        assert_eq!(buf, [0x7f, 0x80, 0, 0, 0x80, 0x80, 0, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_encode_fastcgi_record() {
+        let mut buf = Vec::new();
+        encode_fastcgi_record(&mut buf, FCGI_PARAMS, b"abc").unwrap();
+        assert_eq!(buf, [1, FCGI_PARAMS, 0, 1, 0, 3, 0, 0, b'a', b'b', b'c']);
+
+        assert!(encode_fastcgi_record(&mut buf, FCGI_PARAMS, &[0; 0x10000]).is_err());
+    }
+
+    #[test]
+    fn test_split_head_body() {
+        let (head, body) = split_head_body(b"HTTP/1.0 200 OK\r\nA: b\r\n\r\n{}\r\n\r\n").unwrap();
+        assert_eq!(head, "HTTP/1.0 200 OK\r\nA: b");
+        assert_eq!(body, b"{}\r\n\r\n");
+
+        let (head, body) = split_head_body(b"\r\n\r\n").unwrap();
+        assert_eq!(head, "");
+        assert_eq!(body, b"");
+
+        assert!(split_head_body(b"HTTP/1.0 200 OK\r\n").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_status_by_http() {
+        let (client, mut server) = duplex(4096);
+        server
+            .write_all(
+                b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"pool\":\"www\"}",
+            )
+            .await
+            .unwrap();
+        server.shutdown().await.unwrap();
+        let body = fetch_status_by_http(client, "localhost", "/status")
+            .await
+            .unwrap();
+        assert_eq!(body, b"{\"pool\":\"www\"}");
+
+        let (client, mut server) = duplex(4096);
+        server
+            .write_all(b"HTTP/1.0 404 Not Found\r\n\r\n")
+            .await
+            .unwrap();
+        server.shutdown().await.unwrap();
+        assert!(
+            fetch_status_by_http(client, "localhost", "/status")
+                .await
+                .is_err()
+        );
+    }
+
+    /// Write the records of the FastCGI response, the stdout is split into two
+    /// padded records.
+    async fn write_fastcgi_response(server: &mut (impl AsyncWrite + Unpin), stdout: &[u8]) {
+        let (first, second) = stdout.split_at(stdout.len() / 2);
+        for content in [first, second] {
+            let mut record = vec![FCGI_VERSION, FCGI_STDOUT, 0, 1];
+            record.extend_from_slice(&(content.len() as u16).to_be_bytes());
+            record.extend_from_slice(&[3, 0]);
+            record.extend_from_slice(content);
+            record.extend_from_slice(&[0; 3]);
+            server.write_all(&record).await.unwrap();
+        }
+        server
+            .write_all(&[
+                FCGI_VERSION,
+                FCGI_END_REQUEST,
+                0,
+                1,
+                0,
+                8,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ])
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fetch_status_by_fastcgi() {
+        let (client, mut server) = duplex(4096);
+        write_fastcgi_response(
+            &mut server,
+            b"Content-type: application/json\r\n\r\n{\"pool\":\"www\"}",
+        )
+        .await;
+        let body = fetch_status_by_fastcgi(client, "/status").await.unwrap();
+        assert_eq!(body, b"{\"pool\":\"www\"}");
+
+        // The request is begin request, params, empty params and empty stdin.
+        let mut request = Vec::new();
+        server.read_to_end(&mut request).await.unwrap();
+        assert_eq!(&request[..4], [FCGI_VERSION, FCGI_BEGIN_REQUEST, 0, 1]);
+        assert!(
+            request
+                .windows(b"QUERY_STRINGjson".len())
+                .any(|window| window == b"QUERY_STRINGjson")
+        );
+        assert!(request.ends_with(&[
+            FCGI_VERSION,
+            FCGI_PARAMS,
+            0,
+            1,
+            0,
+            0,
+            0,
+            0,
+            FCGI_VERSION,
+            FCGI_STDIN,
+            0,
+            1,
+            0,
+            0,
+            0,
+            0
+        ]));
+
+        let (client, mut server) = duplex(4096);
+        write_fastcgi_response(&mut server, b"Status: 404 Not Found\r\n\r\nFile not found.").await;
+        assert!(fetch_status_by_fastcgi(client, "/status").await.is_err());
+    }
+}
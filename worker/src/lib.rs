@@ -14,21 +14,37 @@
 // limitations under the License.
 
 pub mod channel;
+pub mod configuration_discovery;
+pub mod fpm_metrics;
 pub mod reporter;
+pub mod runtime_metrics;
+pub mod spool;
+pub mod stats;
+pub mod telemetry;
 
 use crate::{
     channel::TxReporter,
-    reporter::{ReporterConfiguration, run_reporter},
+    configuration_discovery::{ConfigurationDiscoveryConfiguration, fetch_configurations},
+    fpm_metrics::{FpmMetricsConfiguration, report_fpm_metrics},
+    reporter::{CollectItemBatchConsume, Connectivity, ReporterConfiguration, run_reporter},
+    runtime_metrics::{RuntimeMetricsAggregator, report_runtime_metrics, split_runtime_metrics},
+    spool::{Spool, SpoolConfiguration},
+    stats::{DropReason, WorkerStats, log_stats},
+    telemetry::{SelfTelemetryConfiguration, report_self_telemetry},
 };
+use anyhow::bail;
+use bincode::error::DecodeError;
 use skywalking::{
     management::{instance::Properties, manager::Manager},
     reporter::{CollectItem, CollectItemConsume},
 };
 use std::{
+    collections::VecDeque,
     error::Error,
     fs, io,
     os::unix::prelude::OsStrExt,
     path::{Path, PathBuf},
+    sync::Arc,
     time::Duration,
 };
 use tokio::{
@@ -37,14 +53,56 @@ use tokio::{
     select,
     signal::unix::{SignalKind, signal},
     sync::mpsc::{self, error::TrySendError},
+    task::JoinSet,
+    time::{Instant, timeout_at},
 };
 use tonic::async_trait;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+/// The interval to check whether the spooled items can be replayed, when
+/// there is no new item.
+const SPOOL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct WorkerConfiguration {
     pub socket_file_path: PathBuf,
     pub heart_beat: Option<HeartBeatConfiguration>,
-    pub reporter_config: ReporterConfiguration,
+    /// The collect items are sent to every reporter, each reporter has its
+    /// own queue and spool, so a slow or down reporter doesn't block the
+    /// others.
+    pub reporter_configs: Vec<ReporterConfiguration>,
+    /// The spool of the reporter, the spool of the nth reporter is the
+    /// subdirectory `n` when there are multiple reporters.
+    pub spool: Option<SpoolConfiguration>,
+    pub queue: QueueConfiguration,
+    pub self_telemetry: Option<SelfTelemetryConfiguration>,
+    /// The period to report the aggregated runtime metrics, zero means the
+    /// runtime metrics are forwarded without aggregation.
+    pub runtime_metrics_period: Duration,
+    pub fpm_metrics: Option<FpmMetricsConfiguration>,
+    pub configuration_discovery: Option<ConfigurationDiscoveryConfiguration>,
+}
+
+pub struct QueueConfiguration {
+    /// The capacity of the in-memory queue of the collect items.
+    pub capacity: usize,
+    /// The count of the items to wait for before reporting, the http, otlp
+    /// and file reporters send them at once, with the items already queued.
+    pub batch_size: usize,
+    /// The max time to wait for a batch to be filled.
+    pub batch_interval: Duration,
+    /// The interval to log and publish the worker stats, zero means never.
+    pub stats_interval: Duration,
+}
+
+impl Default for QueueConfiguration {
+    fn default() -> Self {
+        Self {
+            capacity: 255,
+            batch_size: 1,
+            batch_interval: Duration::ZERO,
+            stats_interval: Duration::from_secs(60),
+        }
+    }
 }
 
 pub struct HeartBeatConfiguration {
@@ -69,7 +127,13 @@ pub async fn start_worker(config: WorkerConfiguration) -> anyhow::Result<()> {
     let socket_file = config.socket_file_path;
 
     // Ensure to cleanup resources when worker exits.
-    let _guard = WorkerExitGuard(socket_file.clone());
+    let _guard = WorkerExitGuard {
+        socket_file: socket_file.clone(),
+        configuration_file: config
+            .configuration_discovery
+            .as_ref()
+            .map(|config| config.path.clone()),
+    };
 
     // Graceful shutdown signal, put it on the top of program.
     let mut sig_term = signal(SignalKind::terminate())?;
@@ -80,16 +144,103 @@ pub async fn start_worker(config: WorkerConfiguration) -> anyhow::Result<()> {
         let listener = UnixListener::bind(&socket_file)?;
         change_permission(socket_file, 0o777);
 
-        let (tx, rx) = mpsc::channel::<CollectItem>(255);
+        if config.reporter_configs.is_empty() {
+            bail!("no reporter is configured");
+        }
+
+        let stats = Arc::new(WorkerStats::default());
+        let queue_config = config.queue;
+
+        if !queue_config.stats_interval.is_zero() {
+            tokio::spawn(log_stats(stats.clone(), queue_config.stats_interval));
+        }
+
+        let (tx, rx) = mpsc::channel::<CollectItem>(queue_config.capacity.max(1));
         let tx_ = tx.clone();
+        let stats_ = stats.clone();
+
+        let is_fan_out = config.reporter_configs.len() > 1;
+        let mut reporters = Vec::with_capacity(config.reporter_configs.len());
+        let mut fan_out_targets = Vec::new();
+        let mut rx = Some(rx);
+        for (index, reporter_config) in config.reporter_configs.into_iter().enumerate() {
+            let spool = config.spool.as_ref().and_then(|spool_config| {
+                let spool_config = SpoolConfiguration {
+                    dir: if is_fan_out {
+                        spool_config.dir.join(index.to_string())
+                    } else {
+                        spool_config.dir.clone()
+                    },
+                    max_size: spool_config.max_size,
+                };
+                match Spool::open(spool_config) {
+                    Ok(spool) => Some(Arc::new(spool)),
+                    Err(err) => {
+                        error!(?err, index, "Open spool failed, spool is disabled");
+                        None
+                    }
+                }
+            });
+
+            // The single reporter consumes the queue directly.
+            let reporter_rx = if is_fan_out {
+                let (reporter_tx, reporter_rx) = mpsc::channel(queue_config.capacity.max(1));
+                fan_out_targets.push(FanOutTarget {
+                    tx: reporter_tx,
+                    spool: spool.clone(),
+                });
+                reporter_rx
+            } else {
+                rx.take()
+                    .expect("the queue is consumed by the only reporter")
+            };
+
+            let connectivity = Connectivity::default();
+            let consumer = Consumer {
+                rx: reporter_rx,
+                spool: spool.clone(),
+                connectivity: connectivity.clone(),
+                stats: stats.clone(),
+                batch: VecDeque::new(),
+                batch_size: queue_config.batch_size.max(1),
+                batch_interval: queue_config.batch_interval,
+                is_replay_turn: false,
+            };
+            reporters.push((reporter_config, consumer, connectivity, spool));
+        }
+
+        // Only the single reporter spools the items when the queue is full,
+        // the reporters of fan-out spool by themselves.
+        let spool_ = match &*reporters {
+            [(_, _, _, spool)] => spool.clone(),
+            _ => None,
+        };
+        if let Some(rx) = rx {
+            tokio::spawn(fan_out(rx, fan_out_targets, stats.clone()));
+        }
+
+        let aggregator = (!config.runtime_metrics_period.is_zero()).then(|| {
+            let aggregator = Arc::new(RuntimeMetricsAggregator::default());
+            tokio::spawn(report_runtime_metrics(
+                aggregator.clone(),
+                config.runtime_metrics_period,
+                tx.clone(),
+            ));
+            aggregator
+        });
         tokio::spawn(async move {
             loop {
                 match listener.accept().await {
                     Ok((mut stream, _addr)) => {
                         let tx = tx.clone();
+                        let spool = spool_.clone();
+                        let stats = stats_.clone();
+                        let aggregator = aggregator.clone();
 
                         tokio::spawn(async move {
                             debug!("Entering channel_receive loop");
+                            stats.inc_ipc_connections();
+                            let _guard = IpcConnectionGuard(stats.clone());
 
                             loop {
                                 let r = match channel::channel_receive(&mut stream).await {
@@ -100,19 +251,41 @@ pub async fn start_worker(config: WorkerConfiguration) -> anyhow::Result<()> {
                                         }
                                         _ => {
                                             error!(?err, "channel_receive failed");
+                                            if err.is::<DecodeError>() {
+                                                stats.add_decode_failed();
+                                            }
                                             continue;
                                         }
                                     },
                                     Ok(i) => i,
                                 };
+                                stats.add_received();
 
-                                // Try send here, to prevent the ipc blocking caused by the channel
-                                // bursting (too late to report),
-                                // which affects the pool process of php-fpm.
-                                if let Err(err) = tx.try_send(r) {
-                                    error!(?err, "Send collect item failed");
-                                    if !matches!(err, TrySendError::Full(_)) {
-                                        return;
+                                let items = match &aggregator {
+                                    Some(aggregator) => {
+                                        aggregator.aggregate(r).into_iter().collect()
+                                    }
+                                    None => split_runtime_metrics(r),
+                                };
+
+                                for r in items {
+                                    // Try send here, to prevent the ipc blocking caused by the
+                                    // channel bursting (too late to report), which affects the
+                                    // pool process of php-fpm.
+                                    match tx.try_send(r) {
+                                        Ok(_) => {}
+                                        Err(TrySendError::Full(item)) => match &spool {
+                                            Some(spool) => spool_item(spool, &stats, item).await,
+                                            None => {
+                                                debug!("Send collect item failed, channel is full");
+                                                stats.add_dropped(DropReason::QueueFull, 1);
+                                            }
+                                        },
+                                        Err(err) => {
+                                            error!(?err, "Send collect item failed");
+                                            stats.add_dropped(DropReason::QueueClosed, 1);
+                                            return;
+                                        }
                                     }
                                 }
                             }
@@ -125,12 +298,53 @@ pub async fn start_worker(config: WorkerConfiguration) -> anyhow::Result<()> {
             }
         });
 
+        if let Some(self_telemetry_config) = config.self_telemetry {
+            report_self_telemetry(
+                self_telemetry_config,
+                stats.clone(),
+                reporters
+                    .iter()
+                    .map(|(_, _, connectivity, _)| connectivity.clone())
+                    .collect(),
+                tx_.clone(),
+            );
+        }
+
+        if let Some(fpm_metrics_config) = config.fpm_metrics {
+            tokio::spawn(report_fpm_metrics(fpm_metrics_config, tx_.clone()));
+        }
+
+        if let Some(configuration_discovery_config) = config.configuration_discovery {
+            tokio::spawn(fetch_configurations(configuration_discovery_config));
+        }
+
         if let Some(heart_beat_config) = config.heart_beat {
             report_properties_and_keep_alive(heart_beat_config, TxReporter(tx_));
         }
 
-        // Run reporter with blocking.
-        run_reporter(config.reporter_config, (), Consumer(rx)).await?;
+        // Run reporters with blocking.
+        if !is_fan_out {
+            for (reporter_config, consumer, connectivity, _) in reporters {
+                run_reporter(reporter_config, (), consumer, connectivity).await?;
+            }
+            return Ok(());
+        }
+
+        let mut handles = JoinSet::new();
+        for (index, (reporter_config, consumer, connectivity, _)) in
+            reporters.into_iter().enumerate()
+        {
+            handles.spawn(async move {
+                if let Err(err) = run_reporter(reporter_config, (), consumer, connectivity).await {
+                    error!(?err, index, "Reporter exit unexpectedly");
+                }
+            });
+        }
+        while let Some(result) = handles.join_next().await {
+            if let Err(err) = result {
+                error!(?err, "Reporter task failed");
+            }
+        }
 
         Ok::<_, anyhow::Error>(())
     };
@@ -149,28 +363,205 @@ pub async fn start_worker(config: WorkerConfiguration) -> anyhow::Result<()> {
     Ok(())
 }
 
-struct Consumer(mpsc::Receiver<CollectItem>);
+struct Consumer {
+    rx: mpsc::Receiver<CollectItem>,
+    spool: Option<Arc<Spool>>,
+    connectivity: Connectivity,
+    stats: Arc<WorkerStats>,
+    batch: VecDeque<CollectItem>,
+    batch_size: usize,
+    batch_interval: Duration,
+    /// Whether to replay the spooled item rather than the received item next.
+    is_replay_turn: bool,
+}
+
+impl Consumer {
+    /// Wait for the first item, then collect the items until the batch is
+    /// full or the batch interval elapsed.
+    async fn fill_batch(&mut self) {
+        let Some(item) = self.receive(None).await else {
+            return;
+        };
+        self.batch.push_back(item);
+
+        let deadline = Instant::now() + self.batch_interval;
+        while self.batch.len() < self.batch_size {
+            match self.receive(Some(deadline)).await {
+                Some(item) => self.batch.push_back(item),
+                None => break,
+            }
+        }
+    }
+
+    /// Receive the next item, `None` means the queue is closed or the
+    /// deadline elapsed.
+    async fn receive(&mut self, deadline: Option<Instant>) -> Option<CollectItem> {
+        match (self.spool.clone(), deadline) {
+            (Some(spool), _) => self.consume_with_spool(&spool, deadline).await,
+            (None, Some(deadline)) => timeout_at(deadline, self.rx.recv()).await.ok()?,
+            (None, None) => self.rx.recv().await,
+        }
+    }
 
+    /// Spool the received items when the reporter is disconnected, and replay
+    /// the spooled items once the reporter connected.
+    ///
+    /// The replayed items are interleaved with the received items, so the
+    /// spool is drained even if the items keep coming. Only the waiting of the
+    /// queue is bounded by the deadline, the spooling and replaying are never
+    /// cancelled halfway.
+    async fn consume_with_spool(
+        &mut self, spool: &Arc<Spool>, deadline: Option<Instant>,
+    ) -> Option<CollectItem> {
+        loop {
+            if self.connectivity.is_connected() && !spool.is_empty() {
+                self.is_replay_turn = !self.is_replay_turn;
+                if !self.is_replay_turn {
+                    if let Ok(item) = self.rx.try_recv() {
+                        return Some(item);
+                    }
+                }
+                match spool.clone().pop().await {
+                    Ok(Some(item)) => return Some(item),
+                    Ok(None) => {}
+                    Err(err) => {
+                        warn!(?err, "Replay spooled item failed");
+                        continue;
+                    }
+                }
+            }
+
+            let check_deadline = Instant::now() + SPOOL_CHECK_INTERVAL;
+            let wait_deadline = deadline.map_or(check_deadline, |d| d.min(check_deadline));
+            let Ok(item) = timeout_at(wait_deadline, self.rx.recv()).await else {
+                if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                    return None;
+                }
+                continue;
+            };
+            let item = item?;
+            if self.connectivity.is_connected() {
+                return Some(item);
+            }
+            spool_item(spool, &self.stats, item).await;
+        }
+    }
+}
+
+struct FanOutTarget {
+    tx: mpsc::Sender<CollectItem>,
+    spool: Option<Arc<Spool>>,
+}
+
+/// Send every item to the queue of every reporter, a full queue is spooled or
+/// dropped without blocking the other reporters.
+async fn fan_out(
+    mut rx: mpsc::Receiver<CollectItem>, targets: Vec<FanOutTarget>, stats: Arc<WorkerStats>,
+) {
+    while let Some(item) = rx.recv().await {
+        for target in &targets {
+            match target.tx.try_send(item.clone()) {
+                Ok(_) => {}
+                Err(TrySendError::Full(item)) => match &target.spool {
+                    Some(spool) => spool_item(spool, &stats, item).await,
+                    None => stats.add_dropped(DropReason::QueueFull, 1),
+                },
+                Err(TrySendError::Closed(_)) => stats.add_dropped(DropReason::QueueClosed, 1),
+            }
+        }
+    }
+}
+
+async fn spool_item(spool: &Arc<Spool>, stats: &WorkerStats, item: CollectItem) {
+    match spool.clone().push(item).await {
+        Ok(evicted) => {
+            stats.add_spooled();
+            stats.add_dropped(DropReason::SpoolEvicted, evicted);
+        }
+        Err(err) => {
+            error!(?err, "Spool collect item failed");
+            stats.add_dropped(DropReason::SpoolFailed, 1);
+        }
+    }
+}
+
+/// For the gRPC and Kafka reporters, which stream the items one by one, the
+/// items are counted as reported once handed to the stream.
 #[async_trait]
 impl CollectItemConsume for Consumer {
     async fn consume(&mut self) -> Result<Option<CollectItem>, Box<dyn Error + Send>> {
-        Ok(self.0.recv().await)
+        if self.batch.is_empty() {
+            self.fill_batch().await;
+        }
+        let item = self.batch.pop_front();
+        if item.is_some() {
+            self.stats.add_reported(1);
+        }
+        Ok(item)
     }
 
     async fn try_consume(&mut self) -> Result<Option<CollectItem>, Box<dyn Error + Send>> {
-        Ok(self.0.try_recv().ok())
+        let item = self.batch.pop_front().or_else(|| self.rx.try_recv().ok());
+        if item.is_some() {
+            self.stats.add_reported(1);
+        }
+        Ok(item)
     }
 }
 
-struct WorkerExitGuard(PathBuf);
+/// For the HTTP, OTLP and file reporters, which send the items in bulk, the
+/// items are counted as reported once sent successfully.
+#[async_trait]
+impl CollectItemBatchConsume for Consumer {
+    async fn consume_batch(&mut self, max_count: usize) -> Option<Vec<CollectItem>> {
+        if self.batch.is_empty() {
+            self.fill_batch().await;
+        }
+
+        // Also take the items already queued, without waiting.
+        let mut items = Vec::with_capacity(self.batch.len().min(max_count));
+        while items.len() < max_count {
+            match self.batch.pop_front().or_else(|| self.rx.try_recv().ok()) {
+                Some(item) => items.push(item),
+                None => break,
+            }
+        }
+        (!items.is_empty()).then_some(items)
+    }
+
+    fn ack(&self, count: usize) {
+        self.stats.add_reported(count as u64);
+    }
+}
+
+struct IpcConnectionGuard(Arc<WorkerStats>);
+
+impl Drop for IpcConnectionGuard {
+    fn drop(&mut self) {
+        self.0.dec_ipc_connections();
+    }
+}
+
+struct WorkerExitGuard {
+    socket_file: PathBuf,
+    configuration_file: Option<PathBuf>,
+}
 
 impl Drop for WorkerExitGuard {
     fn drop(&mut self) {
-        let &mut Self(ref socket_file) = self;
+        let socket_file = &self.socket_file;
         info!(?socket_file, "Remove socket file");
         if let Err(err) = fs::remove_file(socket_file) {
             error!(?err, "Remove socket file failed");
         }
+
+        if let Some(configuration_file) = &self.configuration_file {
+            match fs::remove_file(configuration_file) {
+                Ok(()) => info!(?configuration_file, "Remove configuration file"),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => error!(?err, "Remove configuration file failed"),
+            }
+        }
     }
 }
 
@@ -202,3 +593,62 @@ fn change_permission(f: impl AsRef<Path>, mode: libc::mode_t) {
         libc::chmod(path.as_ptr().cast(), mode);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skywalking::proto::v3::LogData;
+
+    fn log_item(service: &str) -> CollectItem {
+        CollectItem::Log(Box::new(LogData {
+            service: service.to_owned(),
+            ..Default::default()
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_isolation() {
+        let stats = Arc::new(WorkerStats::default());
+
+        // The full queue.
+        let (full_tx, mut full_rx) = mpsc::channel(1);
+        full_tx.try_send(log_item("pending")).unwrap();
+        // The closed queue.
+        let (closed_tx, closed_rx) = mpsc::channel(8);
+        drop(closed_rx);
+        // The healthy queue.
+        let (healthy_tx, mut healthy_rx) = mpsc::channel(8);
+
+        let targets = [full_tx, closed_tx, healthy_tx]
+            .into_iter()
+            .map(|tx| FanOutTarget { tx, spool: None })
+            .collect();
+
+        let (tx, rx) = mpsc::channel(8);
+        for service in ["a", "b", "c"] {
+            tx.send(log_item(service)).await.unwrap();
+        }
+        drop(tx);
+
+        timeout_at(
+            Instant::now() + Duration::from_secs(5),
+            fan_out(rx, targets, stats.clone()),
+        )
+        .await
+        .expect("fan out is blocked");
+
+        let mut services = Vec::new();
+        while let Ok(CollectItem::Log(log)) = healthy_rx.try_recv() {
+            services.push(log.service);
+        }
+        assert_eq!(services, ["a", "b", "c"]);
+
+        assert!(
+            matches!(full_rx.try_recv(), Ok(CollectItem::Log(log)) if log.service == "pending")
+        );
+        assert!(full_rx.try_recv().is_err());
+
+        assert_eq!(stats.dropped(DropReason::QueueFull), 3);
+        assert_eq!(stats.dropped(DropReason::QueueClosed), 3);
+    }
+}
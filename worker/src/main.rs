@@ -15,11 +15,17 @@
 
 use clap::Parser;
 use skywalking_php_worker::{
-    WorkerConfiguration, new_tokio_runtime,
-    reporter::{GrpcReporterConfiguration, KafkaReporterConfiguration, ReporterConfiguration},
+    QueueConfiguration, WorkerConfiguration, new_tokio_runtime,
+    reporter::{
+        FileReporterConfiguration, GrpcReporterConfiguration, HttpReporterConfiguration,
+        KafkaReporterConfiguration, OtlpReporterConfiguration, ReporterConfiguration,
+    },
+    spool::SpoolConfiguration,
     start_worker,
 };
-use std::{num::NonZeroUsize, path::PathBuf, thread::available_parallelism};
+use std::{
+    env, iter, num::NonZeroUsize, path::PathBuf, thread::available_parallelism, time::Duration,
+};
 use tracing::log::LevelFilter;
 use tracing_subscriber::{EnvFilter, FmtSubscriber};
 
@@ -38,17 +44,55 @@ struct Args {
     #[arg(short, long, default_value = "INFO")]
     log_level: LevelFilter,
 
-    /// Select reporter
+    /// Directory of the disk spool, the collect items are spooled when the
+    /// reporter is disconnected or the channel is full. The directory is
+    /// locked, so each worker needs its own directory
+    #[arg(long)]
+    spool_dir: Option<PathBuf>,
+
+    /// Max total size of the disk spool in bytes
+    #[arg(long, default_value_t = 100 * 1024 * 1024)]
+    spool_max_size: u64,
+
+    /// Capacity of the in-memory queue of the collect items
+    #[arg(long, default_value_t = 255)]
+    queue_capacity: usize,
+
+    /// Count of the collect items to wait for before reporting, the http, otlp
+    /// and file reporters send them at once
+    #[arg(long, default_value_t = 1)]
+    batch_size: usize,
+
+    /// Max milliseconds to wait for a batch to be filled
+    #[arg(long, default_value_t = 0)]
+    batch_interval: u64,
+
+    /// Seconds between the worker stats logs, 0 means never
+    #[arg(long, default_value_t = 60)]
+    stats_interval: u64,
+
+    /// Seconds between the aggregated runtime metrics reports, 0 means the
+    /// runtime metrics are forwarded without aggregation
+    #[arg(long, default_value_t = 20)]
+    runtime_metrics_period: u64,
+
+    /// Select reporter, more reporters can be appended after `--and`, such as
+    /// `grpc --server-addr 127.0.0.1:11800 --and file --file-path
+    /// /tmp/a.ndjson`
     #[command(subcommand)]
     reporter: ReporterArgs,
 }
 
+/// The separator of the reporters in the command line arguments.
+const REPORTER_SEPARATOR: &str = "--and";
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 enum ReporterArgs {
     /// Report to Skywalking OAP via grpc protocol
     Grpc {
-        /// skywalking server address
+        /// skywalking server address, multiple addresses are separated by
+        /// comma, the requests are balanced across the reachable servers
         #[arg(long)]
         server_addr: String,
 
@@ -74,6 +118,54 @@ enum ReporterArgs {
         #[arg(long)]
         ssl_trusted_ca_path: Option<String>,
     },
+    /// Report to Skywalking OAP via http/json protocol
+    Http {
+        /// The base url of the skywalking server, such as
+        /// `http://127.0.0.1:12800`
+        #[arg(long)]
+        http_server_addr: String,
+
+        /// Skywalking agent authentication token
+        #[arg(long)]
+        authentication: Option<String>,
+
+        /// Whether to compress the request body by gzip
+        #[arg(long)]
+        http_enable_gzip: bool,
+
+        /// Max retry times of a failed request
+        #[arg(long, default_value_t = 3)]
+        http_max_retries: u32,
+    },
+    /// Report to OpenTelemetry collector via OTLP/gRPC protocol
+    Otlp {
+        /// The OTLP/gRPC endpoint, such as `http://127.0.0.1:4317`
+        #[arg(long)]
+        otlp_endpoint: String,
+
+        /// The extra gRPC metadata, in the format `key1=value1,key2=value2`
+        #[arg(long)]
+        otlp_headers: Option<String>,
+    },
+    /// Write to the rotating files as newline-delimited JSON
+    File {
+        /// Path of the file to write
+        #[arg(long)]
+        file_path: PathBuf,
+
+        /// Rotate when the file exceeds the size in bytes, 0 means never
+        #[arg(long, default_value_t = 100 * 1024 * 1024)]
+        file_max_size: u64,
+
+        /// Rotate when the file has been written for the seconds, 0 means
+        /// never
+        #[arg(long, default_value_t = 0)]
+        file_rotate_interval: u64,
+
+        /// Max count of the rotated files to keep
+        #[arg(long, default_value_t = 5)]
+        file_max_files: usize,
+    },
     /// Report to kafka
     Kafka {
         /// A list of host/port pairs to use for establishing the initial
@@ -107,6 +199,35 @@ impl From<ReporterArgs> for ReporterConfiguration {
                 ssl_key_path: ssl_key_path.unwrap_or_default(),
                 ssl_trusted_ca_path: ssl_trusted_ca_path.unwrap_or_default(),
             }),
+            ReporterArgs::Http {
+                http_server_addr,
+                authentication,
+                http_enable_gzip,
+                http_max_retries,
+            } => ReporterConfiguration::Http(HttpReporterConfiguration {
+                server_addr: http_server_addr,
+                authentication: authentication.unwrap_or_default(),
+                enable_gzip: http_enable_gzip,
+                max_retries: http_max_retries,
+            }),
+            ReporterArgs::Otlp {
+                otlp_endpoint,
+                otlp_headers,
+            } => ReporterConfiguration::Otlp(OtlpReporterConfiguration {
+                endpoint: otlp_endpoint,
+                headers: otlp_headers.unwrap_or_default(),
+            }),
+            ReporterArgs::File {
+                file_path,
+                file_max_size,
+                file_rotate_interval,
+                file_max_files,
+            } => ReporterConfiguration::File(FileReporterConfiguration {
+                path: file_path,
+                max_size: file_max_size,
+                rotate_interval: Duration::from_secs(file_rotate_interval),
+                max_files: file_max_files,
+            }),
             ReporterArgs::Kafka {
                 kafka_bootstrap_servers,
                 kafka_producer_config,
@@ -140,7 +261,20 @@ fn worker_threads(worker_threads: Option<usize>) -> usize {
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    let mut segments = env::args().collect::<Vec<_>>();
+    let mut reporter_segments = Vec::new();
+    while let Some(pos) = segments.iter().rposition(|arg| arg == REPORTER_SEPARATOR) {
+        reporter_segments.push(segments.split_off(pos + 1));
+        segments.pop();
+    }
+
+    let bin_name = segments.first().cloned().unwrap_or_default();
+    let args = Args::parse_from(segments);
+    let mut reporter_configs = vec![args.reporter.into()];
+    for segment in reporter_segments.into_iter().rev() {
+        let reporter = ReporterArgs::parse_from(iter::once(bin_name.clone()).chain(segment));
+        reporter_configs.push(reporter.into());
+    }
 
     init_logger(&args.log_level)?;
 
@@ -149,7 +283,21 @@ fn main() -> anyhow::Result<()> {
     rt.block_on(start_worker(WorkerConfiguration {
         socket_file_path: args.socket_file_path,
         heart_beat: None,
-        reporter_config: args.reporter.into(),
+        reporter_configs,
+        spool: args.spool_dir.map(|dir| SpoolConfiguration {
+            dir,
+            max_size: args.spool_max_size,
+        }),
+        queue: QueueConfiguration {
+            capacity: args.queue_capacity,
+            batch_size: args.batch_size,
+            batch_interval: Duration::from_millis(args.batch_interval),
+            stats_interval: Duration::from_secs(args.stats_interval),
+        },
+        self_telemetry: None,
+        runtime_metrics_period: Duration::from_secs(args.runtime_metrics_period),
+        fpm_metrics: None,
+        configuration_discovery: None,
     }))?;
 
     Ok(())
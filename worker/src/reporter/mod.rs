@@ -13,31 +13,137 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod otlp_proto;
+mod reporter_file;
 mod reporter_grpc;
+mod reporter_http;
 mod reporter_kafka;
+mod reporter_otlp;
 
+pub use reporter_file::FileReporterConfiguration;
 pub use reporter_grpc::GrpcReporterConfiguration;
+pub(crate) use reporter_grpc::create_balance_channel;
+pub use reporter_http::HttpReporterConfiguration;
 #[cfg(feature = "kafka-reporter")]
 pub use reporter_kafka::KafkaReporterConfiguration;
-use skywalking::reporter::{CollectItemConsume, CollectItemProduce};
+pub use reporter_otlp::OtlpReporterConfiguration;
+use skywalking::reporter::{CollectItem, CollectItemConsume, CollectItemProduce};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tonic::async_trait;
 
 pub enum ReporterConfiguration {
     Grpc(GrpcReporterConfiguration),
 
+    Http(HttpReporterConfiguration),
+
+    Otlp(OtlpReporterConfiguration),
+
+    File(FileReporterConfiguration),
+
     #[cfg(feature = "kafka-reporter")]
     Kafka(KafkaReporterConfiguration),
 }
 
+/// Consume the collect items in batches, for the reporters sending the items
+/// in bulk.
+#[async_trait]
+pub trait CollectItemBatchConsume: Send {
+    /// Wait for the next batch of at most `max_count` items, `None` means the
+    /// queue is closed.
+    async fn consume_batch(&mut self, max_count: usize) -> Option<Vec<CollectItem>>;
+
+    /// Count the items sent to the server successfully.
+    fn ack(&self, count: usize);
+}
+
+/// Whether the reporter is connected to the server, the collect items are
+/// spooled when disconnected.
+#[derive(Clone, Default)]
+pub struct Connectivity(Arc<ConnectivityInner>);
+
+#[derive(Default)]
+struct ConnectivityInner {
+    is_connected: AtomicBool,
+    reconnects: AtomicU64,
+}
+
+impl Connectivity {
+    pub fn is_connected(&self) -> bool {
+        self.0.is_connected.load(Ordering::Relaxed)
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.0.is_connected.store(connected, Ordering::Relaxed);
+    }
+
+    /// Mark connected again after disconnected.
+    pub fn set_reconnected(&self) {
+        self.0.reconnects.fetch_add(1, Ordering::Relaxed);
+        self.set_connected(true);
+    }
+
+    /// The count of the reconnections since the worker started.
+    pub fn reconnects(&self) -> u64 {
+        self.0.reconnects.load(Ordering::Relaxed)
+    }
+}
+
+/// The exponential backoff of the reconnections.
+struct Backoff {
+    delay: Duration,
+}
+
+impl Backoff {
+    const MAX_DELAY: Duration = Duration::from_secs(60);
+    const MIN_DELAY: Duration = Duration::from_secs(1);
+
+    /// Get the delay, and double it for the next time.
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (delay * 2).min(Self::MAX_DELAY);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.delay = Self::MIN_DELAY;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            delay: Self::MIN_DELAY,
+        }
+    }
+}
+
 pub async fn run_reporter(
     config: ReporterConfiguration, producer: impl CollectItemProduce,
-    consumer: impl CollectItemConsume,
+    consumer: impl CollectItemConsume + CollectItemBatchConsume, connectivity: Connectivity,
 ) -> anyhow::Result<()> {
     match config {
         ReporterConfiguration::Grpc(config) => {
-            reporter_grpc::run_reporter(config, producer, consumer).await
+            reporter_grpc::run_reporter(config, producer, consumer, connectivity).await
+        }
+        ReporterConfiguration::Http(config) => {
+            reporter_http::run_reporter(config, consumer, connectivity).await
+        }
+        ReporterConfiguration::Otlp(config) => {
+            reporter_otlp::run_reporter(config, consumer, connectivity).await
+        }
+        ReporterConfiguration::File(config) => {
+            reporter_file::run_reporter(config, consumer, connectivity).await
         }
         #[cfg(feature = "kafka-reporter")]
         ReporterConfiguration::Kafka(config) => {
+            // The kafka client buffers and retries by itself.
+            connectivity.set_connected(true);
             reporter_kafka::run_reporter(config, producer, consumer).await
         }
     }
@@ -0,0 +1,250 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Write the collect items as newline-delimited JSON, one item per line in the
+//! serde format of `CollectItem`, such as `{"Trace":{...}}`.
+//!
+//! The file is rotated like logrotate, `collect.ndjson` is renamed to
+//! `collect.ndjson.1`, and `collect.ndjson.1` to `collect.ndjson.2`, etc.
+
+use super::{CollectItemBatchConsume, Connectivity};
+use anyhow::Context;
+use skywalking::reporter::CollectItem;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+};
+use tracing::{debug, info, warn};
+
+/// Max count of the collect items written before flushing.
+const MAX_WRITE_BATCH: usize = 512;
+
+pub struct FileReporterConfiguration {
+    pub path: PathBuf,
+    /// Rotate when the file exceeds the size in bytes, 0 means never.
+    pub max_size: u64,
+    /// Rotate when the file has been written for the interval, zero means
+    /// never.
+    pub rotate_interval: Duration,
+    /// Max count of the rotated files to keep.
+    pub max_files: usize,
+}
+
+pub async fn run_reporter(
+    config: FileReporterConfiguration, mut consumer: impl CollectItemBatchConsume,
+    connectivity: Connectivity,
+) -> anyhow::Result<()> {
+    if let Some(dir) = config.path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("create directory {:?} failed", dir))?;
+    }
+
+    let mut writer = RotatingWriter::open(config).await?;
+    connectivity.set_connected(true);
+
+    info!("Worker is ready...");
+
+    while let Some(items) = consumer.consume_batch(MAX_WRITE_BATCH).await {
+        match writer.write(&items).await {
+            Ok(count) => consumer.ack(count),
+            Err(err) => warn!(?err, count = items.len(), "Write collect items failed"),
+        }
+    }
+
+    writer.file.flush().await?;
+
+    Ok(())
+}
+
+struct RotatingWriter {
+    config: FileReporterConfiguration,
+    file: BufWriter<File>,
+    size: u64,
+    opened_at: Instant,
+}
+
+impl RotatingWriter {
+    async fn open(config: FileReporterConfiguration) -> anyhow::Result<Self> {
+        let (file, size) = open_file(&config.path).await?;
+        Ok(Self {
+            config,
+            file,
+            size,
+            opened_at: Instant::now(),
+        })
+    }
+
+    /// Write the items, return the count of the items written.
+    async fn write(&mut self, items: &[CollectItem]) -> anyhow::Result<usize> {
+        if self.should_rotate() {
+            self.rotate().await?;
+        }
+
+        let mut count = 0;
+        for item in items {
+            let mut line = match serde_json::to_vec(item) {
+                Ok(line) => line,
+                Err(err) => {
+                    warn!(?err, "Serialize collect item failed");
+                    continue;
+                }
+            };
+            line.push(b'\n');
+            self.file.write_all(&line).await?;
+            self.size += line.len() as u64;
+            count += 1;
+        }
+        self.file.flush().await?;
+
+        Ok(count)
+    }
+
+    fn should_rotate(&self) -> bool {
+        (self.config.max_size > 0 && self.size >= self.config.max_size)
+            || (!self.config.rotate_interval.is_zero()
+                && self.size > 0
+                && self.opened_at.elapsed() >= self.config.rotate_interval)
+    }
+
+    async fn rotate(&mut self) -> anyhow::Result<()> {
+        self.file.flush().await?;
+
+        let path = &self.config.path;
+        if self.config.max_files == 0 {
+            fs::remove_file(path).await?;
+        } else {
+            let oldest = rotated_path(path, self.config.max_files);
+            if let Err(err) = fs::remove_file(&oldest).await {
+                debug!(?err, ?oldest, "Remove the oldest rotated file failed");
+            }
+            for n in (1..self.config.max_files).rev() {
+                let from = rotated_path(path, n);
+                if fs::try_exists(&from).await.unwrap_or_default() {
+                    fs::rename(&from, rotated_path(path, n + 1)).await?;
+                }
+            }
+            fs::rename(path, rotated_path(path, 1)).await?;
+        }
+
+        let (file, size) = open_file(path).await?;
+        self.file = file;
+        self.size = size;
+        self.opened_at = Instant::now();
+
+        debug!(?path, "Collect file rotated");
+
+        Ok(())
+    }
+}
+
+async fn open_file(path: &Path) -> anyhow::Result<(BufWriter<File>, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .with_context(|| format!("open file {:?} failed", path))?;
+    let size = file.metadata().await?.len();
+    Ok((BufWriter::new(file), size))
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(format!(".{}", n));
+    path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skywalking::proto::v3::LogData;
+
+    fn log_item(service: &str) -> CollectItem {
+        CollectItem::Log(Box::new(LogData {
+            service: service.to_owned(),
+            ..Default::default()
+        }))
+    }
+
+    /// The services of the items in the file, `None` if the file doesn't exist.
+    async fn read_services(path: &Path) -> Option<Vec<String>> {
+        let content = fs::read_to_string(path).await.ok()?;
+        Some(
+            content
+                .lines()
+                .map(|line| match serde_json::from_str(line).unwrap() {
+                    CollectItem::Log(log) => log.service,
+                    _ => panic!("unexpected collect item"),
+                })
+                .collect(),
+        )
+    }
+
+    async fn open_writer(path: &Path, max_size: u64, max_files: usize) -> RotatingWriter {
+        RotatingWriter::open(FileReporterConfiguration {
+            path: path.to_owned(),
+            max_size,
+            rotate_interval: Duration::ZERO,
+            max_files,
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("collect.ndjson");
+
+        let mut writer = open_writer(&path, 1024 * 1024, 2).await;
+        let items = [log_item("a"), log_item("b")];
+        assert_eq!(writer.write(&items).await.unwrap(), 2);
+        assert_eq!(writer.write(&[log_item("c")]).await.unwrap(), 1);
+        drop(writer);
+        assert_eq!(read_services(&path).await.unwrap(), ["a", "b", "c"]);
+        assert_eq!(read_services(&rotated_path(&path, 1)).await, None);
+
+        // Reopen with a smaller max size, the existing size is counted.
+        let mut writer = open_writer(&path, 1, 2).await;
+        for service in ["d", "e", "f"] {
+            writer.write(&[log_item(service)]).await.unwrap();
+        }
+        drop(writer);
+        assert_eq!(read_services(&path).await.unwrap(), ["f"]);
+        assert_eq!(read_services(&rotated_path(&path, 1)).await.unwrap(), ["e"]);
+        assert_eq!(read_services(&rotated_path(&path, 2)).await.unwrap(), ["d"]);
+        assert_eq!(read_services(&rotated_path(&path, 3)).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_without_keeping() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("collect.ndjson");
+
+        let mut writer = open_writer(&path, 1, 0).await;
+        for service in ["a", "b"] {
+            writer.write(&[log_item(service)]).await.unwrap();
+        }
+        drop(writer);
+        assert_eq!(read_services(&path).await.unwrap(), ["b"]);
+        assert_eq!(read_services(&rotated_path(&path, 1)).await, None);
+    }
+}
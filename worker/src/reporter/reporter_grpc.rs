@@ -13,14 +13,38 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::anyhow;
+use super::{Backoff, Connectivity};
+use anyhow::{anyhow, bail};
 use skywalking::reporter::{CollectItemConsume, CollectItemProduce, grpc::GrpcReporter};
-use std::time::Duration;
-use tokio::time::sleep;
-use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    net::lookup_host,
+    sync::mpsc,
+    time::{interval, sleep},
+};
+use tonic::{
+    Code,
+    transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity},
+};
+use tower::discover::Change;
 use tracing::{debug, info, warn};
 
+/// The interval to resolve the server addresses again, so the changes of the
+/// DNS records are applied.
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The min capacity of the endpoint changes of the balanced channel.
+const BALANCE_CHANNEL_CAPACITY: usize = 64;
+
+#[derive(Clone)]
 pub struct GrpcReporterConfiguration {
+    /// The comma separated server addresses, the requests are balanced across
+    /// the reachable servers.
     pub server_addr: String,
     pub authentication: String,
     pub enable_tls: bool,
@@ -31,10 +55,12 @@ pub struct GrpcReporterConfiguration {
 
 pub async fn run_reporter(
     config: GrpcReporterConfiguration, producer: impl CollectItemProduce,
-    consumer: impl CollectItemConsume,
+    consumer: impl CollectItemConsume, connectivity: Connectivity,
 ) -> anyhow::Result<()> {
-    let endpoint = create_endpoint(&config).await?;
-    let channel = connect(endpoint).await;
+    let (channel, endpoints) = create_balance_channel(&config).await?;
+
+    connect(&endpoints).await;
+    connectivity.set_connected(true);
 
     let mut reporter = GrpcReporter::new_with_pc(channel, producer, consumer);
 
@@ -47,11 +73,19 @@ pub async fn run_reporter(
     let handle = reporter
         .reporting()
         .await
-        .with_status_handle(|message, status| {
-            warn!(?status, "Collect failed: {}", message);
+        .with_status_handle({
+            let connectivity = connectivity.clone();
+            move |message, status| {
+                warn!(?status, "Collect failed: {}", message);
+                if status.code() == Code::Unavailable {
+                    connectivity.set_connected(false);
+                }
+            }
         })
         .spawn();
 
+    tokio::spawn(probe_connectivity(endpoints, connectivity));
+
     handle
         .await
         .map_err(|err| anyhow!("Tracer reporting failed: {:?}", err))?;
@@ -59,13 +93,66 @@ pub async fn run_reporter(
     Ok(())
 }
 
-async fn create_endpoint(config: &GrpcReporterConfiguration) -> anyhow::Result<Endpoint> {
-    let scheme = if config.enable_tls { "https" } else { "http" };
+/// Create the channel balanced across the resolved server addresses, which
+/// are resolved again periodically in the background.
+pub(crate) async fn create_balance_channel(
+    config: &GrpcReporterConfiguration,
+) -> anyhow::Result<(Channel, Endpoints)> {
+    let servers = parse_server_addrs(&config.server_addr)?;
+    let tls = create_tls_config(config).await?;
 
-    let url = format!("{}://{}", scheme, config.server_addr);
-    debug!(url, "Create Endpoint");
-    let mut endpoint = Endpoint::from_shared(url)?;
+    // The changes are only consumed by the requests, so the channel must hold
+    // all the changes of the first resolution.
+    let resolved = lookup_endpoints(&servers, &tls).await;
+    let (channel, changes) =
+        Channel::balance_channel::<SocketAddr>(resolved.len().max(BALANCE_CHANNEL_CAPACITY));
+    let resolver = Resolver {
+        servers,
+        tls,
+        changes,
+        endpoints: Default::default(),
+    };
+    resolver.apply(resolved).await;
+
+    let endpoints = resolver.endpoints.clone();
+    tokio::spawn(resolver.run());
+
+    Ok((channel, endpoints))
+}
+
+/// The server address with the host name, such as `oap.example.com:11800`.
+struct ServerAddr {
+    host: String,
+    addr: String,
+}
 
+fn parse_server_addrs(server_addr: &str) -> anyhow::Result<Vec<ServerAddr>> {
+    let servers = server_addr
+        .split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(|addr| {
+            let Some((host, _)) = addr.rsplit_once(':') else {
+                bail!("invalid server addr, the port is missing: {}", addr);
+            };
+            Ok(ServerAddr {
+                host: host
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .to_owned(),
+                addr: addr.to_owned(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if servers.is_empty() {
+        bail!("server addr is empty");
+    }
+    Ok(servers)
+}
+
+async fn create_tls_config(
+    config: &GrpcReporterConfiguration,
+) -> anyhow::Result<Option<ClientTlsConfig>> {
     debug!(
         enable_tls = config.enable_tls,
         ssl_trusted_ca_path = config.ssl_trusted_ca_path,
@@ -74,49 +161,248 @@ async fn create_endpoint(config: &GrpcReporterConfiguration) -> anyhow::Result<E
         "Skywalking TLS info"
     );
 
-    if config.enable_tls {
-        let domain_name = config.server_addr.split(':').next().unwrap_or_default();
-        debug!(domain_name, "Configure TLS domain");
-        let mut tls = ClientTlsConfig::new().domain_name(domain_name);
-
-        let ssl_trusted_ca_path = &config.ssl_trusted_ca_path;
-        if !ssl_trusted_ca_path.is_empty() {
-            debug!(ssl_trusted_ca_path, "Configure TLS CA");
-            let ca_cert = tokio::fs::read(&config.ssl_trusted_ca_path).await?;
-            let ca_cert = Certificate::from_pem(ca_cert);
-            tls = tls.ca_certificate(ca_cert);
+    if !config.enable_tls {
+        return Ok(None);
+    }
+
+    let mut tls = ClientTlsConfig::new();
+
+    let ssl_trusted_ca_path = &config.ssl_trusted_ca_path;
+    if !ssl_trusted_ca_path.is_empty() {
+        debug!(ssl_trusted_ca_path, "Configure TLS CA");
+        let ca_cert = tokio::fs::read(&config.ssl_trusted_ca_path).await?;
+        let ca_cert = Certificate::from_pem(ca_cert);
+        tls = tls.ca_certificate(ca_cert);
+    }
+
+    let ssl_key_path = &config.ssl_key_path;
+    let ssl_cert_chain_path = &config.ssl_cert_chain_path;
+    if !ssl_key_path.is_empty() && !ssl_cert_chain_path.is_empty() {
+        debug!(ssl_trusted_ca_path, "Configure mTLS");
+        let client_cert = tokio::fs::read(&config.ssl_cert_chain_path).await?;
+        let client_key = tokio::fs::read(&config.ssl_key_path).await?;
+        let client_identity = Identity::from_pem(client_cert, client_key);
+        tls = tls.identity(client_identity);
+    }
+
+    Ok(Some(tls))
+}
+
+/// The endpoints of the resolved socket addresses, shared by the resolver and
+/// the connectivity probing.
+pub(crate) type Endpoints = Arc<Mutex<HashMap<SocketAddr, Endpoint>>>;
+
+/// Resolve the server addresses periodically, and apply the changes to the
+/// balanced channel.
+struct Resolver {
+    servers: Vec<ServerAddr>,
+    tls: Option<ClientTlsConfig>,
+    changes: mpsc::Sender<Change<SocketAddr, Endpoint>>,
+    endpoints: Endpoints,
+}
+
+impl Resolver {
+    async fn run(self) {
+        let mut ticker = interval(RESOLVE_INTERVAL);
+        // The first tick completes immediately, and it's already resolved.
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            self.resolve().await;
+        }
+    }
+
+    async fn resolve(&self) {
+        let resolved = lookup_endpoints(&self.servers, &self.tls).await;
+        self.apply(resolved).await;
+    }
+
+    /// Apply the changes of the resolved endpoints to the balanced channel.
+    async fn apply(&self, resolved: HashMap<SocketAddr, Endpoint>) {
+        // Keep the previous endpoints if resolve failed totally.
+        if resolved.is_empty() {
+            return;
+        }
+
+        let mut changes = Vec::new();
+        {
+            let mut endpoints = self.endpoints.lock().unwrap_or_else(|err| err.into_inner());
+            for addr in endpoints.keys() {
+                if !resolved.contains_key(addr) {
+                    debug!(%addr, "Remove server endpoint");
+                    changes.push(Change::Remove(*addr));
+                }
+            }
+            for (addr, endpoint) in &resolved {
+                if !endpoints.contains_key(addr) {
+                    debug!(%addr, "Insert server endpoint");
+                    changes.push(Change::Insert(*addr, endpoint.clone()));
+                }
+            }
+            *endpoints = resolved;
         }
 
-        let ssl_key_path = &config.ssl_key_path;
-        let ssl_cert_chain_path = &config.ssl_cert_chain_path;
-        if !ssl_key_path.is_empty() && !ssl_cert_chain_path.is_empty() {
-            debug!(ssl_trusted_ca_path, "Configure mTLS");
-            let client_cert = tokio::fs::read(&config.ssl_cert_chain_path).await?;
-            let client_key = tokio::fs::read(&config.ssl_key_path).await?;
-            let client_identity = Identity::from_pem(client_cert, client_key);
-            tls = tls.identity(client_identity);
+        // Wait for the channel rather than dropping the changes, since the
+        // recorded endpoints aren't changed again.
+        for change in changes {
+            if let Err(err) = self.changes.send(change).await {
+                warn!(?err, "Apply server endpoint change failed");
+                break;
+            }
+        }
+    }
+}
+
+/// Resolve the server addresses to the endpoints, the failed ones are skipped.
+async fn lookup_endpoints(
+    servers: &[ServerAddr], tls: &Option<ClientTlsConfig>,
+) -> HashMap<SocketAddr, Endpoint> {
+    let mut resolved = HashMap::new();
+    for server in servers {
+        match lookup_host(&server.addr).await {
+            Ok(addrs) => {
+                for addr in addrs {
+                    match create_endpoint(server, tls, addr) {
+                        Ok(endpoint) => {
+                            resolved.insert(addr, endpoint);
+                        }
+                        Err(err) => {
+                            warn!(?err, server.addr, "Create endpoint failed");
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(?err, server.addr, "Resolve server addr failed");
+            }
         }
+    }
+    resolved
+}
+
+fn create_endpoint(
+    server: &ServerAddr, tls: &Option<ClientTlsConfig>, addr: SocketAddr,
+) -> anyhow::Result<Endpoint> {
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let url = format!("{}://{}", scheme, addr);
+    debug!(url, server.addr, "Create Endpoint");
+    let mut endpoint = Endpoint::from_shared(url)?;
 
-        endpoint = endpoint.tls_config(tls)?;
+    if let Some(tls) = tls {
+        // Verify the certificate with the host name rather than the ip.
+        debug!(domain_name = server.host, "Configure TLS domain");
+        endpoint = endpoint.tls_config(tls.clone().domain_name(&server.host))?;
     }
 
     Ok(endpoint)
 }
 
+/// Wait until any server is reachable, try the servers in turn with the
+/// exponential backoff.
 #[tracing::instrument(skip_all)]
-async fn connect(endpoint: Endpoint) -> Channel {
-    let channel = loop {
+async fn connect(endpoints: &Endpoints) {
+    let mut backoff = Backoff::default();
+    for attempt in 0.. {
+        if let Some(uri) = try_connect(endpoints, attempt).await {
+            info!(%uri, "Skywalking server connected");
+            return;
+        }
+        let delay = backoff.next_delay();
+        warn!(?delay, "Connect to skywalking servers failed, retry later");
+        sleep(delay).await;
+    }
+}
+
+/// Start from the next server of the last attempt.
+async fn try_connect(endpoints: &Endpoints, attempt: usize) -> Option<String> {
+    let mut endpoints = endpoints
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .iter()
+        .map(|(addr, endpoint)| (*addr, endpoint.clone()))
+        .collect::<Vec<_>>();
+    if endpoints.is_empty() {
+        return None;
+    }
+    endpoints.sort_by_key(|(addr, _)| *addr);
+    let len = endpoints.len();
+    endpoints.rotate_left(attempt % len);
+
+    for (_, endpoint) in endpoints {
         match endpoint.connect().await {
-            Ok(channel) => break channel,
+            Ok(_) => return Some(endpoint.uri().to_string()),
             Err(err) => {
-                warn!(?err, "Connect to skywalking server failed, retry after 10s");
-                sleep(Duration::from_secs(10)).await;
+                debug!(?err, uri = %endpoint.uri(), "Connect to skywalking server failed");
             }
         }
-    };
+    }
+    None
+}
+
+/// The channel reconnects by itself, but the disconnected state is only known
+/// from the failed calls, so probe the servers until any is reachable again.
+async fn probe_connectivity(endpoints: Endpoints, connectivity: Connectivity) {
+    let mut backoff = Backoff::default();
+    for attempt in 0.. {
+        if connectivity.is_connected() {
+            backoff.reset();
+            sleep(Backoff::MIN_DELAY).await;
+            continue;
+        }
+
+        sleep(backoff.next_delay()).await;
+        match try_connect(&endpoints, attempt).await {
+            Some(uri) => {
+                info!(%uri, "Skywalking server reconnected");
+                connectivity.set_reconnected();
+            }
+            None => {
+                debug!("Skywalking servers are still unreachable");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let uri = &*endpoint.uri().to_string();
-    info!(uri, "Skywalking server connected");
+    #[tokio::test]
+    async fn test_resolve_all_changes() {
+        let (changes, mut rx) = mpsc::channel(1);
+        let resolver = Resolver {
+            servers: parse_server_addrs(
+                &(1..=100)
+                    .map(|port| format!("127.0.0.1:{}", port))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            )
+            .unwrap(),
+            tls: None,
+            changes,
+            endpoints: Default::default(),
+        };
 
-    channel
+        let receiving = tokio::spawn(async move {
+            let mut inserted = Vec::new();
+            while let Some(change) = rx.recv().await {
+                match change {
+                    Change::Insert(addr, _) => inserted.push(addr.port()),
+                    Change::Remove(_) => panic!("unexpected remove"),
+                }
+            }
+            inserted
+        });
+
+        resolver.resolve().await;
+        assert_eq!(resolver.endpoints.lock().unwrap().len(), 100);
+        // Resolve again, nothing is changed.
+        resolver.resolve().await;
+        drop(resolver);
+
+        let mut inserted = receiving.await.unwrap();
+        inserted.sort_unstable();
+        assert_eq!(inserted, (1..=100).collect::<Vec<_>>());
+    }
 }
@@ -0,0 +1,379 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Report to the HTTP/JSON receivers of the SkyWalking OAP.
+//!
+//! The JSON is parsed by the protobuf JSON parser of the OAP, which accepts
+//! the original field names and the enum numbers, so the serde serialization
+//! of the protobuf objects is used except for the oneof fields.
+
+use super::{CollectItemBatchConsume, Connectivity};
+use anyhow::{anyhow, bail};
+use flate2::{Compression, write::GzEncoder};
+use reqwest::{
+    Client, StatusCode,
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+};
+use serde_json::Value;
+use skywalking::reporter::CollectItem;
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+/// Max count of the collect items posted in one request.
+const MAX_POST_BATCH: usize = 512;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+const SEGMENTS_PATH: &str = "/v3/segments";
+const LOGS_PATH: &str = "/v3/logs";
+const REPORT_PROPERTIES_PATH: &str = "/v3/management/reportProperties";
+const KEEP_ALIVE_PATH: &str = "/v3/management/keepAlive";
+
+pub struct HttpReporterConfiguration {
+    /// The base url of the OAP REST server, such as `http://127.0.0.1:12800`.
+    pub server_addr: String,
+    pub authentication: String,
+    pub enable_gzip: bool,
+    /// Max retry times of a failed request, only the connection errors and the
+    /// `5xx`/`429` responses are retried.
+    pub max_retries: u32,
+}
+
+pub async fn run_reporter(
+    config: HttpReporterConfiguration, mut consumer: impl CollectItemBatchConsume,
+    connectivity: Connectivity,
+) -> anyhow::Result<()> {
+    if !config.server_addr.starts_with("http://") && !config.server_addr.starts_with("https://") {
+        bail!("invalid http server addr: {}", config.server_addr);
+    }
+
+    let client = Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+    let base_url = config.server_addr.trim_end_matches('/').to_owned();
+    let reporter = HttpReporter {
+        client,
+        base_url,
+        config,
+        connectivity,
+    };
+
+    reporter.wait_connected().await;
+    reporter.connectivity.set_connected(true);
+
+    tokio::spawn(probe_connectivity(
+        reporter.client.clone(),
+        reporter.base_url.clone(),
+        reporter.connectivity.clone(),
+    ));
+
+    info!("Worker is ready...");
+
+    while let Some(items) = consumer.consume_batch(MAX_POST_BATCH).await {
+        let count = reporter.report(items).await;
+        consumer.ack(count);
+    }
+
+    Ok(())
+}
+
+struct HttpReporter {
+    client: Client,
+    base_url: String,
+    config: HttpReporterConfiguration,
+    connectivity: Connectivity,
+}
+
+impl HttpReporter {
+    async fn wait_connected(&self) {
+        loop {
+            match self.client.get(&self.base_url).send().await {
+                Ok(_) => break,
+                Err(err) => {
+                    warn!(?err, "Connect to skywalking server failed, retry after 10s");
+                    sleep(Duration::from_secs(10)).await;
+                }
+            }
+        }
+        info!(url = self.base_url, "Skywalking server connected");
+    }
+
+    /// Post the items, return the count of the items posted successfully.
+    async fn report(&self, items: Vec<CollectItem>) -> usize {
+        let mut count = 0;
+        let mut segments = Vec::new();
+        let mut logs = Vec::new();
+        for item in items {
+            let (path, value) = match item {
+                CollectItem::Trace(segment) => (SEGMENTS_PATH, serde_json::to_value(segment)),
+                CollectItem::Log(log) => (LOGS_PATH, serde_json::to_value(log)),
+                CollectItem::Instance(properties) => {
+                    (REPORT_PROPERTIES_PATH, serde_json::to_value(properties))
+                }
+                CollectItem::Ping(ping) => (KEEP_ALIVE_PATH, serde_json::to_value(ping)),
+                item => {
+                    debug!(?item, "Skip the collect item unsupported by http");
+                    continue;
+                }
+            };
+            let mut value = match value {
+                Ok(value) => value,
+                Err(err) => {
+                    warn!(?err, path, "Serialize collect item failed");
+                    continue;
+                }
+            };
+
+            // The segments and logs are posted in batch.
+            match path {
+                SEGMENTS_PATH => segments.push(value),
+                LOGS_PATH => {
+                    convert_log_body(&mut value);
+                    logs.push(value);
+                }
+                _ => {
+                    if self.post(path, &value).await {
+                        count += 1;
+                    }
+                }
+            }
+        }
+
+        if !segments.is_empty() {
+            let len = segments.len();
+            if self.post(SEGMENTS_PATH, &Value::Array(segments)).await {
+                count += len;
+            }
+        }
+        if !logs.is_empty() {
+            let len = logs.len();
+            if self.post(LOGS_PATH, &Value::Array(logs)).await {
+                count += len;
+            }
+        }
+        count
+    }
+
+    /// Post the body with retries, return whether it succeeded.
+    async fn post(&self, path: &str, body: &Value) -> bool {
+        let body = match serde_json::to_vec(body) {
+            Ok(body) => body,
+            Err(err) => {
+                warn!(?err, path, "Serialize request body failed");
+                return false;
+            }
+        };
+        let body = if self.config.enable_gzip {
+            match gzip(&body) {
+                Ok(body) => body,
+                Err(err) => {
+                    warn!(?err, path, "Gzip request body failed");
+                    return false;
+                }
+            }
+        } else {
+            body
+        };
+
+        let mut retries = 0;
+        loop {
+            match self.try_post(path, body.clone()).await {
+                Ok(()) => return true,
+                Err(PostError::Retryable(err)) if retries < self.config.max_retries => {
+                    retries += 1;
+                    let backoff = RETRY_BACKOFF * 2u32.pow((retries - 1).min(6));
+                    debug!(?err, path, retries, ?backoff, "Post failed, retry later");
+                    sleep(backoff).await;
+                }
+                Err(PostError::Retryable(err)) => {
+                    warn!(?err, path, "Post to skywalking server failed");
+                    if err.is_connect() || err.is_timeout() {
+                        self.connectivity.set_connected(false);
+                    }
+                    return false;
+                }
+                Err(PostError::Fatal(err)) => {
+                    warn!(?err, path, "Post to skywalking server failed");
+                    return false;
+                }
+            }
+        }
+    }
+
+    async fn try_post(&self, path: &str, body: Vec<u8>) -> Result<(), PostError> {
+        let mut request = self
+            .client
+            .post(format!("{}{}", self.base_url, path))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        if self.config.enable_gzip {
+            request = request.header(CONTENT_ENCODING, "gzip");
+        }
+        if !self.config.authentication.is_empty() {
+            request = request.header("Authentication", &self.config.authentication);
+        }
+
+        let response = request.send().await.map_err(PostError::Retryable)?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let err = response.error_for_status().err();
+        match err {
+            Some(err) if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+                Err(PostError::Retryable(err))
+            }
+            Some(err) => Err(PostError::Fatal(err.into())),
+            None => Err(PostError::Fatal(anyhow!("unexpected status: {}", status))),
+        }
+    }
+}
+
+enum PostError {
+    Retryable(reqwest::Error),
+    Fatal(anyhow::Error),
+}
+
+/// Any response means the server is reachable again.
+async fn probe_connectivity(client: Client, base_url: String, connectivity: Connectivity) {
+    loop {
+        sleep(Duration::from_secs(10)).await;
+
+        if connectivity.is_connected() {
+            continue;
+        }
+        match client.get(&base_url).send().await {
+            Ok(_) => {
+                info!("Skywalking server reconnected");
+                connectivity.set_reconnected();
+            }
+            Err(err) => {
+                debug!(?err, "Skywalking server is still unreachable");
+            }
+        }
+    }
+}
+
+/// The serde serialization of the oneof is `{"content": {"Text": {..}}}`, but
+/// the protobuf JSON is `{"text": {..}}`.
+fn convert_log_body(log: &mut Value) {
+    let Some(body) = log.get_mut("body").and_then(Value::as_object_mut) else {
+        return;
+    };
+    let Some(Value::Object(content)) = body.remove("content") else {
+        return;
+    };
+    for (typ, value) in content {
+        body.insert(typ.to_ascii_lowercase(), value);
+    }
+}
+
+fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use skywalking::proto::v3::{
+        JsonLog, KeyStringValuePair, LogData, LogDataBody, LogTags, TextLog, TraceContext,
+        log_data_body::Content,
+    };
+    use std::io::Read;
+
+    #[test]
+    fn test_gzip() {
+        let data = r#"[{"traceId":"abc","spans":[]}]"#.repeat(100);
+        let buf = gzip(data.as_bytes()).unwrap();
+
+        let mut decoded = String::new();
+        GzDecoder::new(buf.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    fn log_value(content: Content) -> Value {
+        let log = LogData {
+            timestamp: 1700000000000,
+            service: "service".to_owned(),
+            service_instance: "instance".to_owned(),
+            endpoint: "/foo".to_owned(),
+            body: Some(LogDataBody {
+                r#type: String::new(),
+                content: Some(content),
+            }),
+            trace_context: Some(TraceContext {
+                trace_id: "trace".to_owned(),
+                trace_segment_id: "segment".to_owned(),
+                span_id: 1,
+            }),
+            tags: Some(LogTags {
+                data: vec![KeyStringValuePair {
+                    key: "level".to_owned(),
+                    value: "INFO".to_owned(),
+                }],
+            }),
+            layer: String::new(),
+        };
+        let mut value = serde_json::to_value(log).unwrap();
+        convert_log_body(&mut value);
+        value
+    }
+
+    #[test]
+    fn test_convert_log_body() {
+        let value = log_value(Content::Text(TextLog {
+            text: "message".to_owned(),
+        }));
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "timestamp": 1700000000000i64,
+                "service": "service",
+                "service_instance": "instance",
+                "endpoint": "/foo",
+                "body": {
+                    "type": "",
+                    "text": {"text": "message"},
+                },
+                "trace_context": {
+                    "trace_id": "trace",
+                    "trace_segment_id": "segment",
+                    "span_id": 1,
+                },
+                "tags": {
+                    "data": [{"key": "level", "value": "INFO"}],
+                },
+                "layer": "",
+            })
+        );
+
+        let value = log_value(Content::Json(JsonLog {
+            json: r#"{"a":1}"#.to_owned(),
+        }));
+        assert_eq!(
+            value["body"],
+            serde_json::json!({"type": "", "json": {"json": r#"{"a":1}"#}})
+        );
+    }
+}
@@ -0,0 +1,1053 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Convert the segments and logs to the OTLP traces and logs, and export them
+//! to the OpenTelemetry collector via OTLP/gRPC.
+
+use super::{
+    CollectItemBatchConsume, Connectivity,
+    otlp_proto::{
+        AnyValue, ExportLogsServiceRequest, ExportLogsServiceResponse, ExportTraceServiceRequest,
+        ExportTraceServiceResponse, InstrumentationScope, KeyValue, LOGS_EXPORT_PATH, LogRecord,
+        Resource, ResourceLogs, ResourceSpans, ScopeLogs, ScopeSpans, SeverityNumber, Span,
+        SpanEvent, SpanKind, SpanLink, Status, StatusCode, TRACE_EXPORT_PATH, any_value::Value,
+    },
+};
+use anyhow::{Context, bail};
+use skywalking::{
+    proto::v3::{
+        LogData, SegmentObject, SegmentReference, SpanLayer, SpanObject, SpanType,
+        log_data_body::Content,
+    },
+    reporter::CollectItem,
+};
+use std::{collections::HashMap, time::Duration};
+use tokio::time::sleep;
+use tonic::{
+    Code, Request,
+    client::Grpc,
+    codec::ProstCodec,
+    codegen::http::uri::PathAndQuery,
+    metadata::{AsciiMetadataKey, AsciiMetadataValue},
+    transport::{Channel, ClientTlsConfig, Endpoint},
+};
+use tracing::{debug, info, warn};
+
+/// Max count of the collect items exported in one request.
+const MAX_EXPORT_BATCH: usize = 512;
+
+const SCOPE_NAME: &str = "skywalking-php";
+
+pub struct OtlpReporterConfiguration {
+    /// The OTLP/gRPC endpoint of the collector, such as
+    /// `http://127.0.0.1:4317`, TLS is enabled for `https://`.
+    pub endpoint: String,
+    /// The extra gRPC metadata sent with every export, in the format
+    /// `key1=value1,key2=value2`.
+    pub headers: String,
+}
+
+pub async fn run_reporter(
+    config: OtlpReporterConfiguration, mut consumer: impl CollectItemBatchConsume,
+    connectivity: Connectivity,
+) -> anyhow::Result<()> {
+    let headers = parse_headers(&config.headers)?;
+    let endpoint = create_endpoint(&config)?;
+    let channel = connect(endpoint.clone()).await;
+    connectivity.set_connected(true);
+
+    tokio::spawn(probe_connectivity(endpoint, connectivity.clone()));
+
+    let mut exporter = Exporter {
+        grpc: Grpc::new(channel),
+        headers,
+        connectivity,
+    };
+
+    info!("Worker is ready...");
+
+    while let Some(items) = consumer.consume_batch(MAX_EXPORT_BATCH).await {
+        let count = exporter.export(items).await;
+        consumer.ack(count);
+    }
+
+    Ok(())
+}
+
+struct Exporter {
+    grpc: Grpc<Channel>,
+    headers: Vec<(AsciiMetadataKey, AsciiMetadataValue)>,
+    connectivity: Connectivity,
+}
+
+impl Exporter {
+    /// Export the items, return the count of the items exported successfully.
+    async fn export(&mut self, items: Vec<CollectItem>) -> usize {
+        let mut exported = 0;
+        let mut segments = Vec::new();
+        let mut logs = Vec::new();
+        for item in items {
+            match item {
+                CollectItem::Trace(segment) => segments.push(*segment),
+                CollectItem::Log(log) => logs.push(*log),
+                item => {
+                    debug!(?item, "Skip the collect item unsupported by otlp");
+                }
+            }
+        }
+
+        if !segments.is_empty() {
+            let count = segments.len();
+            let request = convert_segments(segments);
+            match self
+                .unary::<_, ExportTraceServiceResponse>(request, TRACE_EXPORT_PATH)
+                .await
+            {
+                Ok(response) => {
+                    exported += count;
+                    if let Some(partial) = response.partial_success.filter(|p| p.rejected_spans > 0)
+                    {
+                        warn!(
+                            rejected_spans = partial.rejected_spans,
+                            "Export traces partially failed: {}", partial.error_message
+                        );
+                    }
+                }
+                Err(status) => self.handle_failure(status, "traces", count),
+            }
+        }
+
+        if !logs.is_empty() {
+            let count = logs.len();
+            let request = convert_logs(logs);
+            match self
+                .unary::<_, ExportLogsServiceResponse>(request, LOGS_EXPORT_PATH)
+                .await
+            {
+                Ok(response) => {
+                    exported += count;
+                    if let Some(partial) = response
+                        .partial_success
+                        .filter(|p| p.rejected_log_records > 0)
+                    {
+                        warn!(
+                            rejected_log_records = partial.rejected_log_records,
+                            "Export logs partially failed: {}", partial.error_message
+                        );
+                    }
+                }
+                Err(status) => self.handle_failure(status, "logs", count),
+            }
+        }
+
+        exported
+    }
+
+    async fn unary<Req, Resp>(
+        &mut self, message: Req, path: &'static str,
+    ) -> Result<Resp, tonic::Status>
+    where
+        Req: prost::Message + Send + Sync + 'static,
+        Resp: prost::Message + Default + Send + Sync + 'static,
+    {
+        self.grpc
+            .ready()
+            .await
+            .map_err(|err| tonic::Status::unavailable(err.to_string()))?;
+
+        let mut request = Request::new(message);
+        for (key, value) in &self.headers {
+            request.metadata_mut().insert(key.clone(), value.clone());
+        }
+
+        let response = self
+            .grpc
+            .unary(
+                request,
+                PathAndQuery::from_static(path),
+                ProstCodec::<Req, Resp>::default(),
+            )
+            .await?;
+        Ok(response.into_inner())
+    }
+
+    fn handle_failure(&self, status: tonic::Status, signal: &str, count: usize) {
+        warn!(?status, signal, count, "Export to otlp collector failed");
+        if status.code() == Code::Unavailable {
+            self.connectivity.set_connected(false);
+        }
+    }
+}
+
+fn parse_headers(headers: &str) -> anyhow::Result<Vec<(AsciiMetadataKey, AsciiMetadataValue)>> {
+    headers
+        .split(',')
+        .map(str::trim)
+        .filter(|header| !header.is_empty())
+        .map(|header| {
+            let Some((key, value)) = header.split_once('=') else {
+                bail!("invalid otlp header: {}", header);
+            };
+            let key = key
+                .trim()
+                .to_ascii_lowercase()
+                .parse::<AsciiMetadataKey>()?;
+            let value = value.trim().parse::<AsciiMetadataValue>()?;
+            Ok((key, value))
+        })
+        .collect::<anyhow::Result<_>>()
+        .context("parse otlp headers failed")
+}
+
+fn create_endpoint(config: &OtlpReporterConfiguration) -> anyhow::Result<Endpoint> {
+    debug!(endpoint = config.endpoint, "Create otlp endpoint");
+    let mut endpoint = Endpoint::from_shared(config.endpoint.clone())?;
+    if config.endpoint.starts_with("https://") {
+        endpoint = endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?;
+    }
+    Ok(endpoint)
+}
+
+#[tracing::instrument(skip_all)]
+async fn connect(endpoint: Endpoint) -> Channel {
+    let channel = loop {
+        match endpoint.connect().await {
+            Ok(channel) => break channel,
+            Err(err) => {
+                warn!(?err, "Connect to otlp collector failed, retry after 10s");
+                sleep(Duration::from_secs(10)).await;
+            }
+        }
+    };
+
+    let uri = &*endpoint.uri().to_string();
+    info!(uri, "Otlp collector connected");
+
+    channel
+}
+
+/// Like the grpc reporter, the disconnected state is only known from the
+/// failed exports, so probe the collector until it's reachable again.
+async fn probe_connectivity(endpoint: Endpoint, connectivity: Connectivity) {
+    loop {
+        sleep(Duration::from_secs(10)).await;
+
+        if connectivity.is_connected() {
+            continue;
+        }
+        match endpoint.connect().await {
+            Ok(_) => {
+                info!("Otlp collector reconnected");
+                connectivity.set_reconnected();
+            }
+            Err(err) => {
+                debug!(?err, "Otlp collector is still unreachable");
+            }
+        }
+    }
+}
+
+fn resource(service: String, service_instance: String) -> Resource {
+    Resource {
+        attributes: vec![
+            KeyValue::string("service.name", service),
+            KeyValue::string("service.instance.id", service_instance),
+            KeyValue::string("telemetry.sdk.language", "php"),
+        ],
+    }
+}
+
+fn scope() -> InstrumentationScope {
+    InstrumentationScope {
+        name: SCOPE_NAME.to_owned(),
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+    }
+}
+
+fn convert_segments(segments: Vec<SegmentObject>) -> ExportTraceServiceRequest {
+    let mut resources: HashMap<(String, String), Vec<Span>> = HashMap::new();
+    for segment in segments {
+        let trace_id = convert_trace_id(&segment.trace_id);
+        let spans = resources
+            .entry((segment.service.clone(), segment.service_instance.clone()))
+            .or_default();
+        for span in &segment.spans {
+            spans.push(convert_span(&segment, &trace_id, span));
+        }
+    }
+
+    ExportTraceServiceRequest {
+        resource_spans: resources
+            .into_iter()
+            .map(|((service, service_instance), spans)| ResourceSpans {
+                resource: Some(resource(service, service_instance)),
+                scope_spans: vec![ScopeSpans {
+                    scope: Some(scope()),
+                    spans,
+                }],
+            })
+            .collect(),
+    }
+}
+
+fn convert_span(segment: &SegmentObject, trace_id: &[u8], span: &SpanObject) -> Span {
+    let layer = SpanLayer::try_from(span.span_layer).unwrap_or(SpanLayer::Unknown);
+    let kind = match (SpanType::try_from(span.span_type), layer) {
+        (Ok(SpanType::Entry), SpanLayer::Mq) => SpanKind::Consumer,
+        (Ok(SpanType::Entry), _) => SpanKind::Server,
+        (Ok(SpanType::Exit), SpanLayer::Mq) => SpanKind::Producer,
+        (Ok(SpanType::Exit), _) => SpanKind::Client,
+        (Ok(SpanType::Local), _) => SpanKind::Internal,
+        (Err(_), _) => SpanKind::Unspecified,
+    };
+
+    // The parent is in the same segment, or the first reference for the first
+    // span of the segment, the other references are kept as the links.
+    let mut refs = span.refs.iter();
+    let parent_span_id = if span.parent_span_id >= 0 {
+        convert_span_id(&segment.trace_segment_id, span.parent_span_id)
+    } else {
+        refs.next()
+            .map(|r| convert_span_id(&r.parent_trace_segment_id, r.parent_span_id))
+            .unwrap_or_default()
+    };
+    let links = refs.map(convert_ref).collect();
+
+    let mut attributes = vec![
+        KeyValue::new("sw.component.id", Value::Int(span.component_id.into())),
+        KeyValue::string("sw.span.layer", layer.as_str_name().to_ascii_lowercase()),
+    ];
+    if !span.peer.is_empty() {
+        match kind {
+            SpanKind::Server | SpanKind::Consumer => {
+                attributes.push(KeyValue::string("network.peer.address", &span.peer));
+            }
+            _ => attributes.extend(convert_peer(&span.peer)),
+        }
+    }
+    attributes.extend(
+        span.tags
+            .iter()
+            .map(|tag| convert_tag(&tag.key, &tag.value)),
+    );
+
+    let events = span
+        .logs
+        .iter()
+        .map(|log| {
+            let mut name = "log";
+            let attributes = log
+                .data
+                .iter()
+                .map(|kv| {
+                    let key = match kv.key.as_str() {
+                        "error.kind" => {
+                            name = "exception";
+                            "exception.type"
+                        }
+                        "message" => "exception.message",
+                        "stack" => "exception.stacktrace",
+                        key => key,
+                    };
+                    KeyValue::string(key, &kv.value)
+                })
+                .collect::<Vec<_>>();
+            SpanEvent {
+                time_unix_nano: millis_to_nanos(log.time),
+                name: name.to_owned(),
+                attributes,
+            }
+        })
+        .collect();
+
+    Span {
+        trace_id: trace_id.to_vec(),
+        span_id: convert_span_id(&segment.trace_segment_id, span.span_id),
+        parent_span_id,
+        name: span.operation_name.clone(),
+        kind: kind.into(),
+        start_time_unix_nano: millis_to_nanos(span.start_time),
+        end_time_unix_nano: millis_to_nanos(span.end_time),
+        attributes,
+        events,
+        links,
+        status: span.is_error.then(|| Status {
+            message: String::new(),
+            code: StatusCode::Error.into(),
+        }),
+    }
+}
+
+fn convert_ref(r: &SegmentReference) -> SpanLink {
+    SpanLink {
+        trace_id: convert_trace_id(&r.trace_id),
+        span_id: convert_span_id(&r.parent_trace_segment_id, r.parent_span_id),
+        attributes: vec![
+            KeyValue::string("sw.parent.service", &r.parent_service),
+            KeyValue::string("sw.parent.endpoint", &r.parent_endpoint),
+        ],
+    }
+}
+
+/// Map the tags to the OpenTelemetry semantic conventions, the unknown tags
+/// are kept as is.
+fn convert_tag(key: &str, value: &str) -> KeyValue {
+    let key = match key {
+        "url" => "url.full",
+        "http.method" => "http.request.method",
+        "http.status_code" | "status_code" => {
+            return match value.parse::<i64>() {
+                Ok(code) => KeyValue::new("http.response.status_code", Value::Int(code)),
+                Err(_) => KeyValue::string("http.response.status_code", value),
+            };
+        }
+        "http.client_ip" => "client.address",
+        "http.user_agent" => "user_agent.original",
+        "http.protocol" => "network.protocol.name",
+        "db.type" | "cache.type" => "db.system",
+        "db.statement" => "db.query.text",
+        "db.data_source" | "mongo.db" => "db.namespace",
+        "mongo.collection" => "db.collection.name",
+        "cache.cmd" => "db.operation.name",
+        "mq.queue" | "mq.topic" => "messaging.destination.name",
+        key => key,
+    };
+    KeyValue::string(key, value)
+}
+
+fn convert_peer(peer: &str) -> Vec<KeyValue> {
+    match peer.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() => match port.parse::<i64>() {
+            Ok(port) => vec![
+                KeyValue::string("server.address", host),
+                KeyValue::new("server.port", Value::Int(port)),
+            ],
+            Err(_) => vec![KeyValue::string("server.address", peer)],
+        },
+        _ => vec![KeyValue::string("server.address", peer)],
+    }
+}
+
+fn convert_logs(logs: Vec<LogData>) -> ExportLogsServiceRequest {
+    let mut resources: HashMap<(String, String), Vec<LogRecord>> = HashMap::new();
+    for log in logs {
+        resources
+            .entry((log.service.clone(), log.service_instance.clone()))
+            .or_default()
+            .push(convert_log(log));
+    }
+
+    ExportLogsServiceRequest {
+        resource_logs: resources
+            .into_iter()
+            .map(|((service, service_instance), log_records)| ResourceLogs {
+                resource: Some(resource(service, service_instance)),
+                scope_logs: vec![ScopeLogs {
+                    scope: Some(scope()),
+                    log_records,
+                }],
+            })
+            .collect(),
+    }
+}
+
+fn convert_log(log: LogData) -> LogRecord {
+    let body = log
+        .body
+        .and_then(|body| body.content)
+        .map(|content| match content {
+            Content::Text(text) => text.text,
+            Content::Json(json) => json.json,
+            Content::Yaml(yaml) => yaml.yaml,
+        })
+        .map(|text| AnyValue {
+            value: Some(Value::String(text)),
+        });
+
+    let mut severity_text = String::new();
+    let mut attributes = Vec::new();
+    if !log.endpoint.is_empty() {
+        attributes.push(KeyValue::string("sw.endpoint", log.endpoint));
+    }
+    for tag in log.tags.into_iter().flat_map(|tags| tags.data) {
+        if tag.key == "level" {
+            severity_text = tag.value;
+        } else {
+            attributes.push(KeyValue::string(tag.key, tag.value));
+        }
+    }
+
+    let (trace_id, span_id) = match log.trace_context {
+        Some(context) => (
+            convert_trace_id(&context.trace_id),
+            convert_span_id(&context.trace_segment_id, context.span_id),
+        ),
+        None => Default::default(),
+    };
+
+    let time_unix_nano = millis_to_nanos(log.timestamp);
+    LogRecord {
+        time_unix_nano,
+        severity_number: convert_severity(&severity_text).into(),
+        severity_text,
+        body,
+        attributes,
+        trace_id,
+        span_id,
+        observed_time_unix_nano: time_unix_nano,
+    }
+}
+
+/// The levels of PSR-3 and the error reporting.
+fn convert_severity(level: &str) -> SeverityNumber {
+    match level.to_ascii_lowercase().as_str() {
+        "trace" => SeverityNumber::Trace,
+        "debug" => SeverityNumber::Debug,
+        "info" | "notice" => SeverityNumber::Info,
+        "warn" | "warning" => SeverityNumber::Warn,
+        "error" => SeverityNumber::Error,
+        "critical" | "alert" | "emergency" | "fatal" => SeverityNumber::Fatal,
+        _ => SeverityNumber::Unspecified,
+    }
+}
+
+/// The trace id generated by the agent is an u128 in decimal, the others are
+/// hashed into 16 bytes.
+fn convert_trace_id(trace_id: &str) -> Vec<u8> {
+    if let Ok(id) = trace_id.parse::<u128>() {
+        return id.to_be_bytes().to_vec();
+    }
+    if trace_id.len() == 32 {
+        if let Ok(id) = u128::from_str_radix(trace_id, 16) {
+            return id.to_be_bytes().to_vec();
+        }
+    }
+    let mut id = fnv1a(trace_id.bytes()).to_be_bytes().to_vec();
+    id.extend_from_slice(&fnv1a(trace_id.bytes().rev()).to_be_bytes());
+    id
+}
+
+/// The span id is unique in the segment only, so hash it with the segment id
+/// into 8 bytes.
+fn convert_span_id(segment_id: &str, span_id: i32) -> Vec<u8> {
+    let id = fnv1a(
+        segment_id
+            .bytes()
+            .chain([b':'])
+            .chain(span_id.to_string().into_bytes()),
+    );
+    id.to_be_bytes().to_vec()
+}
+
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn millis_to_nanos(millis: i64) -> u64 {
+    (millis.max(0) as u64).saturating_mul(1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use skywalking::proto::v3::{KeyStringValuePair, Log, LogDataBody, TextLog, TraceContext};
+    use std::{
+        collections::VecDeque,
+        convert::Infallible,
+        future::{Ready, ready},
+        marker::PhantomData,
+        pin::Pin,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+        task::{Context, Poll},
+    };
+    use tokio::net::TcpListener;
+    use tonic::{
+        async_trait,
+        body::BoxBody,
+        codegen::http,
+        server::{NamedService, UnaryService},
+        transport::{Server, server::TcpIncoming},
+    };
+    use tower::Service;
+
+    fn string_value(value: &str) -> Option<AnyValue> {
+        Some(AnyValue {
+            value: Some(Value::String(value.to_owned())),
+        })
+    }
+
+    fn attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a Value> {
+        attributes
+            .iter()
+            .find(|kv| kv.key == key)
+            .and_then(|kv| kv.value.as_ref()?.value.as_ref())
+    }
+
+    #[test]
+    fn test_convert_trace_id() {
+        assert_eq!(convert_trace_id("1"), 1u128.to_be_bytes());
+        assert_eq!(
+            convert_trace_id("0123456789abcdef0123456789abcdef"),
+            0x0123456789abcdef0123456789abcdefu128.to_be_bytes()
+        );
+
+        let id = convert_trace_id("a1b2.c3d4.e5f6");
+        assert_eq!(id.len(), 16);
+        assert_eq!(id, convert_trace_id("a1b2.c3d4.e5f6"));
+        assert_ne!(id, convert_trace_id("a1b2.c3d4.e5f7"));
+    }
+
+    #[test]
+    fn test_convert_span_id() {
+        let id = convert_span_id("segment", 1);
+        assert_eq!(id.len(), 8);
+        assert_eq!(id, convert_span_id("segment", 1));
+        assert_ne!(id, convert_span_id("segment", 2));
+        assert_ne!(id, convert_span_id("segment1", 1));
+        // The separator prevents the collision of the concatenation.
+        assert_ne!(
+            convert_span_id("segment1", 1),
+            convert_span_id("segment", 11)
+        );
+    }
+
+    #[test]
+    fn test_convert_tag() {
+        assert_eq!(
+            convert_tag("url", "http://a/b"),
+            KeyValue::string("url.full", "http://a/b")
+        );
+        assert_eq!(
+            convert_tag("http.status_code", "200"),
+            KeyValue::new("http.response.status_code", Value::Int(200))
+        );
+        assert_eq!(
+            convert_tag("status_code", "unknown"),
+            KeyValue::string("http.response.status_code", "unknown")
+        );
+        assert_eq!(
+            convert_tag("db.statement", "SELECT 1"),
+            KeyValue::string("db.query.text", "SELECT 1")
+        );
+        assert_eq!(convert_tag("foo", "bar"), KeyValue::string("foo", "bar"));
+    }
+
+    #[test]
+    fn test_convert_peer() {
+        assert_eq!(
+            convert_peer("mysql:3306"),
+            [
+                KeyValue::string("server.address", "mysql"),
+                KeyValue::new("server.port", Value::Int(3306)),
+            ]
+        );
+        assert_eq!(
+            convert_peer("mysql"),
+            [KeyValue::string("server.address", "mysql")]
+        );
+        assert_eq!(
+            convert_peer("mysql:x"),
+            [KeyValue::string("server.address", "mysql:x")]
+        );
+    }
+
+    fn segment() -> SegmentObject {
+        SegmentObject {
+            trace_id: "1".to_owned(),
+            trace_segment_id: "segment".to_owned(),
+            service: "service".to_owned(),
+            service_instance: "instance".to_owned(),
+            spans: vec![
+                SpanObject {
+                    span_id: 0,
+                    parent_span_id: -1,
+                    start_time: 1000,
+                    end_time: 2000,
+                    refs: vec![
+                        SegmentReference {
+                            trace_id: "1".to_owned(),
+                            parent_trace_segment_id: "parent".to_owned(),
+                            parent_span_id: 3,
+                            ..Default::default()
+                        },
+                        SegmentReference {
+                            trace_id: "2".to_owned(),
+                            parent_trace_segment_id: "other".to_owned(),
+                            parent_span_id: 4,
+                            parent_service: "other_service".to_owned(),
+                            ..Default::default()
+                        },
+                    ],
+                    operation_name: "GET:/foo".to_owned(),
+                    peer: "127.0.0.1:8080".to_owned(),
+                    span_type: SpanType::Entry as i32,
+                    span_layer: SpanLayer::Http as i32,
+                    component_id: 8001,
+                    tags: vec![KeyStringValuePair {
+                        key: "http.method".to_owned(),
+                        value: "GET".to_owned(),
+                    }],
+                    ..Default::default()
+                },
+                SpanObject {
+                    span_id: 1,
+                    parent_span_id: 0,
+                    start_time: 1100,
+                    end_time: 1200,
+                    operation_name: "PDO->query".to_owned(),
+                    peer: "mysql:3306".to_owned(),
+                    span_type: SpanType::Exit as i32,
+                    span_layer: SpanLayer::Database as i32,
+                    is_error: true,
+                    logs: vec![Log {
+                        time: 1150,
+                        data: vec![
+                            KeyStringValuePair {
+                                key: "error.kind".to_owned(),
+                                value: "PDOException".to_owned(),
+                            },
+                            KeyStringValuePair {
+                                key: "message".to_owned(),
+                                value: "gone away".to_owned(),
+                            },
+                        ],
+                    }],
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_convert_span() {
+        let segment = segment();
+        let trace_id = convert_trace_id(&segment.trace_id);
+
+        let entry = convert_span(&segment, &trace_id, &segment.spans[0]);
+        assert_eq!(entry.trace_id, 1u128.to_be_bytes());
+        assert_eq!(entry.span_id, convert_span_id("segment", 0));
+        // The first reference is the parent, the others are the links.
+        assert_eq!(entry.parent_span_id, convert_span_id("parent", 3));
+        assert_eq!(entry.links.len(), 1);
+        assert_eq!(entry.links[0].trace_id, 2u128.to_be_bytes());
+        assert_eq!(entry.links[0].span_id, convert_span_id("other", 4));
+        assert_eq!(entry.name, "GET:/foo");
+        assert_eq!(entry.kind, SpanKind::Server as i32);
+        assert_eq!(entry.start_time_unix_nano, 1_000_000_000);
+        assert_eq!(entry.end_time_unix_nano, 2_000_000_000);
+        assert_eq!(
+            attribute(&entry.attributes, "sw.component.id"),
+            Some(&Value::Int(8001))
+        );
+        assert_eq!(
+            attribute(&entry.attributes, "network.peer.address"),
+            Some(&Value::String("127.0.0.1:8080".to_owned()))
+        );
+        assert_eq!(
+            attribute(&entry.attributes, "http.request.method"),
+            Some(&Value::String("GET".to_owned()))
+        );
+        assert_eq!(entry.status, None);
+
+        let exit = convert_span(&segment, &trace_id, &segment.spans[1]);
+        assert_eq!(exit.parent_span_id, entry.span_id);
+        assert!(exit.links.is_empty());
+        assert_eq!(exit.kind, SpanKind::Client as i32);
+        assert_eq!(
+            attribute(&exit.attributes, "sw.span.layer"),
+            Some(&Value::String("database".to_owned()))
+        );
+        assert_eq!(
+            attribute(&exit.attributes, "server.port"),
+            Some(&Value::Int(3306))
+        );
+        assert_eq!(exit.events.len(), 1);
+        assert_eq!(exit.events[0].name, "exception");
+        assert_eq!(exit.events[0].time_unix_nano, 1_150_000_000);
+        assert_eq!(
+            exit.events[0].attributes,
+            [
+                KeyValue::string("exception.type", "PDOException"),
+                KeyValue::string("exception.message", "gone away"),
+            ]
+        );
+        assert_eq!(
+            exit.status.map(|status| status.code),
+            Some(StatusCode::Error as i32)
+        );
+    }
+
+    #[test]
+    fn test_convert_log() {
+        let record = convert_log(LogData {
+            timestamp: 1000,
+            endpoint: "/foo".to_owned(),
+            body: Some(LogDataBody {
+                r#type: String::new(),
+                content: Some(Content::Text(TextLog {
+                    text: "message".to_owned(),
+                })),
+            }),
+            trace_context: Some(TraceContext {
+                trace_id: "1".to_owned(),
+                trace_segment_id: "segment".to_owned(),
+                span_id: 1,
+            }),
+            tags: Some(skywalking::proto::v3::LogTags {
+                data: vec![
+                    KeyStringValuePair {
+                        key: "level".to_owned(),
+                        value: "WARNING".to_owned(),
+                    },
+                    KeyStringValuePair {
+                        key: "logger".to_owned(),
+                        value: "app".to_owned(),
+                    },
+                ],
+            }),
+            ..Default::default()
+        });
+        assert_eq!(record.time_unix_nano, 1_000_000_000);
+        assert_eq!(record.severity_text, "WARNING");
+        assert_eq!(record.severity_number, SeverityNumber::Warn as i32);
+        assert_eq!(record.body, string_value("message"));
+        assert_eq!(
+            record.attributes,
+            [
+                KeyValue::string("sw.endpoint", "/foo"),
+                KeyValue::string("logger", "app"),
+            ]
+        );
+        assert_eq!(record.trace_id, 1u128.to_be_bytes());
+        assert_eq!(record.span_id, convert_span_id("segment", 1));
+    }
+
+    /// The field numbers and the wire types are the same as
+    /// `opentelemetry-proto`.
+    #[test]
+    fn test_span_wire_format() {
+        let span = Span {
+            trace_id: vec![1],
+            span_id: vec![2],
+            parent_span_id: vec![3],
+            name: "a".to_owned(),
+            kind: SpanKind::Client.into(),
+            start_time_unix_nano: 1,
+            end_time_unix_nano: 2,
+            ..Default::default()
+        };
+        let mut expected = vec![0x0a, 1, 1, 0x12, 1, 2, 0x22, 1, 3, 0x2a, 1, b'a', 0x30, 3];
+        expected.push(0x39);
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.push(0x41);
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        assert_eq!(span.encode_to_vec(), expected);
+    }
+
+    /// The received requests with the `authorization` metadata.
+    type Requests<Req> = Arc<Mutex<Vec<(Req, Option<String>)>>>;
+
+    /// The mock collector of the OTLP service, which records the requests.
+    struct MockService<Req, Resp> {
+        requests: Requests<Req>,
+        _response: PhantomData<fn() -> Resp>,
+    }
+
+    impl<Req, Resp> Clone for MockService<Req, Resp> {
+        fn clone(&self) -> Self {
+            Self {
+                requests: self.requests.clone(),
+                _response: PhantomData,
+            }
+        }
+    }
+
+    impl<Req, Resp> MockService<Req, Resp> {
+        fn new() -> Self {
+            Self {
+                requests: Default::default(),
+                _response: PhantomData,
+            }
+        }
+    }
+
+    impl NamedService for MockService<ExportTraceServiceRequest, ExportTraceServiceResponse> {
+        const NAME: &'static str = "opentelemetry.proto.collector.trace.v1.TraceService";
+    }
+
+    impl NamedService for MockService<ExportLogsServiceRequest, ExportLogsServiceResponse> {
+        const NAME: &'static str = "opentelemetry.proto.collector.logs.v1.LogsService";
+    }
+
+    struct MockExport<Req, Resp>(MockService<Req, Resp>);
+
+    impl<Req, Resp: Default> UnaryService<Req> for MockExport<Req, Resp> {
+        type Future = Ready<Result<tonic::Response<Resp>, tonic::Status>>;
+        type Response = Resp;
+
+        fn call(&mut self, request: tonic::Request<Req>) -> Self::Future {
+            let authorization = request
+                .metadata()
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned);
+            self.0
+                .requests
+                .lock()
+                .unwrap()
+                .push((request.into_inner(), authorization));
+            ready(Ok(tonic::Response::new(Resp::default())))
+        }
+    }
+
+    impl<Req, Resp> Service<http::Request<BoxBody>> for MockService<Req, Resp>
+    where
+        Req: prost::Message + Default + Send + 'static,
+        Resp: prost::Message + Default + Send + 'static,
+    {
+        type Error = Infallible;
+        type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+        type Response = http::Response<BoxBody>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+            let export = MockExport(self.clone());
+            Box::pin(async move {
+                let mut grpc = tonic::server::Grpc::new(ProstCodec::<Resp, Req>::default());
+                Ok(grpc.unary(export, request).await)
+            })
+        }
+    }
+
+    /// Consume the prepared batches, and count the acknowledged items.
+    struct MockConsumer {
+        batches: VecDeque<Vec<CollectItem>>,
+        acked: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl CollectItemBatchConsume for MockConsumer {
+        async fn consume_batch(&mut self, _max_count: usize) -> Option<Vec<CollectItem>> {
+            self.batches.pop_front()
+        }
+
+        fn ack(&self, count: usize) {
+            self.acked.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    fn log_item() -> CollectItem {
+        CollectItem::Log(Box::new(LogData {
+            service: "service".to_owned(),
+            service_instance: "instance".to_owned(),
+            body: Some(LogDataBody {
+                r#type: String::new(),
+                content: Some(Content::Text(TextLog {
+                    text: "message".to_owned(),
+                })),
+            }),
+            ..Default::default()
+        }))
+    }
+
+    /// Run the reporter against the collector until the batches are consumed,
+    /// return the count of the acknowledged items.
+    async fn run_against(
+        server: tonic::transport::server::Router, batches: Vec<Vec<CollectItem>>,
+    ) -> usize {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+        let server = tokio::spawn(server.serve_with_incoming(incoming));
+
+        let acked = Arc::new(AtomicUsize::new(0));
+        let consumer = MockConsumer {
+            batches: batches.into(),
+            acked: acked.clone(),
+        };
+        let config = OtlpReporterConfiguration {
+            endpoint: format!("http://{}", addr),
+            headers: "Authorization=Bearer token".to_owned(),
+        };
+        run_reporter(config, consumer, Connectivity::default())
+            .await
+            .unwrap();
+
+        server.abort();
+        acked.load(Ordering::Relaxed)
+    }
+
+    #[tokio::test]
+    async fn test_export_to_mock_collector() {
+        let traces = MockService::<ExportTraceServiceRequest, ExportTraceServiceResponse>::new();
+        let logs = MockService::<ExportLogsServiceRequest, ExportLogsServiceResponse>::new();
+        let server = Server::builder()
+            .add_service(traces.clone())
+            .add_service(logs.clone());
+
+        let batches = vec![
+            vec![
+                CollectItem::Trace(Box::new(segment())),
+                log_item(),
+                CollectItem::Ping(Default::default()),
+            ],
+            vec![log_item()],
+        ];
+        assert_eq!(run_against(server, batches).await, 3);
+
+        let traces = traces.requests.lock().unwrap();
+        assert_eq!(traces.len(), 1);
+        let (request, authorization) = &traces[0];
+        assert_eq!(authorization.as_deref(), Some("Bearer token"));
+        assert_eq!(request.resource_spans.len(), 1);
+        let resource_spans = &request.resource_spans[0];
+        assert_eq!(
+            attribute(
+                &resource_spans.resource.as_ref().unwrap().attributes,
+                "service.name"
+            ),
+            Some(&Value::String("service".to_owned()))
+        );
+        assert_eq!(resource_spans.scope_spans[0].spans.len(), 2);
+
+        let logs = logs.requests.lock().unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(
+            logs[0].0.resource_logs[0].scope_logs[0].log_records[0].body,
+            string_value("message")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_partially_failed() {
+        // The logs service is unimplemented by the collector.
+        let traces = MockService::<ExportTraceServiceRequest, ExportTraceServiceResponse>::new();
+        let server = Server::builder().add_service(traces.clone());
+
+        let batches = vec![vec![CollectItem::Trace(Box::new(segment())), log_item()]];
+        assert_eq!(run_against(server, batches).await, 1);
+        assert_eq!(traces.requests.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,367 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Aggregate the runtime metrics reported by the php processes at every
+//! request shutdown, then report them as the meters periodically.
+//!
+//! The php processes send the metrics of a request as a single batch meter,
+//! which is split into the meters with the names below, so the worker without
+//! aggregation still forwards them as the individual meters.
+
+use skywalking::{
+    proto::v3::{Label, MeterData, MeterSingleValue, meter_data::Metric},
+    reporter::CollectItem,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::mpsc, time::interval};
+use tracing::{debug, warn};
+
+/// The batch meter of the runtime metrics of a request, whose labels are the
+/// meter names and values below.
+pub const RUNTIME_METER_BATCH: &str = "php_runtime_metrics";
+
+/// The count of the requests, always 1 for every request.
+pub const RUNTIME_METER_REQUESTS: &str = "php_runtime_requests";
+
+/// The peak memory allocated from the system, in bytes.
+pub const RUNTIME_METER_MEMORY_PEAK_USAGE: &str = "php_runtime_memory_peak_usage";
+
+/// The count of the gc runs during the request.
+pub const RUNTIME_METER_GC_RUNS: &str = "php_runtime_gc_runs";
+
+/// The count of the gc collected cycles during the request.
+pub const RUNTIME_METER_GC_COLLECTED: &str = "php_runtime_gc_collected";
+
+/// The count of the included or required files.
+pub const RUNTIME_METER_INCLUDED_FILES: &str = "php_runtime_included_files";
+
+/// The opcache hit rate percentage.
+pub const RUNTIME_METER_OPCACHE_HIT_RATE: &str = "php_runtime_opcache_hit_rate";
+
+/// The used opcache shared memory, in bytes.
+pub const RUNTIME_METER_OPCACHE_USED_MEMORY: &str = "php_runtime_opcache_used_memory";
+
+/// The free opcache shared memory, in bytes.
+pub const RUNTIME_METER_OPCACHE_FREE_MEMORY: &str = "php_runtime_opcache_free_memory";
+
+#[derive(Debug, Clone, Copy)]
+enum Aggregation {
+    Sum,
+    Max,
+    Avg,
+    Last,
+}
+
+fn aggregation(name: &str) -> Option<Aggregation> {
+    match name {
+        RUNTIME_METER_REQUESTS | RUNTIME_METER_GC_RUNS | RUNTIME_METER_GC_COLLECTED => {
+            Some(Aggregation::Sum)
+        }
+        RUNTIME_METER_MEMORY_PEAK_USAGE => Some(Aggregation::Max),
+        RUNTIME_METER_INCLUDED_FILES => Some(Aggregation::Avg),
+        RUNTIME_METER_OPCACHE_HIT_RATE
+        | RUNTIME_METER_OPCACHE_USED_MEMORY
+        | RUNTIME_METER_OPCACHE_FREE_MEMORY => Some(Aggregation::Last),
+        _ => None,
+    }
+}
+
+/// The key is the service, service instance and the meter name.
+type MeterKey = (String, String, String);
+
+struct MeterState {
+    aggregation: Aggregation,
+    sum: f64,
+    max: f64,
+    last: f64,
+    count: u64,
+}
+
+impl MeterState {
+    fn new(aggregation: Aggregation) -> Self {
+        Self {
+            aggregation,
+            sum: 0.,
+            max: f64::MIN,
+            last: 0.,
+            count: 0,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.sum += value;
+        self.max = self.max.max(value);
+        self.last = value;
+        self.count += 1;
+    }
+
+    fn value(&self) -> f64 {
+        match self.aggregation {
+            Aggregation::Sum => self.sum,
+            Aggregation::Max => self.max,
+            Aggregation::Avg => self.sum / self.count as f64,
+            Aggregation::Last => self.last,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct RuntimeMetricsAggregator {
+    meters: Mutex<HashMap<MeterKey, MeterState>>,
+}
+
+impl RuntimeMetricsAggregator {
+    /// Aggregate the item if it's a runtime metrics batch, otherwise give it
+    /// back.
+    pub fn aggregate(&self, item: CollectItem) -> Option<CollectItem> {
+        let CollectItem::Meter(meter) = &item else {
+            return Some(item);
+        };
+        let Some(metrics) = unbatch_runtime_metrics(meter) else {
+            return Some(item);
+        };
+
+        let mut meters = self.meters.lock().unwrap_or_else(|err| err.into_inner());
+        for (name, value) in metrics {
+            let Some(aggregation) = aggregation(name) else {
+                continue;
+            };
+            let key = (
+                meter.service.clone(),
+                meter.service_instance.clone(),
+                name.to_owned(),
+            );
+            meters
+                .entry(key)
+                .or_insert_with(|| MeterState::new(aggregation))
+                .add(value);
+        }
+
+        None
+    }
+
+    /// Take the aggregated meters since the last flush.
+    fn flush(&self) -> Vec<MeterData> {
+        let meters = {
+            let mut meters = self.meters.lock().unwrap_or_else(|err| err.into_inner());
+            std::mem::take(&mut *meters)
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|dur| dur.as_millis() as i64)
+            .unwrap_or_default();
+
+        meters
+            .into_iter()
+            .map(|((service, service_instance, name), state)| MeterData {
+                service,
+                service_instance,
+                timestamp,
+                metric: Some(Metric::SingleValue(MeterSingleValue {
+                    name,
+                    labels: Vec::new(),
+                    value: state.value(),
+                })),
+            })
+            .collect()
+    }
+}
+
+/// Batch the runtime metrics of a request into a single meter, to send them
+/// in one IPC frame.
+pub fn batch_runtime_metrics(
+    service: String, service_instance: String, timestamp: i64, metrics: &[(&str, f64)],
+) -> MeterData {
+    MeterData {
+        service,
+        service_instance,
+        timestamp,
+        metric: Some(Metric::SingleValue(MeterSingleValue {
+            name: RUNTIME_METER_BATCH.to_owned(),
+            labels: metrics
+                .iter()
+                .map(|(name, value)| Label {
+                    name: (*name).to_owned(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            value: metrics.len() as f64,
+        })),
+    }
+}
+
+/// Get the metric names and values of the batch meter, the unparsable values
+/// are skipped.
+fn unbatch_runtime_metrics(meter: &MeterData) -> Option<impl Iterator<Item = (&str, f64)>> {
+    match &meter.metric {
+        Some(Metric::SingleValue(value)) if value.name == RUNTIME_METER_BATCH => Some(
+            value
+                .labels
+                .iter()
+                .filter_map(|label| Some((label.name.as_str(), label.value.parse::<f64>().ok()?))),
+        ),
+        _ => None,
+    }
+}
+
+/// Split the runtime metrics batch into the individual meters, for the worker
+/// without aggregation. The other items are given back as is.
+pub fn split_runtime_metrics(item: CollectItem) -> Vec<CollectItem> {
+    let CollectItem::Meter(meter) = &item else {
+        return vec![item];
+    };
+    let Some(metrics) = unbatch_runtime_metrics(meter) else {
+        return vec![item];
+    };
+
+    metrics
+        .map(|(name, value)| {
+            CollectItem::Meter(Box::new(MeterData {
+                service: meter.service.clone(),
+                service_instance: meter.service_instance.clone(),
+                timestamp: meter.timestamp,
+                metric: Some(Metric::SingleValue(MeterSingleValue {
+                    name: name.to_owned(),
+                    labels: Vec::new(),
+                    value,
+                })),
+            }))
+        })
+        .collect()
+}
+
+/// Report the aggregated meters every period.
+pub async fn report_runtime_metrics(
+    aggregator: Arc<RuntimeMetricsAggregator>, period: Duration, tx: mpsc::Sender<CollectItem>,
+) {
+    let mut ticker = interval(period);
+    // The first tick completes immediately.
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let meters = aggregator.flush();
+        debug!(count = meters.len(), "Report runtime metrics");
+        for meter in meters {
+            if let Err(err) = tx.try_send(CollectItem::Meter(Box::new(meter))) {
+                warn!(?err, "Send runtime metrics failed");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(metrics: &[(&str, f64)]) -> CollectItem {
+        CollectItem::Meter(Box::new(batch_runtime_metrics(
+            "service".to_owned(),
+            "instance".to_owned(),
+            1000,
+            metrics,
+        )))
+    }
+
+    fn values(meters: Vec<MeterData>) -> HashMap<String, f64> {
+        meters
+            .into_iter()
+            .map(|meter| match meter.metric {
+                Some(Metric::SingleValue(value)) => (value.name, value.value),
+                metric => panic!("unexpected metric: {metric:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let aggregator = RuntimeMetricsAggregator::default();
+        for (peak, files, hit_rate) in [(10., 100., 90.), (30., 200., 95.), (20., 300., 99.)] {
+            assert!(
+                aggregator
+                    .aggregate(batch(&[
+                        (RUNTIME_METER_REQUESTS, 1.),
+                        (RUNTIME_METER_MEMORY_PEAK_USAGE, peak),
+                        (RUNTIME_METER_GC_RUNS, 2.),
+                        (RUNTIME_METER_INCLUDED_FILES, files),
+                        (RUNTIME_METER_OPCACHE_HIT_RATE, hit_rate),
+                        ("unknown", 1.),
+                    ]))
+                    .is_none()
+            );
+        }
+
+        let values = values(aggregator.flush());
+        assert_eq!(values.len(), 5);
+        assert_eq!(values[RUNTIME_METER_REQUESTS], 3.);
+        assert_eq!(values[RUNTIME_METER_MEMORY_PEAK_USAGE], 30.);
+        assert_eq!(values[RUNTIME_METER_GC_RUNS], 6.);
+        assert_eq!(values[RUNTIME_METER_INCLUDED_FILES], 200.);
+        assert_eq!(values[RUNTIME_METER_OPCACHE_HIT_RATE], 99.);
+
+        assert!(aggregator.flush().is_empty());
+    }
+
+    #[test]
+    fn test_aggregate_gives_back_other_items() {
+        let aggregator = RuntimeMetricsAggregator::default();
+        let item = CollectItem::Meter(Box::new(MeterData {
+            metric: Some(Metric::SingleValue(MeterSingleValue {
+                name: RUNTIME_METER_REQUESTS.to_owned(),
+                labels: Vec::new(),
+                value: 1.,
+            })),
+            ..Default::default()
+        }));
+        assert!(aggregator.aggregate(item).is_some());
+        assert!(aggregator.flush().is_empty());
+    }
+
+    #[test]
+    fn test_split_runtime_metrics() {
+        let items = split_runtime_metrics(batch(&[
+            (RUNTIME_METER_REQUESTS, 1.),
+            (RUNTIME_METER_MEMORY_PEAK_USAGE, 2097152.),
+            (RUNTIME_METER_OPCACHE_HIT_RATE, 99.5),
+        ]));
+        let meters = items
+            .into_iter()
+            .map(|item| match item {
+                CollectItem::Meter(meter) => {
+                    assert_eq!(meter.service, "service");
+                    assert_eq!(meter.service_instance, "instance");
+                    assert_eq!(meter.timestamp, 1000);
+                    *meter
+                }
+                item => panic!("unexpected item: {item:?}"),
+            })
+            .collect();
+        let values = values(meters);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[RUNTIME_METER_REQUESTS], 1.);
+        assert_eq!(values[RUNTIME_METER_MEMORY_PEAK_USAGE], 2097152.);
+        assert_eq!(values[RUNTIME_METER_OPCACHE_HIT_RATE], 99.5);
+
+        let item = CollectItem::Meter(Box::default());
+        assert_eq!(split_runtime_metrics(item).len(), 1);
+    }
+}
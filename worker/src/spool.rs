@@ -0,0 +1,294 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The disk spool of the collect items, which can't be reported in time, such
+//! as the OAP is unreachable.
+
+use anyhow::{Context, bail};
+use skywalking::reporter::CollectItem;
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    os::unix::prelude::AsRawFd,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::task;
+use tracing::{info, warn};
+
+const SPOOL_FILE_EXTENSION: &str = "item";
+
+const SPOOL_LOCK_FILE_NAME: &str = "spool.lock";
+
+pub struct SpoolConfiguration {
+    pub dir: PathBuf,
+    /// The max total size of the spooled items in bytes, the oldest items are
+    /// evicted when exceeded.
+    pub max_size: u64,
+}
+
+pub struct Spool {
+    dir: PathBuf,
+    max_size: u64,
+    inner: Mutex<SpoolInner>,
+    /// Hold the lock of the directory, prevent the other workers from using
+    /// the same spool.
+    _lock_file: File,
+}
+
+struct SpoolInner {
+    /// The sequence and size of the spooled files, the oldest first.
+    files: VecDeque<(u64, u64)>,
+    total_size: u64,
+    next_seq: u64,
+}
+
+impl Spool {
+    pub fn open(config: SpoolConfiguration) -> anyhow::Result<Self> {
+        let SpoolConfiguration { dir, max_size } = config;
+
+        fs::create_dir_all(&dir).context("create spool directory failed")?;
+
+        let lock_file = File::create(dir.join(SPOOL_LOCK_FILE_NAME))?;
+        if unsafe { libc::flock(lock_file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            bail!(
+                "spool directory {:?} is used by another worker, each worker needs its own spool \
+                 directory",
+                dir
+            );
+        }
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SPOOL_FILE_EXTENSION) {
+                continue;
+            }
+            let Some(seq) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            files.push((seq, fs::metadata(&path)?.len()));
+        }
+        files.sort_unstable();
+
+        let inner = SpoolInner {
+            total_size: files.iter().map(|(_, size)| size).sum(),
+            next_seq: files.last().map(|(seq, _)| seq + 1).unwrap_or_default(),
+            files: files.into(),
+        };
+        info!(
+            ?dir,
+            count = inner.files.len(),
+            size = inner.total_size,
+            "Spool opened"
+        );
+
+        let spool = Self {
+            dir,
+            max_size,
+            inner: Mutex::new(inner),
+            _lock_file: lock_file,
+        };
+        spool.evict(&mut spool.lock());
+        Ok(spool)
+    }
+
+    /// Write the item to the end of the spool, return the count of the
+    /// evicted items. Only the trace segments, logs and meters are spooled,
+    /// the management items are stale when replayed.
+    ///
+    /// The file is written in the blocking thread pool, not to block the
+    /// receiving of the other items.
+    pub async fn push(self: Arc<Self>, item: CollectItem) -> anyhow::Result<u64> {
+        if !matches!(
+            item,
+            CollectItem::Trace(_) | CollectItem::Log(_) | CollectItem::Meter(_)
+        ) {
+            return Ok(0);
+        }
+
+        task::spawn_blocking(move || self.push_blocking(&item)).await?
+    }
+
+    /// Take the oldest item from the spool.
+    pub async fn pop(self: Arc<Self>) -> anyhow::Result<Option<CollectItem>> {
+        task::spawn_blocking(move || self.pop_blocking()).await?
+    }
+
+    fn push_blocking(&self, item: &CollectItem) -> anyhow::Result<u64> {
+        let content = bincode::serde::encode_to_vec(item, bincode::config::standard())?;
+        let size = content.len() as u64;
+        if size > self.max_size {
+            bail!("collect item is larger than the spool max size");
+        }
+
+        let mut inner = self.lock();
+        let seq = inner.next_seq;
+        fs::write(self.file_path(seq), content)?;
+        inner.next_seq += 1;
+        inner.files.push_back((seq, size));
+        inner.total_size += size;
+
+        Ok(self.evict(&mut inner))
+    }
+
+    fn pop_blocking(&self) -> anyhow::Result<Option<CollectItem>> {
+        let mut inner = self.lock();
+        let Some((seq, size)) = inner.files.pop_front() else {
+            return Ok(None);
+        };
+        inner.total_size -= size;
+
+        let path = self.file_path(seq);
+        let content = fs::read(&path);
+        if let Err(err) = fs::remove_file(&path) {
+            warn!(?err, ?path, "Remove spool file failed");
+        }
+
+        let (item, _) = bincode::serde::decode_from_slice(&content?, bincode::config::standard())?;
+        Ok(Some(item))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().files.is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SpoolInner> {
+        // The inner state is always consistent, even if the lock is poisoned.
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Remove the oldest items until the total size doesn't exceed the max
+    /// size.
+    fn evict(&self, inner: &mut SpoolInner) -> u64 {
+        let mut count = 0;
+        while inner.total_size > self.max_size {
+            let Some((seq, size)) = inner.files.pop_front() else {
+                break;
+            };
+            inner.total_size -= size;
+            if let Err(err) = fs::remove_file(self.file_path(seq)) {
+                warn!(?err, seq, "Remove spool file failed");
+            }
+            count += 1;
+        }
+        if count > 0 {
+            warn!(count, "Spool is full, the oldest items are evicted");
+        }
+        count
+    }
+
+    fn file_path(&self, seq: u64) -> PathBuf {
+        self.dir
+            .join(format!("{:020}.{}", seq, SPOOL_FILE_EXTENSION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use skywalking::proto::v3::{InstancePingPkg, LogData};
+
+    fn log_item(service: &str) -> CollectItem {
+        CollectItem::Log(Box::new(LogData {
+            service: service.to_owned(),
+            ..Default::default()
+        }))
+    }
+
+    fn item_size(item: &CollectItem) -> u64 {
+        bincode::serde::encode_to_vec(item, bincode::config::standard())
+            .unwrap()
+            .len() as u64
+    }
+
+    async fn pop_service(spool: &Arc<Spool>) -> Option<String> {
+        match spool.clone().pop().await.unwrap()? {
+            CollectItem::Log(log) => Some(log.service),
+            _ => panic!("unexpected collect item"),
+        }
+    }
+
+    fn open(dir: &tempfile::TempDir, max_size: u64) -> Arc<Spool> {
+        Arc::new(
+            Spool::open(SpoolConfiguration {
+                dir: dir.path().to_owned(),
+                max_size,
+            })
+            .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_push_and_pop() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = open(&dir, 1024 * 1024);
+
+        for service in ["a", "b", "c"] {
+            assert_eq!(spool.clone().push(log_item(service)).await.unwrap(), 0);
+        }
+        // The management items are not spooled.
+        let ping = CollectItem::Ping(Box::<InstancePingPkg>::default());
+        assert_eq!(spool.clone().push(ping).await.unwrap(), 0);
+
+        assert_eq!(pop_service(&spool).await.as_deref(), Some("a"));
+        assert_eq!(pop_service(&spool).await.as_deref(), Some("b"));
+        assert_eq!(pop_service(&spool).await.as_deref(), Some("c"));
+        assert_eq!(pop_service(&spool).await, None);
+        assert!(spool.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_evict_oldest() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = open(&dir, item_size(&log_item("a")) * 2);
+
+        assert_eq!(spool.clone().push(log_item("a")).await.unwrap(), 0);
+        assert_eq!(spool.clone().push(log_item("b")).await.unwrap(), 0);
+        assert_eq!(spool.clone().push(log_item("c")).await.unwrap(), 1);
+
+        assert_eq!(pop_service(&spool).await.as_deref(), Some("b"));
+        assert_eq!(pop_service(&spool).await.as_deref(), Some("c"));
+        assert_eq!(pop_service(&spool).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = open(&dir, 1024 * 1024);
+        spool.clone().push(log_item("a")).await.unwrap();
+        spool.clone().push(log_item("b")).await.unwrap();
+
+        // The directory is locked by the opened spool.
+        assert!(
+            Spool::open(SpoolConfiguration {
+                dir: dir.path().to_owned(),
+                max_size: 1024 * 1024,
+            })
+            .is_err()
+        );
+        drop(spool);
+
+        let spool = open(&dir, 1024 * 1024);
+        spool.clone().push(log_item("c")).await.unwrap();
+        assert_eq!(pop_service(&spool).await.as_deref(), Some("a"));
+        assert_eq!(pop_service(&spool).await.as_deref(), Some("b"));
+        assert_eq!(pop_service(&spool).await.as_deref(), Some("c"));
+    }
+}
@@ -0,0 +1,155 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The counters of the collect items passing through the worker.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::time::interval;
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The queue is full and the spool is disabled.
+    QueueFull,
+    /// The queue is closed, the worker is shutting down.
+    QueueClosed,
+    /// Write the item to the spool failed.
+    SpoolFailed,
+    /// The oldest item is evicted, because the spool is full.
+    SpoolEvicted,
+}
+
+impl DropReason {
+    pub const ALL: [DropReason; 4] = [
+        DropReason::QueueFull,
+        DropReason::QueueClosed,
+        DropReason::SpoolFailed,
+        DropReason::SpoolEvicted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::QueueFull => "queue_full",
+            DropReason::QueueClosed => "queue_closed",
+            DropReason::SpoolFailed => "spool_failed",
+            DropReason::SpoolEvicted => "spool_evicted",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct WorkerStats {
+    received: AtomicU64,
+    reported: AtomicU64,
+    spooled: AtomicU64,
+    dropped: [AtomicU64; DropReason::ALL.len()],
+    decode_failed: AtomicU64,
+    ipc_connections: AtomicU64,
+}
+
+/// The counters are accumulated since the worker started.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkerStatsSnapshot {
+    pub received: u64,
+    pub reported: u64,
+    pub spooled: u64,
+    pub dropped: [u64; DropReason::ALL.len()],
+    pub decode_failed: u64,
+    /// The current count of the connections from the php processes.
+    pub ipc_connections: u64,
+}
+
+impl WorkerStatsSnapshot {
+    pub fn dropped(&self, reason: DropReason) -> u64 {
+        self.dropped[reason as usize]
+    }
+}
+
+impl WorkerStats {
+    pub fn add_received(&self) {
+        self.received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_reported(&self, count: u64) {
+        self.reported.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_spooled(&self) {
+        self.spooled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_dropped(&self, reason: DropReason, count: u64) {
+        self.dropped[reason as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn add_decode_failed(&self) {
+        self.decode_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn inc_ipc_connections(&self) {
+        self.ipc_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec_ipc_connections(&self) {
+        self.ipc_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> WorkerStatsSnapshot {
+        WorkerStatsSnapshot {
+            received: self.received.load(Ordering::Relaxed),
+            reported: self.reported.load(Ordering::Relaxed),
+            spooled: self.spooled.load(Ordering::Relaxed),
+            dropped: DropReason::ALL.map(|reason| self.dropped(reason)),
+            decode_failed: self.decode_failed.load(Ordering::Relaxed),
+            ipc_connections: self.ipc_connections.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn dropped(&self, reason: DropReason) -> u64 {
+        self.dropped[reason as usize].load(Ordering::Relaxed)
+    }
+}
+
+/// Log the snapshot every interval, the counters are also reported as meters
+/// by the self telemetry.
+pub async fn log_stats(stats: Arc<WorkerStats>, period: Duration) {
+    let mut ticker = interval(period);
+    // The first tick completes immediately.
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let snapshot = stats.snapshot();
+        info!(
+            received = snapshot.received,
+            reported = snapshot.reported,
+            spooled = snapshot.spooled,
+            dropped_queue_full = snapshot.dropped(DropReason::QueueFull),
+            dropped_queue_closed = snapshot.dropped(DropReason::QueueClosed),
+            dropped_spool_failed = snapshot.dropped(DropReason::SpoolFailed),
+            dropped_spool_evicted = snapshot.dropped(DropReason::SpoolEvicted),
+            decode_failed = snapshot.decode_failed,
+            ipc_connections = snapshot.ipc_connections,
+            "Worker stats"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+// Licensed to the Apache Software Foundation (ASF) under one or more
+// contributor license agreements.  See the NOTICE file distributed with
+// this work for additional information regarding copyright ownership.
+// The ASF licenses this file to You under the Apache License, Version 2.0
+// (the "License"); you may not use this file except in compliance with
+// the License.  You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Report the worker stats as the meters of the service instance.
+
+use crate::{
+    channel::TxReporter,
+    reporter::Connectivity,
+    stats::{DropReason, WorkerStats, WorkerStatsSnapshot},
+};
+use skywalking::{
+    metrics::{meter::Gauge, metricer::Metricer},
+    reporter::CollectItem,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+
+type GetCounter = fn(&WorkerStatsSnapshot) -> u64;
+
+pub struct SelfTelemetryConfiguration {
+    pub service_name: String,
+    pub service_instance: String,
+    pub report_interval: Duration,
+}
+
+pub fn report_self_telemetry(
+    config: SelfTelemetryConfiguration, stats: Arc<WorkerStats>, connectivities: Vec<Connectivity>,
+    tx: mpsc::Sender<CollectItem>,
+) {
+    let mut metricer = Metricer::new(
+        config.service_name,
+        config.service_instance,
+        TxReporter(tx.clone()),
+    );
+    metricer.set_report_interval(config.report_interval);
+
+    metricer.register(Gauge::new("php_worker_queue_depth", move || {
+        (tx.max_capacity() - tx.capacity()) as f64
+    }));
+
+    let counters: [(&str, GetCounter); 5] = [
+        ("php_worker_received_items", |snapshot| snapshot.received),
+        ("php_worker_reported_items", |snapshot| snapshot.reported),
+        ("php_worker_spooled_items", |snapshot| snapshot.spooled),
+        ("php_worker_decode_errors", |snapshot| {
+            snapshot.decode_failed
+        }),
+        ("php_worker_ipc_connections", |snapshot| {
+            snapshot.ipc_connections
+        }),
+    ];
+    for (name, get) in counters {
+        let stats = stats.clone();
+        metricer.register(Gauge::new(name, move || get(&stats.snapshot()) as f64));
+    }
+
+    for reason in DropReason::ALL {
+        let stats = stats.clone();
+        metricer.register(
+            Gauge::new("php_worker_dropped_items", move || {
+                stats.dropped(reason) as f64
+            })
+            .add_label("reason", reason.as_str()),
+        );
+    }
+
+    // The reporters are labeled by the index in the configuration.
+    for (index, connectivity) in connectivities.into_iter().enumerate() {
+        metricer.register(
+            Gauge::new("php_worker_reporter_reconnects", move || {
+                connectivity.reconnects() as f64
+            })
+            .add_label("reporter", index.to_string()),
+        );
+    }
+
+    debug!("Start reporting worker self telemetry");
+
+    // The reporting stops when the booting is dropped.
+    tokio::spawn(async move {
+        if let Err(err) = metricer.boot().await {
+            error!(?err, "Worker self telemetry reporting failed");
+        }
+    });
+}